@@ -16,9 +16,11 @@
 
 use std::collections::HashMap;
 use std::iter;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
-use ls_types::{DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Range};
+use ls_types::{DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Range,
+               TextEdit};
 use lsp_data::ls_util;
 use serde_json;
 use span::compiler::DiagnosticSpan;
@@ -31,6 +33,34 @@ pub struct Suggestion {
     pub range: Range,
     pub new_text: String,
     pub label: String,
+    /// Whether rustc is confident the suggestion can be applied as is, so it
+    /// can be applied together with others without being looked at.
+    pub machine_applicable: bool,
+    /// The edits of all the parts of a suggestion rustc made in several parts,
+    /// which must be applied together, or empty.
+    pub parts: Vec<TextEdit>,
+    /// The lines the suggestion edits, including those of its parts, as they
+    /// were when it was built, by their 0-based line number.
+    pub built_lines: Vec<(u64, String)>,
+}
+
+impl Suggestion {
+    fn edit(&self) -> TextEdit {
+        TextEdit {
+            range: self.range,
+            new_text: self.new_text.clone(),
+        }
+    }
+
+    /// Whether the lines the suggestion edits are unchanged in `lines`, the
+    /// current lines of its file, so it still applies after the file has been
+    /// changed since it was built.
+    pub fn is_current(&self, lines: &[&str]) -> bool {
+        !self.built_lines.is_empty() && self.built_lines.iter().all(|&(line, ref built)| {
+            lines.get(line as usize).map(|line| line.trim_right_matches('\r'))
+                == Some(built.trim_right_matches('\r'))
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -44,7 +74,7 @@ struct CompilerMessage {
     message: String,
     code: Option<CompilerMessageCode>,
     level: String,
-    spans: Vec<DiagnosticSpan>,
+    spans: Vec<CompilerSpan>,
     children: Vec<AssociatedMessage>,
 }
 
@@ -55,7 +85,30 @@ struct CompilerMessage {
 struct AssociatedMessage {
     message: String,
     level: String,
-    spans: Vec<DiagnosticSpan>,
+    spans: Vec<CompilerSpan>,
+}
+
+/// A `DiagnosticSpan` along with how confident rustc is in its suggested
+/// replacement, which `DiagnosticSpan` doesn't record.
+#[derive(Debug, Clone, Deserialize)]
+struct CompilerSpan {
+    #[serde(flatten)]
+    span: DiagnosticSpan,
+    suggestion_applicability: Option<String>,
+}
+
+impl CompilerSpan {
+    fn is_machine_applicable(&self) -> bool {
+        self.suggestion_applicability.as_ref().map(String::as_str) == Some("MachineApplicable")
+    }
+}
+
+impl Deref for CompilerSpan {
+    type Target = DiagnosticSpan;
+
+    fn deref(&self) -> &DiagnosticSpan {
+        &self.span
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,7 +142,7 @@ pub fn parse_diagnostics(
     // diagnostics, since they can contain a single primary range. Those will
     // also share any additional notes, suggestions, and secondary spans emitted
     // by rustc, in a form of LSP diagnostic related information.
-    let (primaries, secondaries): (Vec<DiagnosticSpan>, Vec<DiagnosticSpan>) = message
+    let (primaries, secondaries): (Vec<CompilerSpan>, Vec<CompilerSpan>) = message
         .spans
         .iter()
         .cloned()
@@ -108,7 +161,11 @@ pub fn parse_diagnostics(
         let children = || message.children.iter().flat_map(|msg| &msg.spans);
         let all_spans = || iter::once(span).chain(&secondaries).chain(children());
 
-        let suggestions = make_suggestions(span, all_spans());
+        let suggestions = make_suggestions(
+            span,
+            iter::once(span).chain(&secondaries),
+            &message.children,
+        );
         let related_information = if related_information_support {
             Some(make_related_information(all_spans(), cwd))
         } else {
@@ -137,7 +194,7 @@ pub fn parse_diagnostics(
         };
 
         let rls_span = {
-            let mut span = span;
+            let mut span: &DiagnosticSpan = span;
             // if span points to a macro, search through the expansions
             // for a more useful source location
             while span.file_name.ends_with(" macros>") && span.expansion.is_some() {
@@ -222,7 +279,7 @@ fn severity(level: &str, is_primary_span: bool) -> DiagnosticSeverity {
 }
 
 fn make_related_information<'a>(
-    spans: impl Iterator<Item = &'a DiagnosticSpan>,
+    spans: impl Iterator<Item = &'a CompilerSpan>,
     cwd: &Path,
 ) -> Vec<DiagnosticRelatedInformation> {
     let mut related_information: Vec<DiagnosticRelatedInformation> = spans
@@ -248,22 +305,50 @@ fn make_related_information<'a>(
 
 fn make_suggestions<'a>(
     primary: &DiagnosticSpan,
-    spans: impl Iterator<Item = &'a DiagnosticSpan>,
+    spans: impl Iterator<Item = &'a CompilerSpan>,
+    children: &[AssociatedMessage],
 ) -> Vec<Suggestion> {
     let primary_range = ls_util::rls_to_range(primary.rls_span().zero_indexed().range);
 
-    let mut suggestions: Vec<Suggestion> = spans
-        .filter_map(|span| {
-            span.suggested_replacement
-                .as_ref()
-                .map(|suggested| span_suggestion(span, suggested))
-                .or_else(|| {
-                    span.label
-                        .as_ref()
-                        .and_then(|label| label_suggestion(span, label))
-                })
-        })
-        .collect();
+    let suggestion = |span: &CompilerSpan| {
+        span.suggested_replacement
+            .as_ref()
+            .map(|suggested| span_suggestion(span, suggested, span.is_machine_applicable()))
+            .or_else(|| {
+                span.label
+                    .as_ref()
+                    .and_then(|label| label_suggestion(span, label))
+            })
+    };
+    let mut suggestions: Vec<Suggestion> = spans.filter_map(&suggestion).collect();
+
+    // The replacements in a child are the parts of a single suggestion.
+    for child in children {
+        let mut child_suggestions: Vec<_> = child.spans.iter().filter_map(&suggestion).collect();
+        let parts: Vec<_> = child
+            .spans
+            .iter()
+            .filter_map(|span| {
+                let suggested = span.suggested_replacement.as_ref()?;
+                Some(span_suggestion(span, suggested, false).edit())
+            })
+            .collect();
+        if parts.len() > 1 {
+            let mut built_lines: Vec<_> = child
+                .spans
+                .iter()
+                .filter(|span| span.suggested_replacement.is_some())
+                .flat_map(|span| span_lines(span))
+                .collect();
+            built_lines.sort();
+            built_lines.dedup();
+            for suggestion in &mut child_suggestions {
+                suggestion.parts = parts.clone();
+                suggestion.built_lines = built_lines.clone();
+            }
+        }
+        suggestions.extend(child_suggestions);
+    }
 
     // Suggestions are displayed at primary span, so if the change is somewhere
     // else, be sure to specify that
@@ -278,7 +363,11 @@ fn make_suggestions<'a>(
     suggestions
 }
 
-fn span_suggestion(span: &DiagnosticSpan, suggested: &str) -> Suggestion {
+fn span_suggestion(
+    span: &DiagnosticSpan,
+    suggested: &str,
+    machine_applicable: bool,
+) -> Suggestion {
     let rls_span = span.rls_span().zero_indexed();
     let range = ls_util::rls_to_range(rls_span.range);
     let action = if range.start == range.end {
@@ -291,18 +380,77 @@ fn span_suggestion(span: &DiagnosticSpan, suggested: &str) -> Suggestion {
         new_text: suggested.to_string(),
         range,
         label,
+        machine_applicable,
+        parts: vec![],
+        built_lines: span_lines(span),
     }
 }
 
+// The source lines of `span`, by their 0-based line number.
+fn span_lines(span: &DiagnosticSpan) -> Vec<(u64, String)> {
+    let first = span.line_start.saturating_sub(1) as u64;
+    span.text
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (first + i as u64, line.text.clone()))
+        .collect()
+}
+
 fn label_suggestion(span: &DiagnosticSpan, label: &str) -> Option<Suggestion> {
     let suggest_label = "consider changing this to `";
     if label.starts_with(suggest_label) && label.ends_with('`') {
         let suggested_replacement = &label[suggest_label.len()..label.len() - 1];
-        return Some(span_suggestion(span, suggested_replacement));
+        return Some(span_suggestion(span, suggested_replacement, false));
     }
     None
 }
 
+/// The fixes applying every machine applicable suggestion in `suggestions`,
+/// which must all be for the same file, in order. Each fix is the edits of
+/// one suggestion, in several parts if rustc made it in several parts.
+///
+/// Identical suggestions, e.g., from several diagnostics sharing a span, are
+/// applied once. Where suggestions overlap, only the first one is applied, and
+/// the parts of a suggestion are applied all together or not at all, as by
+/// `cargo fix`, which would apply the rest after rebuilding.
+pub fn fix_all_edits<'a>(
+    suggestions: impl Iterator<Item = &'a Suggestion>,
+) -> Vec<Vec<TextEdit>> {
+    let key = |e: &TextEdit| (e.range.start, e.range.end);
+    let mut suggestions: Vec<Vec<TextEdit>> = suggestions
+        .filter(|s| s.machine_applicable)
+        .map(|s| {
+            let mut edits = if s.parts.is_empty() { vec![s.edit()] } else { s.parts.clone() };
+            edits.sort_by_key(&key);
+            edits
+        })
+        .collect();
+    suggestions.sort_by_key(|edits| {
+        edits.iter().map(|e| (key(e), e.new_text.clone())).collect::<Vec<_>>()
+    });
+    suggestions.dedup();
+
+    let mut fixes: Vec<Vec<TextEdit>> = vec![];
+    for edits in suggestions {
+        let conflicts = edits.iter().enumerate().any(|(i, edit)| {
+            edits[..i]
+                .iter()
+                .chain(fixes.iter().flat_map(|fix| fix))
+                .any(|other| conflict(edit, other))
+        });
+        if !conflicts {
+            fixes.push(edits);
+        }
+    }
+    fixes
+}
+
+// Whether two edits can't both be applied. Two insertions at the same place
+// would be applied in an arbitrary order.
+fn conflict(a: &TextEdit, b: &TextEdit) -> bool {
+    (a.range.start < b.range.end && b.range.start < a.range.end) || a.range == b.range
+}
+
 trait IsWithin {
     /// Returns whether `other` is considered within `self`
     /// note: a thing should be 'within' itself
@@ -794,6 +942,17 @@ mod diagnostic_suggestion_test {
                 end: Position::new(132, 18),
             }
         );
+
+        let line = "        let string = String::new();";
+        assert_eq!(change_to_mut.built_lines, vec![(132, line.to_owned())]);
+
+        let mut lines = vec![""; 140];
+        lines[132] = line;
+        assert!(change_to_mut.is_current(&lines));
+        lines[0] = "// Edits elsewhere don't matter";
+        assert!(change_to_mut.is_current(&lines));
+        lines.insert(0, "// But moving the line does");
+        assert!(!change_to_mut.is_current(&lines));
     }
 
     /// ```
@@ -852,5 +1011,66 @@ mod diagnostic_suggestion_test {
                 end: Position::new(0, 0),
             }
         );
+        // rustc's applicability is "Unspecified".
+        assert!(!change_to_mut.machine_applicable);
+    }
+
+    #[test]
+    fn fix_all_edits_skip_overlapping_suggestions() {
+        let suggestion = |start, end, new_text: &str, machine_applicable| Suggestion {
+            range: Range::new(Position::new(0, start), Position::new(0, end)),
+            new_text: new_text.to_owned(),
+            label: String::new(),
+            machine_applicable,
+            parts: vec![],
+            built_lines: vec![],
+        };
+        let suggestions = vec![
+            suggestion(10, 14, "b", true),
+            suggestion(0, 4, "a", true),
+            suggestion(2, 6, "overlapping", true),
+            suggestion(10, 14, "b", true),
+            suggestion(14, 14, "c", true),
+            suggestion(14, 14, "d", true),
+            suggestion(20, 24, "unspecified", false),
+        ];
+
+        let new_texts: Vec<_> = fix_all_edits(suggestions.iter())
+            .into_iter()
+            .flat_map(|fix| fix)
+            .map(|e| e.new_text)
+            .collect();
+        assert_eq!(new_texts, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn fix_all_edits_apply_multipart_suggestions_atomically() {
+        let edit = |start, end, new_text: &str| TextEdit {
+            range: Range::new(Position::new(0, start), Position::new(0, end)),
+            new_text: new_text.to_owned(),
+        };
+        let multipart = |edits: Vec<TextEdit>| {
+            edits
+                .iter()
+                .map(|e| Suggestion {
+                    range: e.range,
+                    new_text: e.new_text.clone(),
+                    label: String::new(),
+                    machine_applicable: true,
+                    parts: edits.clone(),
+                    built_lines: vec![],
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut suggestions = multipart(vec![edit(0, 4, "a"), edit(10, 14, "b")]);
+        // The second part of this one conflicts with the first suggestion.
+        suggestions.extend(multipart(vec![edit(6, 8, "c"), edit(12, 16, "d")]));
+        suggestions.extend(multipart(vec![edit(20, 24, "e"), edit(30, 34, "f")]));
+
+        let fixes: Vec<Vec<_>> = fix_all_edits(suggestions.iter())
+            .into_iter()
+            .map(|fix| fix.into_iter().map(|e| e.new_text).collect())
+            .collect();
+        assert_eq!(fixes, vec![vec!["a", "b"], vec!["e", "f"]]);
     }
 }
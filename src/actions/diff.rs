@@ -0,0 +1,170 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Line diffs, to send the changes made by formatting a file as edits of the
+//! changed lines, rather than a replacement of the whole file, which moves the
+//! client's cursor and loses its markers.
+
+use lsp_data::{Position, Range, TextEdit};
+
+/// Diffs of more lines than this, after common leading and trailing lines are
+/// skipped, are a single edit, rather than the minimal edits.
+const MAX_DIFF_LINES: usize = 4000;
+
+/// The edits replacing the changed lines of `old` with those of `new`. Each edit
+/// replaces whole lines, except at the end of an `old` without a trailing
+/// newline.
+pub fn line_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let old_changed = &old_lines[prefix..old_lines.len() - suffix];
+    let new_changed = &new_lines[prefix..new_lines.len() - suffix];
+
+    let hunks = if old_changed.len() + new_changed.len() > MAX_DIFF_LINES {
+        vec![((0, old_changed.len()), (0, new_changed.len()))]
+    } else {
+        hunks(old_changed, new_changed)
+    };
+
+    let position = |line: usize| {
+        if line < old_lines.len() || old.ends_with('\n') || old.is_empty() {
+            Position::new(line as u64, 0)
+        } else {
+            // The end of the last line.
+            let last = old_lines[old_lines.len() - 1];
+            Position::new(line as u64 - 1, last.chars().count() as u64)
+        }
+    };
+    hunks
+        .into_iter()
+        .map(|((old_start, old_end), (new_start, new_end))| TextEdit {
+            range: Range::new(position(prefix + old_start), position(prefix + old_end)),
+            new_text: new_changed[new_start..new_end].concat(),
+        })
+        .collect()
+}
+
+/// The lines of `text`, each including its newline.
+fn lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            lines.push(&text[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// The ranges of `old` replaced by ranges of `new`, from a longest common
+/// subsequence of their lines.
+fn hunks(old: &[&str], new: &[&str]) -> Vec<((usize, usize), (usize, usize))> {
+    // `common[i][j]` is the length of the longest common subsequence of
+    // `old[i..]` and `new[j..]`.
+    let width = new.len() + 1;
+    let mut common = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = vec![];
+    let mut hunk_start: Option<(usize, usize)> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            if let Some(start) = hunk_start.take() {
+                hunks.push(((start.0, i), (start.1, j)));
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if hunk_start.is_none() {
+            hunk_start = Some((i, j));
+        }
+        let skip_old = i < old.len() && common[(i + 1) * width + j] >= common[i * width + j + 1];
+        if j == new.len() || skip_old {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    if let Some(start) = hunk_start {
+        hunks.push(((start.0, i), (start.1, j)));
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(text: &str, edits: &[TextEdit]) -> String {
+        let lines = lines(text);
+        let offset = |pos: Position| {
+            let line_start: usize = lines[..pos.line as usize].iter().map(|l| l.len()).sum();
+            line_start + pos.character as usize
+        };
+        let mut text = text.to_owned();
+        for edit in edits.iter().rev() {
+            let (start, end) = (offset(edit.range.start), offset(edit.range.end));
+            text = format!("{}{}{}", &text[..start], edit.new_text, &text[end..]);
+        }
+        text
+    }
+
+    fn edit(start: (u64, u64), end: (u64, u64), new_text: &str) -> TextEdit {
+        let range = Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1));
+        TextEdit::new(range, new_text.to_owned())
+    }
+
+    #[test]
+    fn test_line_edits() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nB\nc\nd\nx\ny\ne\n";
+        let edits = line_edits(old, new);
+        assert_eq!(edits, vec![edit((1, 0), (2, 0), "B\n"), edit((4, 0), (4, 0), "x\ny\n")]);
+        assert_eq!(apply(old, &edits), new);
+
+        assert_eq!(line_edits(old, old), vec![]);
+        let cases = [("", "a\n"), ("a\nb\n", ""), ("a\n  b", "a\nb\n"), ("x\ny", "y\nz")];
+        for &(old, new) in &cases {
+            assert_eq!(apply(old, &line_edits(old, new)), new, "{:?} {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn test_line_edits_end_without_newline() {
+        let old = "a\n    }";
+        assert_eq!(line_edits(old, "a\n}\n"), vec![edit((1, 0), (1, 5), "}\n")]);
+    }
+}
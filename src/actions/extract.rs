@@ -0,0 +1,927 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting a selected expression into a variable, or selected statements
+//! into a function. What can be extracted comes from the text, and which
+//! variables the selection uses from the analysis data.
+
+use actions::imports::{indentation, line_start};
+use actions::lex::{self, Token, TokenKind};
+use actions::rename::is_keyword;
+use actions::requests::{format_impl_items, format_statements};
+use actions::run::LineIndex;
+use actions::InitActionContext;
+use analysis::DefKind;
+use Span;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// An edit, as the byte offsets of the text to replace and its replacement.
+pub type Edit = (usize, usize, String);
+
+struct Tokens<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    pairs: HashMap<usize, usize>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(src: &'a str) -> Tokens<'a> {
+        let tokens = lex::tokenize(src);
+        let pairs = lex::delimiter_pairs(&tokens);
+        Tokens { src, tokens, pairs }
+    }
+
+    fn is(&self, i: usize, keyword: &str) -> bool {
+        self.tokens.get(i).map_or(false, |t| t.is_ident(self.src, keyword))
+    }
+
+    fn is_punct(&self, i: usize, c: char) -> bool {
+        self.tokens.get(i).map_or(false, |t| t.is_punct(c))
+    }
+
+    /// The index of the delimiter closing the one at `i`, if it opens a group.
+    fn closing(&self, i: usize) -> Option<usize> {
+        match self.pairs.get(&i) {
+            Some(&close) if close > i => Some(close),
+            _ => None,
+        }
+    }
+
+    /// The index of the token after the token or group starting at `i`.
+    fn next(&self, i: usize) -> usize {
+        self.closing(i).map_or(i + 1, |close| close + 1)
+    }
+
+    /// The indices of the tokens of the text between `start` and `end`, if
+    /// it starts and ends at token boundaries and its delimiters are balanced.
+    fn selection(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let first = self.tokens.iter().position(|t| t.start >= start)?;
+        let last = self.tokens.iter().rposition(|t| t.end <= end)?;
+        if first > last || self.tokens[first].start != start || self.tokens[last].end != end {
+            return None;
+        }
+        let is_balanced = (first..last + 1).all(|i| match self.tokens[i].kind {
+            TokenKind::Punct(c) if "([{}])".contains(c) => {
+                self.pairs.get(&i).map_or(false, |&j| first <= j && j <= last)
+            }
+            _ => true,
+        });
+        if is_balanced {
+            Some((first, last + 1))
+        } else {
+            None
+        }
+    }
+
+    /// The indices of the `{` enclosing the tokens `s..e`, innermost first.
+    fn enclosing_braces(&self, s: usize, e: usize) -> Vec<usize> {
+        (0..s)
+            .rev()
+            .filter(|&i| self.is_punct(i, '{') && self.closing(i).map_or(false, |close| close >= e))
+            .collect()
+    }
+
+    /// Whether the braces opened at `open` are a block, rather than the body of
+    /// a `match` or an item, or a struct expression.
+    fn is_block(&self, open: usize) -> bool {
+        if open == 0 {
+            return false;
+        }
+        let prev = &self.tokens[open - 1];
+        let after_path = prev.kind == TokenKind::Ident
+            && (!is_keyword(prev.text(self.src)) || prev.is_ident(self.src, "Self"));
+
+        // Walk back over the start of the expression or item.
+        let mut i = open;
+        while i > 0 {
+            i -= 1;
+            let t = &self.tokens[i];
+            if t.is_punct(';') || t.is_punct('{') || t.is_punct('}') {
+                break;
+            }
+            if t.is_punct(')') || t.is_punct(']') {
+                i = self.pairs.get(&i).cloned().unwrap_or(i);
+            } else if ["if", "while", "for", "fn"].iter().any(|k| t.is_ident(self.src, k)) {
+                return true;
+            } else if ["match", "impl", "trait", "mod"].iter().any(|k| t.is_ident(self.src, k)) {
+                return false;
+            }
+        }
+        !after_path
+    }
+
+    /// Whether the identifier at `i` is a field of a struct expression written
+    /// in shorthand, e.g., `x` in `S { x }`.
+    fn is_field_shorthand(&self, i: usize) -> bool {
+        let open = (0..i).rev().find(|&j| self.closing(j).map_or(false, |close| close > i));
+        match open {
+            Some(open) if self.is_punct(open, '{') => {
+                (self.is_punct(i - 1, '{') || self.is_punct(i - 1, ','))
+                    && (self.is_punct(i + 1, '}') || self.is_punct(i + 1, ','))
+                    && !self.is_block(open)
+            }
+            _ => false,
+        }
+    }
+
+    /// The indices of the first token of each statement in the block opened
+    /// at `open`, and of the token after each statement which isn't the
+    /// block's tail expression.
+    fn statement_bounds(&self, open: usize) -> (Vec<usize>, Vec<usize>) {
+        let close = self.closing(open).unwrap_or(self.tokens.len());
+        let mut starts = vec![];
+        let mut ends = vec![];
+        let mut at_start = true;
+        let mut i = open + 1;
+        while i < close {
+            if at_start {
+                starts.push(i);
+            }
+            let t = &self.tokens[i];
+            if t.is_comment() {
+                i += 1;
+                continue;
+            }
+            let next = self.next(i);
+            // A block-like expression, e.g., `if` or `match`, ends a statement,
+            // unless it is followed by a method call, `else`, etc.
+            let continues = self.is_punct(next, '.')
+                || self.is_punct(next, '?')
+                || self.is_punct(next, ';')
+                || self.is(next, "else");
+            at_start = t.is_punct(';') || (t.is_punct('{') && next <= close && !continues);
+            if at_start {
+                ends.push(next);
+            }
+            i = next;
+        }
+        (starts, ends)
+    }
+
+    /// Whether the tokens `s..e` in the statement starting at `statement` may be
+    /// evaluated conditionally, or more than once, e.g., in a `match` arm, a
+    /// closure, a `while` or `else if` condition, or on the right of `&&` or
+    /// `||`.
+    fn is_conditional(&self, statement: usize, s: usize, e: usize) -> bool {
+        let joined = |i: usize, a: char, b: char| {
+            self.is_punct(i, a) && self.is_punct(i + 1, b)
+                && self.tokens[i].end == self.tokens[i + 1].start
+        };
+        // Whether the token at `i` ends an operand, so `&&` after it isn't a
+        // reference.
+        let ends_operand = |i: usize| {
+            let t = &self.tokens[i];
+            t.kind == TokenKind::Literal
+                || (t.kind == TokenKind::Ident && !is_keyword(t.text(self.src)))
+                || t.is_punct(')') || t.is_punct(']') || t.is_punct('}')
+        };
+
+        // Whether the group around the selection, and the part of it since the
+        // last `,`, are conditional.
+        let mut is_group_conditional = false;
+        let mut is_conditional = false;
+        let mut after_match = false;
+        let mut i = statement;
+        while i < s {
+            if self.closing(i).map_or(false, |close| close >= e) {
+                is_group_conditional = is_conditional || (after_match && self.is_punct(i, '{'));
+                is_conditional = is_group_conditional;
+                after_match = false;
+                i += 1;
+                continue;
+            }
+            if self.is_punct(i, ',') {
+                is_conditional = is_group_conditional;
+                after_match = false;
+            } else if self.is(i, "match") {
+                after_match = true;
+            } else if joined(i, '=', '>')
+                || (joined(i, '&', '&') && i > statement && ends_operand(i - 1))
+                || self.is_punct(i, '|')
+                || self.is(i, "while")
+                || self.is(i, "else")
+            {
+                is_conditional = true;
+            }
+            i = self.next(i);
+        }
+        is_conditional
+    }
+
+    /// The indices of the `fn` keyword and the body of the innermost function
+    /// whose body contains the tokens `s..e`.
+    fn enclosing_fn(&self, s: usize, e: usize) -> Option<(usize, usize)> {
+        let mut enclosing = None;
+        for i in 0..s {
+            // Not `fn(u8) -> u8`.
+            let is_named = self.tokens.get(i + 1).map_or(false, |t| t.kind == TokenKind::Ident);
+            if !self.is(i, "fn") || !is_named {
+                continue;
+            }
+            let mut body = i + 2;
+            while body < s && !self.is_punct(body, '{') && !self.is_punct(body, ';') {
+                body = self.next(body);
+            }
+            if body < s && self.is_punct(body, '{')
+                && self.closing(body).map_or(false, |close| close >= e)
+            {
+                enclosing = Some((i, body));
+            }
+        }
+        enclosing
+    }
+
+    /// The text of the return type of the function whose `fn` keyword and body
+    /// are at `keyword` and `body`.
+    fn return_type(&self, keyword: usize, body: usize) -> Option<String> {
+        let params = (keyword..body).find(|&i| self.is_punct(i, '('))?;
+        let arrow = self.closing(params)? + 1;
+        if !self.is_punct(arrow, '-') || !self.is_punct(arrow + 1, '>') {
+            return None;
+        }
+        let end = (arrow + 2..body).find(|&i| self.is(i, "where")).unwrap_or(body);
+        if end <= arrow + 2 {
+            return None;
+        }
+        Some(self.src[self.tokens[arrow + 2].start..self.tokens[end - 1].end].to_owned())
+    }
+
+    /// The text of the `self` parameter of the function whose `fn` keyword
+    /// and body are at `keyword` and `body`, e.g., `&mut self`.
+    fn receiver(&self, keyword: usize, body: usize) -> Option<String> {
+        let params = (keyword..body).find(|&i| self.is_punct(i, '('))?;
+        let params_end = self.closing(params)?;
+        let mut end = params + 1;
+        while end < params_end && !self.is_punct(end, ',') {
+            end = self.next(end);
+        }
+        if end == params + 1 || !(params + 1..end).any(|i| self.is(i, "self")) {
+            return None;
+        }
+        Some(self.src[self.tokens[params + 1].start..self.tokens[end - 1].end].to_owned())
+    }
+
+    /// The kind of block the item starting at `item` is in.
+    fn container(&self, item: usize) -> Container {
+        let open = match self.enclosing_braces(item, item + 1).first() {
+            Some(&open) => open,
+            None => return Container::Module,
+        };
+        let mut is_impl = false;
+        let mut is_trait_impl = false;
+        let mut i = open;
+        while i > 0 {
+            i -= 1;
+            let t = &self.tokens[i];
+            if t.is_punct(';') || t.is_punct('{') || t.is_punct('}') {
+                break;
+            }
+            if t.is_punct(')') || t.is_punct(']') {
+                i = self.pairs.get(&i).cloned().unwrap_or(i);
+            } else if t.is_ident(self.src, "fn") {
+                return Container::Module;
+            } else if t.is_ident(self.src, "trait") {
+                return Container::Trait;
+            } else if t.is_ident(self.src, "impl") {
+                is_impl = true;
+            } else if t.is_ident(self.src, "for") {
+                is_trait_impl = true;
+            }
+        }
+        match (is_impl, is_trait_impl) {
+            (true, true) => Container::Trait,
+            (true, false) => Container::Impl,
+            _ => Container::Module,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Container {
+    /// A module or a block.
+    Module,
+    /// An inherent impl.
+    Impl,
+    /// A trait or a trait impl, where functions can't be added.
+    Trait,
+}
+
+fn trim(src: &str, start: usize, end: usize) -> (usize, usize) {
+    let selection = &src[start..end];
+    let start = start + selection.len() - selection.trim_left().len();
+    (start, start + selection.trim().len())
+}
+
+/// An expression selected to be extracted into a variable.
+#[derive(Debug)]
+pub struct Variable {
+    /// The byte offsets of the expression, without surrounding whitespace.
+    pub start: usize,
+    pub end: usize,
+    /// The byte offset of the statement containing the expression, before
+    /// which the variable is declared.
+    pub statement: usize,
+    /// Whether the statement starts its line.
+    starts_line: bool,
+    indent: String,
+}
+
+/// The expression between `start` and `end`, if it can be extracted into a
+/// variable. It must be a whole expression in a block, but not a statement.
+pub fn variable(src: &str, start: usize, end: usize) -> Option<Variable> {
+    let (start, end) = trim(src, start, end);
+    let t = Tokens::new(src);
+    let (s, e) = t.selection(start, end)?;
+    if t.is(s, "let") {
+        return None;
+    }
+    let mut i = s;
+    while i < e {
+        if t.is_punct(i, ';') {
+            return None;
+        }
+        i = t.next(i);
+    }
+
+    let block = t.enclosing_braces(s, e).into_iter().find(|&open| t.is_block(open))?;
+    let (starts, ends) = t.statement_bounds(block);
+    let statement = *starts.iter().rev().find(|&&i| i <= s)?;
+    if ends.iter().any(|&end| statement < end && end < e) {
+        return None;
+    }
+    if t.is_conditional(statement, s, e) {
+        return None;
+    }
+
+    let statement = t.tokens[statement].start;
+    let line = line_start(src, statement);
+    Some(Variable {
+        start,
+        end,
+        statement,
+        starts_line: src[line..statement].trim().is_empty(),
+        indent: indentation(src, line).to_owned(),
+    })
+}
+
+impl Variable {
+    /// The edits adding `binding`, a `let` statement declaring `name` which is
+    /// indented as if at the top level, before the statement, and replacing
+    /// the expression with `name`.
+    pub fn edits(&self, binding: &str, name: &str) -> Vec<Edit> {
+        let binding = reindent(binding, &self.indent);
+        let separator = if self.starts_line {
+            format!("\n{}", self.indent)
+        } else {
+            " ".to_owned()
+        };
+        vec![
+            (self.statement, self.statement, format!("{}{}", binding.trim_left(), separator)),
+            (self.start, self.end, name.to_owned()),
+        ]
+    }
+}
+
+/// Statements selected to be extracted into a function.
+#[derive(Debug)]
+pub struct Statements {
+    /// The byte offsets of the statements, without surrounding whitespace.
+    pub start: usize,
+    pub end: usize,
+    /// The byte offsets of the enclosing function, from its `fn` keyword.
+    pub function: (usize, usize),
+    /// The enclosing function's `self` parameter, if the statements use
+    /// `self`.
+    pub receiver: Option<String>,
+    /// Whether the enclosing function is in an inherent impl.
+    pub in_impl: bool,
+    /// Whether the statements end with the tail expression of their block.
+    pub is_tail: bool,
+    /// Whether the statements' block is the enclosing function's body.
+    pub is_body: bool,
+    /// The enclosing function's return type.
+    pub return_type: Option<String>,
+    indent: String,
+}
+
+/// The statements between `start` and `end`, if they can be extracted into a
+/// function. They must be whole statements of the same block, in a function
+/// where another can be added, and not return from it or break out of a
+/// loop they're not in.
+pub fn statements(src: &str, start: usize, end: usize) -> Option<Statements> {
+    let (start, end) = trim(src, start, end);
+    let t = Tokens::new(src);
+    let (s, e) = t.selection(start, end)?;
+
+    let block = t.enclosing_braces(s, e).into_iter().find(|&open| t.is_block(open))?;
+    let (starts, ends) = t.statement_bounds(block);
+    let is_tail = Some(e) == t.closing(block) && !ends.contains(&e);
+    if !starts.contains(&s) || !(is_tail || ends.contains(&e)) {
+        return None;
+    }
+
+    let has_loop = (s..e).any(|i| t.is(i, "loop") || t.is(i, "while") || t.is(i, "for"));
+    let leaves = (s..e).any(|i| {
+        t.is(i, "return") || t.is_punct(i, '?')
+            || (!has_loop && (t.is(i, "break") || t.is(i, "continue")))
+    });
+    if leaves {
+        return None;
+    }
+
+    let (keyword, body) = t.enclosing_fn(s, e)?;
+    if block < body {
+        return None;
+    }
+    let in_impl = match t.container(keyword) {
+        Container::Module => false,
+        Container::Impl => true,
+        Container::Trait => return None,
+    };
+    let uses_self = (s..e).any(|i| t.is(i, "self") && !t.is_punct(i + 1, ':'));
+    let receiver = if uses_self {
+        Some(t.receiver(keyword, body)?)
+    } else {
+        None
+    };
+
+    let keyword_start = t.tokens[keyword].start;
+    Some(Statements {
+        start,
+        end,
+        function: (keyword_start, t.tokens[t.closing(body)?].end),
+        receiver,
+        in_impl,
+        is_tail,
+        is_body: block == body,
+        return_type: t.return_type(keyword, body),
+        indent: indentation(src, line_start(src, keyword_start)).to_owned(),
+    })
+}
+
+impl Statements {
+    /// The edit adding `function`, which is indented as if in an impl at the
+    /// top level, after the enclosing function.
+    pub fn insert_function(&self, function: &str) -> Edit {
+        let (_, end) = self.function;
+        (end, end, format!("\n\n{}", reindent(function, &self.indent)))
+    }
+}
+
+/// `text` with the indentation of its first line removed from each line, and
+/// `indent` added instead.
+pub fn reindent(text: &str, indent: &str) -> String {
+    let first_line = text.lines().next().unwrap_or("");
+    let level = &first_line[..first_line.len() - first_line.trim_left().len()];
+    text.lines()
+        .map(|line| {
+            let line = if line.starts_with(level) {
+                &line[level.len()..]
+            } else {
+                line
+            };
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The identifiers between `start` and `end` which may be variables, as byte
+/// offsets. Keywords, fields and methods are left out.
+pub fn identifiers(src: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let tokens = lex::tokenize(src);
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|&(_, t)| start <= t.start && t.end <= end && t.kind == TokenKind::Ident)
+        .filter(|&(i, t)| !is_keyword(t.text(src)) && (i == 0 || !tokens[i - 1].is_punct('.')))
+        .map(|(_, t)| (t.start, t.end))
+        .collect()
+}
+
+/// Whether the variable whose name starts at `name` is declared `mut`.
+pub fn is_mutable_binding(src: &str, name: usize) -> bool {
+    let before = src[..name].trim_right();
+    before.ends_with("mut")
+        && !before[..before.len() - 3].ends_with(|c: char| c == '_' || c.is_alphanumeric())
+}
+
+/// The text between `start` and `end`, with the variables whose names start at
+/// the offsets in `uses` dereferenced, as they are passed by reference to the
+/// extracted function. Method calls and field accesses are left as they are.
+pub fn deref_uses(src: &str, start: usize, end: usize, uses: &[usize]) -> String {
+    let t = Tokens::new(src);
+    let mut text = String::new();
+    let mut copied = start;
+    let mut uses = uses.to_vec();
+    uses.sort();
+    for &offset in &uses {
+        let i = match t.tokens.iter().position(|token| token.start == offset) {
+            Some(i) => i,
+            None => continue,
+        };
+        let name = t.tokens[i].text(src);
+        let deref = if t.is_punct(i + 1, '.') && !t.is_punct(i + 2, '.') {
+            continue;
+        } else if t.is_punct(i + 1, '(') || t.is_punct(i + 1, '[') {
+            format!("(*{})", name)
+        } else if t.is_field_shorthand(i) {
+            format!("{}: *{}", name, name)
+        } else {
+            format!("*{}", name)
+        };
+        text.push_str(&src[copied..offset]);
+        text.push_str(&deref);
+        copied = t.tokens[i].end;
+    }
+    text.push_str(&src[copied..end]);
+    text
+}
+
+/// The text of an extracted function, `params` includes their types.
+pub fn function_text(
+    name: &str,
+    receiver: Option<&str>,
+    params: &[String],
+    return_type: Option<&str>,
+    body: &str,
+    tail: Option<&str>,
+) -> String {
+    let params: Vec<_> = receiver.into_iter().chain(params.iter().map(|p| &**p)).collect();
+    let mut text = format!("fn {}({})", name, params.join(", "));
+    if let Some(return_type) = return_type {
+        text.push_str(&format!(" -> {}", return_type));
+    }
+    text.push_str(&format!(" {{\n{}\n", body));
+    if let Some(tail) = tail {
+        text.push_str(&format!("{}\n", tail));
+    }
+    text.push('}');
+    text
+}
+
+/// The text replacing extracted statements, calling `callee` and binding the
+/// variables in `outputs`, as their names and whether they are mutable. If
+/// the statements end with their block's tail expression, so does the call.
+pub fn call_text(
+    callee: &str,
+    args: &[String],
+    outputs: &[(String, bool)],
+    is_tail: bool,
+) -> String {
+    let call = format!("{}({})", callee, args.join(", "));
+    if is_tail {
+        return call;
+    }
+    let bindings: Vec<_> = outputs
+        .iter()
+        .map(|&(ref name, mutable)| if mutable { format!("mut {}", name) } else { name.clone() })
+        .collect();
+    match bindings.len() {
+        0 => format!("{};", call),
+        1 => format!("let {} = {};", bindings[0], call),
+        _ => format!("let ({}) = {};", bindings.join(", "), call),
+    }
+}
+
+/// A local variable used by code being extracted.
+struct UsedLocal {
+    name: String,
+    /// The span of its declaration.
+    span: Span,
+    /// The byte offset of its declaration.
+    decl: usize,
+    /// The byte offsets of its uses in the code being extracted.
+    uses: Vec<usize>,
+}
+
+/// The local variables used by the code between `start` and `end`, in the
+/// order they are first used.
+fn used_locals(
+    ctx: &InitActionContext,
+    file_path: &Path,
+    text: &str,
+    start: usize,
+    end: usize,
+) -> Vec<UsedLocal> {
+    let analysis = &ctx.analysis;
+    let line_index = LineIndex::new(text);
+    let mut locals: Vec<UsedLocal> = vec![];
+    for (name_start, name_end) in identifiers(text, start, end) {
+        let span = Span::from_positions(
+            line_index.offset_to_position(name_start),
+            line_index.offset_to_position(name_end),
+            file_path.to_owned(),
+        );
+        let def = match analysis.id(&span).and_then(|id| analysis.get_def(id)) {
+            Ok(def) => def,
+            Err(_) => continue,
+        };
+        if def.kind != DefKind::Local || def.span.file != file_path {
+            continue;
+        }
+        let decl = match line_index.position_to_offset(def.span.range.start()) {
+            Some(decl) => decl,
+            None => continue,
+        };
+        match locals.iter().position(|local| local.decl == decl) {
+            Some(i) => locals[i].uses.push(name_start),
+            None => locals.push(UsedLocal {
+                name: def.name,
+                span: def.span,
+                decl,
+                uses: vec![name_start],
+            }),
+        }
+    }
+    locals
+}
+
+/// The edits extracting the expression between `start` and `end` into a
+/// variable.
+pub fn variable_edits(
+    ctx: &InitActionContext,
+    file_path: &Path,
+    text: &str,
+    start: usize,
+    end: usize,
+) -> Result<Vec<Edit>, String> {
+    let variable = variable(text, start, end)
+        .ok_or_else(|| "Select an expression in a block to extract it".to_owned())?;
+    let locals = used_locals(ctx, file_path, text, variable.start, variable.end);
+    // The variable is declared before the statement, so must not use the
+    // statement's own bindings.
+    let declared = locals
+        .iter()
+        .find(|local| variable.statement <= local.decl && local.decl < variable.start);
+    if let Some(local) = declared {
+        return Err(format!(
+            "Cannot extract an expression using `{}`, which is declared in the same statement",
+            local.name
+        ));
+    }
+
+    let name = "new_variable";
+    let binding = format!("let {} = {};", name, &text[variable.start..variable.end]);
+    let binding = format_statements(&binding, ctx)
+        .ok_or_else(|| "Failed to format the new variable".to_owned())?;
+    Ok(variable.edits(&binding, name))
+}
+
+/// The edits extracting the statements between `start` and `end` into a
+/// function, which takes the locals they use as parameters and returns the
+/// locals they declare which are used afterwards.
+pub fn function_edits(
+    ctx: &InitActionContext,
+    file_path: &Path,
+    text: &str,
+    start: usize,
+    end: usize,
+) -> Result<Vec<Edit>, String> {
+    let statements = statements(text, start, end).ok_or_else(|| {
+        "Select whole statements of a function, which don't return or use `?`, to extract them"
+            .to_owned()
+    })?;
+    if statements.is_tail && !statements.is_body {
+        return Err("Cannot extract the tail expression of a nested block".to_owned());
+    }
+    let (start, end) = (statements.start, statements.end);
+    let (fn_start, fn_end) = statements.function;
+
+    let analysis = &ctx.analysis;
+    let line_index = LineIndex::new(text);
+    let type_of = |local: &UsedLocal| match analysis.show_type(&local.span) {
+        Ok(ref ty) if !ty.is_empty() => Ok(ty.clone()),
+        _ => Err(format!("Cannot find the type of `{}`", local.name)),
+    };
+    let used_after = |local: &UsedLocal| {
+        analysis
+            .find_all_refs(&local.span, false, false)
+            .unwrap_or_else(|_| vec![])
+            .iter()
+            .filter(|r| r.file == file_path)
+            .filter_map(|r| line_index.position_to_offset(r.range.start()))
+            .any(|offset| end <= offset && offset < fn_end)
+    };
+
+    let mut params = vec![];
+    let mut args = vec![];
+    let mut borrowed_uses = vec![];
+    let mut outputs = vec![];
+    let mut output_types = vec![];
+    for local in used_locals(ctx, file_path, text, start, end) {
+        if start <= local.decl && local.decl < end {
+            if used_after(&local) {
+                let mutable = is_mutable_binding(text, local.decl);
+                output_types.push(type_of(&local)?);
+                outputs.push((local.name, mutable));
+            }
+        } else if fn_start <= local.decl && local.decl < start {
+            let ty = type_of(&local)?;
+            let borrow = if is_mutable_binding(text, local.decl) {
+                "&mut "
+            } else if used_after(&local) {
+                "&"
+            } else {
+                ""
+            };
+            if !borrow.is_empty() {
+                borrowed_uses.extend(local.uses);
+            }
+            params.push(format!("{}: {}{}", local.name, borrow, ty));
+            args.push(format!("{}{}", borrow, local.name));
+        }
+    }
+
+    let name = "new_function";
+    let (return_type, tail) = if statements.is_tail {
+        (statements.return_type.clone(), None)
+    } else {
+        let names: Vec<_> = outputs.iter().map(|&(ref name, _)| name.clone()).collect();
+        match names.len() {
+            0 => (None, None),
+            1 => (Some(output_types[0].clone()), Some(names[0].clone())),
+            _ => (
+                Some(format!("({})", output_types.join(", "))),
+                Some(format!("({})", names.join(", "))),
+            ),
+        }
+    };
+    let function = function_text(
+        name,
+        statements.receiver.as_ref().map(String::as_str),
+        &params,
+        return_type.as_ref().map(String::as_str),
+        &deref_uses(text, start, end, &borrowed_uses),
+        tail.as_ref().map(String::as_str),
+    );
+    let function = format_impl_items(&function, ctx)
+        .ok_or_else(|| "Failed to format the new function".to_owned())?;
+
+    let callee = if statements.receiver.is_some() {
+        format!("self.{}", name)
+    } else if statements.in_impl {
+        format!("Self::{}", name)
+    } else {
+        name.to_owned()
+    };
+    let call = call_text(&callee, &args, &outputs, statements.is_tail);
+    Ok(vec![(start, end, call), statements.insert_function(&function)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(src: &str, mut edits: Vec<Edit>) -> String {
+        let mut src = src.to_owned();
+        edits.sort_by_key(|&(start, end, _)| (start, end));
+        for (start, end, text) in edits.into_iter().rev() {
+            src.replace_range(start..end, &text);
+        }
+        src
+    }
+
+    fn selection(src: &str, selected: &str) -> (usize, usize) {
+        let start = src.find(selected).unwrap();
+        (start, start + selected.len())
+    }
+
+    #[test]
+    fn test_variable() {
+        let src = "fn f() {\n    let x = g(1 + 2, 3);\n}";
+        let (start, end) = selection(src, "1 + 2");
+        let expr = variable(src, start, end).unwrap();
+        assert_eq!(&src[expr.start..expr.end], "1 + 2");
+        assert_eq!(expr.statement, src.find("let").unwrap());
+        assert_eq!(
+            apply(src, expr.edits("let v = 1\n    + 2;", "v")),
+            "fn f() {\n    let v = 1\n        + 2;\n    let x = g(v, 3);\n}"
+        );
+
+        // In a branch's block.
+        let src = "fn f() { if a { g(1 + 2) } }";
+        let (start, end) = selection(src, "1 + 2");
+        let edits = variable(src, start, end).unwrap().edits("let v = 1 + 2;", "v");
+        assert_eq!(apply(src, edits), "fn f() { if a { let v = 1 + 2; g(v) } }");
+
+        for src in &[
+            "fn f() { match 1 + 2 { _ => {} } }",
+            "fn f() { g(a && b, 1 + 2) }",
+            "fn f() { g(&&(1 + 2)) }",
+        ] {
+            let (start, end) = selection(src, "1 + 2");
+            assert!(variable(src, start, end).is_some(), "{:?}", src);
+        }
+        // Where declaring the variable first would change when it's evaluated.
+        for src in &[
+            "fn f() { match x { A => 1 + 2 } }",
+            "fn f() { match x { A if 1 + 2 > y => {} } }",
+            "fn f() { while 1 + 2 > x {} }",
+            "fn f() { if a {} else if 1 + 2 > x {} }",
+            "fn f() { g(a && h(1 + 2)) }",
+            "fn f() { g(a || (1 + 2 > x)) }",
+            "fn f() { v.map(|x| 1 + 2); }",
+        ] {
+            let (start, end) = selection(src, "1 + 2");
+            assert!(variable(src, start, end).is_none(), "{:?}", src);
+        }
+
+        let src = "fn f() {\n    let x = a();\n    b();\n}";
+        for selected in &["a();", "a();\n    b()", "(", "let x"] {
+            let (start, end) = selection(src, selected);
+            assert!(variable(src, start, end).is_none(), "{:?}", selected);
+        }
+        let src = "const X: u8 = 1 + 2;\nimpl S { const Y: u8 = 1 + 2; }";
+        let (start, end) = selection(src, "1 + 2");
+        assert!(variable(src, start, end).is_none());
+    }
+
+    #[test]
+    fn test_statements() {
+        let src = "impl S {
+    fn f(&mut self, x: u8) -> u8 {
+        let y = x;
+        if y > 0 {
+            self.g();
+        }
+        y + 1
+    }
+}";
+        let selected = "let y = x;\n        if y > 0 {\n            self.g();\n        }";
+        let (start, end) = selection(src, selected);
+        let selected = statements(src, start, end).unwrap();
+        assert_eq!(selected.receiver, Some("&mut self".to_owned()));
+        assert!(selected.in_impl);
+        assert!(!selected.is_tail);
+        assert!(selected.is_body);
+        assert_eq!(selected.return_type, Some("u8".to_owned()));
+        assert_eq!(selected.function, (src.find("fn").unwrap(), src.len() - 2));
+        assert_eq!(
+            selected.insert_function("    fn g() {\n        h();\n    }"),
+            (src.len() - 2, src.len() - 2, "\n\n    fn g() {\n        h();\n    }".to_owned())
+        );
+
+        let (start, end) = selection(src, "y + 1");
+        assert!(statements(src, start, end).unwrap().is_tail);
+        let (start, end) = selection(src, "self.g();");
+        let selected = statements(src, start, end).unwrap();
+        assert!(!selected.is_body);
+
+        for selected in &["let y = x;\n        if y > 0 {", "y > 0", "= x;"] {
+            let (start, end) = selection(src, selected);
+            assert!(statements(src, start, end).is_none(), "{:?}", selected);
+        }
+
+        let src = "impl T for S {\n    fn f() {\n        g();\n    }\n}";
+        let (start, end) = selection(src, "g();");
+        assert!(statements(src, start, end).is_none());
+        let src = "fn f() -> Option<u8> {\n    g()?;\n    None\n}";
+        let (start, end) = selection(src, "g()?;");
+        assert!(statements(src, start, end).is_none());
+    }
+
+    #[test]
+    fn test_deref_uses() {
+        let src = "x.push(y); *z += x.len() + y; w = x..y;";
+        let uses: Vec<_> = src.match_indices(|c| c == 'x' || c == 'y').map(|(i, _)| i).collect();
+        assert_eq!(
+            deref_uses(src, 0, src.len(), &uses),
+            "x.push(*y); *z += x.len() + *y; w = *x..*y;"
+        );
+
+        let src = "x[0] + x(1); S { a: 1, x }; Self { x, a: [1, x] }; if a { x }";
+        let uses: Vec<_> = src.match_indices('x').map(|(i, _)| i).collect();
+        assert_eq!(
+            deref_uses(src, 0, src.len(), &uses),
+            "(*x)[0] + (*x)(1); S { a: 1, x: *x }; Self { x: *x, a: [1, *x] }; if a { *x }"
+        );
+    }
+
+    #[test]
+    fn test_function_and_call_text() {
+        let params = vec!["x: &u8".to_owned(), "v: &mut Vec<u8>".to_owned()];
+        assert_eq!(
+            function_text("g", Some("&self"), &params, Some("(u8, u8)"), "a();", Some("(a, b)")),
+            "fn g(&self, x: &u8, v: &mut Vec<u8>) -> (u8, u8) {\na();\n(a, b)\n}"
+        );
+        let args = vec!["&x".to_owned()];
+        assert_eq!(call_text("g", &args, &[], false), "g(&x);");
+        assert_eq!(call_text("g", &args, &[], true), "g(&x)");
+        let outputs = vec![("a".to_owned(), true), ("b".to_owned(), false)];
+        assert_eq!(call_text("Self::g", &args, &outputs, false), "let (mut a, b) = Self::g(&x);");
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Computing folding ranges for `textDocument/foldingRange`.
+//!
+//! Folding only uses the text of the file, so that it works whether or not the
+//! last build succeeded.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::run::LineIndex;
+use lsp_data::{FoldingRange, FoldingRangeKind};
+
+use std::collections::HashMap;
+
+/// Finds the folding ranges in `text`.
+pub fn folding_ranges(text: &str) -> Vec<FoldingRange> {
+    let folder = Folder {
+        src: text,
+        tokens: lex::tokenize(text),
+        line_index: LineIndex::new(text),
+    };
+    let pairs = lex::delimiter_pairs(&folder.tokens);
+
+    let mut ranges = vec![];
+    ranges.extend(folder.delimited(&pairs));
+    ranges.extend(folder.match_arms(&pairs));
+    ranges.extend(folder.imports(&pairs));
+    ranges.extend(folder.comments());
+
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges.dedup_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
+
+fn folding_range(
+    start_line: u64,
+    end_line: u64,
+    kind: Option<FoldingRangeKind>,
+) -> Option<FoldingRange> {
+    if end_line <= start_line {
+        return None;
+    }
+    Some(FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind,
+    })
+}
+
+struct Folder<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    line_index: LineIndex,
+}
+
+impl<'a> Folder<'a> {
+    fn line(&self, offset: usize) -> u64 {
+        u64::from(self.line_index.offset_to_position(offset).row.0)
+    }
+
+    /// Is the token at `i` the first on its line?
+    fn starts_line(&self, i: usize) -> bool {
+        i == 0 || self.line(self.tokens[i - 1].end) < self.line(self.tokens[i].start)
+    }
+
+    // Blocks, parenthesized lists, etc. spanning several lines.
+    fn delimited(&self, pairs: &HashMap<usize, usize>) -> Vec<FoldingRange> {
+        pairs
+            .iter()
+            .filter(|&(&open, &close)| open < close)
+            .filter_map(|(&open, &close)| {
+                let mut end_line = self.line(self.tokens[close].start);
+                // Keep the closing delimiter visible if it's on its own line.
+                if self.starts_line(close) {
+                    end_line -= 1;
+                }
+                folding_range(self.line(self.tokens[open].start), end_line, None)
+            })
+            .collect()
+    }
+
+    // Match arms whose bodies aren't blocks, but span several lines.
+    fn match_arms(&self, pairs: &HashMap<usize, usize>) -> Vec<FoldingRange> {
+        let tokens = &self.tokens;
+        let mut result = vec![];
+        for (i, t) in tokens.iter().enumerate() {
+            if !t.is_ident(self.src, "match") {
+                continue;
+            }
+            let open = match self.skip_to(i + 1, pairs, |t| t.is_punct('{')) {
+                Some(open) => open,
+                None => continue,
+            };
+            let close = match pairs.get(&open) {
+                Some(&close) => close,
+                None => continue,
+            };
+
+            let mut arm_start = open + 1;
+            while arm_start < close {
+                let arrow = match self.skip_to(arm_start, pairs, |t| t.is_punct('=')) {
+                    Some(arrow) if arrow + 1 < close && tokens[arrow + 1].is_punct('>') => arrow,
+                    _ => break,
+                };
+                let body = arrow + 2;
+                if body < close && tokens[body].is_punct('{') {
+                    // Blocks are folded anyway.
+                    arm_start = pairs.get(&body).map_or(close, |&end| end + 1);
+                    if arm_start < close && tokens[arm_start].is_punct(',') {
+                        arm_start += 1;
+                    }
+                    continue;
+                }
+                let arm_end = self.skip_to(body, pairs, |t| t.is_punct(','))
+                    .unwrap_or(close);
+                result.extend(folding_range(
+                    self.line(tokens[arm_start].start),
+                    self.line(tokens[arm_end - 1].end),
+                    None,
+                ));
+                arm_start = arm_end + 1;
+            }
+        }
+        result
+    }
+
+    // The index of the first token from `start` which satisfies `f`, skipping
+    // over delimited groups.
+    fn skip_to<F: Fn(&Token) -> bool>(
+        &self,
+        start: usize,
+        pairs: &HashMap<usize, usize>,
+        f: F,
+    ) -> Option<usize> {
+        let mut i = start;
+        while i < self.tokens.len() {
+            let t = &self.tokens[i];
+            if f(t) {
+                return Some(i);
+            }
+            match t.kind {
+                TokenKind::Punct('(') | TokenKind::Punct('[') | TokenKind::Punct('{') => {
+                    i = *pairs.get(&i)? + 1;
+                }
+                TokenKind::Punct(')') | TokenKind::Punct(']') | TokenKind::Punct('}') => {
+                    return None;
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    // Runs of consecutive `use` items.
+    fn imports(&self, pairs: &HashMap<usize, usize>) -> Vec<FoldingRange> {
+        let tokens = &self.tokens;
+        let mut result = vec![];
+        // The first and last tokens of the current run of `use` items.
+        let mut run: Option<(usize, usize)> = None;
+        for (i, t) in tokens.iter().enumerate() {
+            if !t.is_ident(self.src, "use") {
+                continue;
+            }
+            let mut start = i;
+            // `pub use` or `pub(crate) use`
+            let visibility = if i > 0 && tokens[i - 1].is_punct(')') {
+                pairs.get(&(i - 1)).cloned()
+            } else {
+                Some(i)
+            };
+            if let Some(v) = visibility {
+                if v > 0 && tokens[v - 1].is_ident(self.src, "pub") {
+                    start = v - 1;
+                }
+            }
+            let end = match self.skip_to(i, pairs, |t| t.is_punct(';')) {
+                Some(end) => end,
+                None => continue,
+            };
+
+            run = match run {
+                Some((run_start, run_end)) if run_end + 1 == start => Some((run_start, end)),
+                Some((run_start, run_end)) => {
+                    result.extend(self.import_range(run_start, run_end));
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some((run_start, run_end)) = run {
+            result.extend(self.import_range(run_start, run_end));
+        }
+        result
+    }
+
+    fn import_range(&self, start: usize, end: usize) -> Option<FoldingRange> {
+        folding_range(
+            self.line(self.tokens[start].start),
+            self.line(self.tokens[end].end),
+            Some(FoldingRangeKind::Imports),
+        )
+    }
+
+    // Multi-line comments, runs of line comments and `// region:` markers.
+    fn comments(&self) -> Vec<FoldingRange> {
+        let mut result = vec![];
+        let mut regions = vec![];
+        // The kind of comment and the first and last lines of the current run of
+        // line comments.
+        let mut run: Option<(&str, u64, u64)> = None;
+
+        for (i, t) in self.tokens.iter().enumerate() {
+            let start_line = self.line(t.start);
+            let end_line = self.line(t.end);
+            let mut run_kind = None;
+
+            if t.kind == TokenKind::BlockComment {
+                result.extend(folding_range(
+                    start_line,
+                    end_line,
+                    Some(FoldingRangeKind::Comment),
+                ));
+            } else if t.kind == TokenKind::LineComment && self.starts_line(i) {
+                let text = t.text(self.src);
+                let marker = if t.is_doc_comment(self.src) {
+                    ""
+                } else {
+                    text["//".len()..].trim()
+                };
+                if marker == "region" || marker.starts_with("region:")
+                    || marker.starts_with("#region")
+                {
+                    regions.push(start_line);
+                } else if marker.starts_with("endregion") || marker.starts_with("#endregion") {
+                    if let Some(region_start) = regions.pop() {
+                        result.extend(folding_range(
+                            region_start,
+                            end_line,
+                            Some(FoldingRangeKind::Region),
+                        ));
+                    }
+                } else if text.starts_with("///") || text.starts_with("//!") {
+                    run_kind = Some(&text[..3]);
+                } else {
+                    run_kind = Some("//");
+                }
+            }
+
+            run = match (run, run_kind) {
+                (Some((kind, run_start, run_end)), Some(new_kind))
+                    if kind == new_kind && run_end + 1 == start_line =>
+                {
+                    Some((kind, run_start, start_line))
+                }
+                (previous, new_kind) => {
+                    if let Some((_, run_start, run_end)) = previous {
+                        result.extend(folding_range(
+                            run_start,
+                            run_end,
+                            Some(FoldingRangeKind::Comment),
+                        ));
+                    }
+                    new_kind.map(|kind| (kind, start_line, start_line))
+                }
+            };
+        }
+        if let Some((_, run_start, run_end)) = run {
+            result.extend(folding_range(run_start, run_end, Some(FoldingRangeKind::Comment)));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn folds(src: &str) -> Vec<(u64, u64, Option<FoldingRangeKind>)> {
+        folding_ranges(src)
+            .into_iter()
+            .map(|r| (r.start_line, r.end_line, r.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let src = r#"use std::io;
+use std::fmt::{
+    Debug,
+    Display,
+};
+
+/// Docs
+/// more docs.
+fn foo(x: Option<u32>) -> u32 {
+    match x {
+        Some(x) =>
+            x + 1,
+        None => {
+            0
+        }
+    }
+}
+
+// region: helpers
+/* a
+   block comment */
+fn bar() { }
+// endregion
+"#;
+        let comment = Some(FoldingRangeKind::Comment);
+        assert_eq!(
+            folds(src),
+            vec![
+                (0, 4, Some(FoldingRangeKind::Imports)),
+                (1, 3, None),
+                (6, 7, comment),
+                (8, 15, None),
+                (9, 14, None),
+                (10, 11, None),
+                (12, 13, None),
+                (18, 22, Some(FoldingRangeKind::Region)),
+                (19, 20, comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folding_ranges_incomplete() {
+        let src = "fn foo() {\n    bar(1,\n        2);\n\n// a\n// b\n";
+        assert_eq!(
+            folds(src),
+            vec![(1, 2, None), (4, 5, Some(FoldingRangeKind::Comment))]
+        );
+    }
+}
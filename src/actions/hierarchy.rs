@@ -0,0 +1,528 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Call hierarchies, for `textDocument/prepareCallHierarchy`,
+//! `callHierarchy/incomingCalls` and `callHierarchy/outgoingCalls`, and type
+//! hierarchies, for `textDocument/prepareTypeHierarchy`,
+//! `typeHierarchy/supertypes` and `typeHierarchy/subtypes`.
+//!
+//! Calls are found from the references in save-analysis, and the types in the
+//! type hierarchy from its impls. Save-analysis doesn't record the extents of
+//! items or the parts of impl headers and trait declarations, so these come
+//! from the text of their files, as for document symbols.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::outline;
+use actions::run::LineIndex;
+use analysis::{AnalysisHost, Def, DefKind, SymbolResult};
+use lsp_data::{ls_util, source_kind_from_def_kind, CallHierarchyIncomingCall,
+               CallHierarchyItem, CallHierarchyOutgoingCall, Range, TypeHierarchyItem};
+use vfs::{FileContents, Vfs};
+use Span;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Is `kind` a kind of def which may appear in a call hierarchy?
+pub fn is_callable(kind: DefKind) -> bool {
+    kind == DefKind::Function || kind == DefKind::Method
+}
+
+/// The functions and methods which call the def whose name is at `span`, with
+/// the ranges of their calls.
+pub fn incoming_calls(
+    analysis: &AnalysisHost,
+    vfs: &Vfs,
+    span: &Span,
+) -> Vec<CallHierarchyIncomingCall> {
+    let mut sources = Sources::new(vfs);
+    let mut symbols: HashMap<PathBuf, Vec<SymbolResult>> = HashMap::new();
+    // Callers, in the order they're found, with the ranges of their calls.
+    let mut callers: Vec<(Def, Vec<Range>)> = vec![];
+
+    let refs = analysis.find_all_refs(span, false, false).unwrap_or_else(|_| vec![]);
+    for r in refs {
+        if !sources.is_call(&r) {
+            continue;
+        }
+        let file_symbols = symbols
+            .entry(r.file.clone())
+            .or_insert_with(|| analysis.symbols(&r.file).unwrap_or_else(|_| vec![]));
+        let caller = match sources.enclosing_callable(&r, file_symbols) {
+            Some(caller) => caller,
+            None => continue,
+        };
+        let range = ls_util::rls_to_range(r.range);
+        if let Some(&mut (_, ref mut ranges)) =
+            callers.iter_mut().find(|&&mut (ref def, _)| def.span == caller.span)
+        {
+            ranges.push(range);
+            continue;
+        }
+        if let Ok(def) = analysis.get_def(caller.id) {
+            callers.push((def, vec![range]));
+        }
+    }
+
+    callers
+        .into_iter()
+        .map(|(def, from_ranges)| CallHierarchyIncomingCall {
+            from: sources.item(&def),
+            from_ranges,
+        })
+        .collect()
+}
+
+/// The functions and methods called by the def whose name is at `span`, with
+/// the ranges of the calls.
+pub fn outgoing_calls(
+    analysis: &AnalysisHost,
+    vfs: &Vfs,
+    span: &Span,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let mut sources = Sources::new(vfs);
+    let def = match analysis.id(span).and_then(|id| analysis.get_def(id)) {
+        Ok(def) => def,
+        Err(_) => return vec![],
+    };
+    let calls = match sources.get(&def.span.file) {
+        Some(source) => source.calls(&def),
+        None => return vec![],
+    };
+
+    // Callees, in the order they're found, with the ranges of their calls.
+    let mut callees: Vec<(Def, Vec<Range>)> = vec![];
+    for call in calls {
+        let callee = match analysis.id(&call).and_then(|id| analysis.get_def(id)) {
+            Ok(ref callee) if !is_callable(callee.kind) => continue,
+            Ok(callee) => callee,
+            Err(_) => continue,
+        };
+        let range = ls_util::rls_to_range(call.range);
+        match callees.iter_mut().find(|&&mut (ref def, _)| def.span == callee.span) {
+            Some(&mut (_, ref mut ranges)) => ranges.push(range),
+            None => callees.push((callee, vec![range])),
+        }
+    }
+
+    callees
+        .into_iter()
+        .map(|(def, from_ranges)| CallHierarchyOutgoingCall {
+            to: sources.item(&def),
+            from_ranges,
+        })
+        .collect()
+}
+
+/// Is `kind` a kind of def which may appear in a type hierarchy?
+pub fn is_type(kind: DefKind) -> bool {
+    match kind {
+        DefKind::Trait | DefKind::Struct | DefKind::Enum | DefKind::Union => true,
+        _ => false,
+    }
+}
+
+/// The supertraits of the trait whose name is at `span`, or the traits
+/// implemented by the type whose name is at `span`.
+pub fn supertypes(analysis: &AnalysisHost, vfs: &Vfs, span: &Span) -> Vec<TypeHierarchyItem> {
+    let mut sources = Sources::new(vfs);
+    let def = match analysis.id(span).and_then(|id| analysis.get_def(id)) {
+        Ok(def) => def,
+        Err(_) => return vec![],
+    };
+
+    let names = if def.kind == DefKind::Trait {
+        sources
+            .get(&def.span.file)
+            .map_or(vec![], |source| source.supertrait_names(&def.span))
+    } else {
+        impl_spans(analysis, span)
+            .iter()
+            .filter_map(|impl_span| {
+                let (trait_name, _) = sources.get(&impl_span.file)?.impl_header(impl_span)?;
+                trait_name
+            })
+            .collect()
+    };
+    type_items(analysis, &mut sources, &def, names)
+}
+
+/// The types implementing the trait whose name is at `span`. Blanket impls,
+/// e.g., `impl<T: Foo> Bar for T`, have no type to show, so are left out.
+pub fn subtypes(analysis: &AnalysisHost, vfs: &Vfs, span: &Span) -> Vec<TypeHierarchyItem> {
+    let mut sources = Sources::new(vfs);
+    let def = match analysis.id(span).and_then(|id| analysis.get_def(id)) {
+        Ok(ref def) if def.kind != DefKind::Trait => return vec![],
+        Ok(def) => def,
+        Err(_) => return vec![],
+    };
+
+    let names = impl_spans(analysis, span)
+        .iter()
+        .filter_map(|impl_span| {
+            let (_, self_name) = sources.get(&impl_span.file)?.impl_header(impl_span)?;
+            self_name
+        })
+        .collect();
+    type_items(analysis, &mut sources, &def, names)
+}
+
+// The spans of the impls of the trait or type whose name is at `span`.
+fn impl_spans(analysis: &AnalysisHost, span: &Span) -> Vec<Span> {
+    analysis
+        .id(span)
+        .and_then(|id| analysis.find_impls(id))
+        .unwrap_or_else(|_| vec![])
+}
+
+// Items for the traits and types referred to by `names`, other than `def`.
+fn type_items(
+    analysis: &AnalysisHost,
+    sources: &mut Sources,
+    def: &Def,
+    names: Vec<Span>,
+) -> Vec<TypeHierarchyItem> {
+    let mut defs: Vec<Def> = vec![];
+    for name in names {
+        let name_def = match analysis.id(&name).and_then(|id| analysis.get_def(id)) {
+            Ok(name_def) => name_def,
+            Err(_) => continue,
+        };
+        if is_type(name_def.kind) && name_def.span != def.span
+            && !defs.iter().any(|d| d.span == name_def.span)
+        {
+            defs.push(name_def);
+        }
+    }
+    defs.iter().map(|def| sources.item(def)).collect()
+}
+
+/// The text of files, loaded from the VFS as needed.
+pub struct Sources<'a> {
+    vfs: &'a Vfs,
+    files: HashMap<PathBuf, Option<Source>>,
+}
+
+impl<'a> Sources<'a> {
+    pub fn new(vfs: &'a Vfs) -> Sources<'a> {
+        Sources {
+            vfs,
+            files: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, file: &Path) -> Option<&Source> {
+        let vfs = self.vfs;
+        self.files
+            .entry(file.to_owned())
+            .or_insert_with(|| match vfs.load_file(file) {
+                Ok(FileContents::Text(text)) => Some(Source::new(file, text)),
+                _ => None,
+            })
+            .as_ref()
+    }
+
+    /// A call or type hierarchy item for `def`.
+    pub fn item(&mut self, def: &Def) -> CallHierarchyItem {
+        let location = ls_util::rls_to_location(&def.span);
+        let range = self.get(&def.span.file)
+            .and_then(|source| source.item_range(&def.span, def.kind))
+            .unwrap_or(location.range);
+        CallHierarchyItem {
+            name: def.name.clone(),
+            kind: source_kind_from_def_kind(def.kind),
+            detail: if def.value.is_empty() {
+                None
+            } else {
+                Some(def.value.clone())
+            },
+            uri: location.uri,
+            range,
+            selection_range: location.range,
+        }
+    }
+
+    // Is the reference at `span` called, rather than, e.g., passed as a
+    // function pointer?
+    fn is_call(&mut self, span: &Span) -> bool {
+        let source = match self.get(&span.file) {
+            Some(source) => source,
+            None => return false,
+        };
+        source.token_at(span).map_or(false, |i| source.is_called(i))
+    }
+
+    // The innermost function or method among `symbols` containing `span`.
+    fn enclosing_callable<'s>(
+        &mut self,
+        span: &Span,
+        symbols: &'s [SymbolResult],
+    ) -> Option<&'s SymbolResult> {
+        let source = self.get(&span.file)?;
+        let offset = source.line_index.position_to_offset(span.range.start())?;
+        symbols
+            .iter()
+            .filter(|s| is_callable(s.kind))
+            .filter_map(|s| {
+                let (start, end) = source.item_extent(&s.span, s.kind)?;
+                if start <= offset && offset < end {
+                    Some((end - start, s))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|&(len, _)| len)
+            .map(|(_, s)| s)
+    }
+}
+
+struct Source {
+    file: PathBuf,
+    text: String,
+    tokens: Vec<Token>,
+    line_index: LineIndex,
+}
+
+impl Source {
+    fn new(file: &Path, text: String) -> Source {
+        Source {
+            file: file.to_owned(),
+            tokens: lex::tokenize(&text),
+            line_index: LineIndex::new(&text),
+            text,
+        }
+    }
+
+    // The index of the token starting at the start of `span`.
+    fn token_at(&self, span: &Span) -> Option<usize> {
+        let offset = self.line_index.position_to_offset(span.range.start())?;
+        self.tokens.binary_search_by_key(&offset, |t| t.start).ok()
+    }
+
+    // Is the name at `i` followed by the arguments of a call, `(..)` or
+    // `::<..>(..)`?
+    fn is_called(&self, i: usize) -> bool {
+        let is = |n: usize, c: char| self.tokens.get(i + n).map_or(false, |t| t.is_punct(c));
+        is(1, '(') || is(1, ':') && is(2, ':') && is(3, '<')
+    }
+
+    fn span(&self, t: &Token) -> Span {
+        Span::from_positions(
+            self.line_index.offset_to_position(t.start),
+            self.line_index.offset_to_position(t.end),
+            self.file.clone(),
+        )
+    }
+
+    // The offsets of the item whose name is at `span`.
+    fn item_extent(&self, span: &Span, kind: DefKind) -> Option<(usize, usize)> {
+        outline::item_extent(&self.text, &self.tokens, self.token_at(span)?, kind)
+    }
+
+    fn item_range(&self, span: &Span, kind: DefKind) -> Option<Range> {
+        let (start, end) = self.item_extent(span, kind)?;
+        Some(Range::new(
+            ls_util::rls_to_position(self.line_index.offset_to_position(start)),
+            ls_util::rls_to_position(self.line_index.offset_to_position(end)),
+        ))
+    }
+
+    // The spans of the last names in the trait and the self type of the header
+    // of the impl containing `span`, e.g., `Baz` and `Bar` in
+    // `impl<T> foo::Baz<T> for Bar<T>`.
+    fn impl_header(&self, span: &Span) -> Option<(Option<Span>, Option<Span>)> {
+        let tokens = &self.tokens;
+        let i = self.token_at(span)?;
+        let start = (0..i + 1).rev().find(|&j| tokens[j].is_ident(&self.text, "impl"))?;
+        if tokens[start..i].iter().any(|t| t.is_punct('{') || t.is_punct(';')) {
+            return None;
+        }
+
+        // Skip generics, `impl<T: Fn() -> u8>`.
+        let mut header_start = start + 1;
+        if tokens.get(header_start).map_or(false, |t| t.is_punct('<')) {
+            header_start = self.after_angle_brackets(header_start)?;
+        }
+        let header_end = (header_start..tokens.len()).find(|&j| {
+            tokens[j].is_punct('{') || tokens[j].is_punct(';')
+                || tokens[j].is_ident(&self.text, "where")
+        })?;
+        let for_token = (header_start..header_end).find(|&j| {
+            tokens[j].is_ident(&self.text, "for") && self.angle_depth(header_start, j) == 0
+        });
+
+        Some(match for_token {
+            Some(for_token) => (
+                self.last_name(header_start, for_token),
+                self.last_name(for_token + 1, header_end),
+            ),
+            None => (None, self.last_name(header_start, header_end)),
+        })
+    }
+
+    // The spans of the last names of each supertrait of the trait whose name is
+    // at `span`, e.g., `Bar` and `Baz` in `trait Foo<T>: Bar + baz::Baz<T> {}`.
+    fn supertrait_names(&self, span: &Span) -> Vec<Span> {
+        let tokens = &self.tokens;
+        let name = match self.token_at(span) {
+            Some(name) => name,
+            None => return vec![],
+        };
+        let mut start = name + 1;
+        if tokens.get(start).map_or(false, |t| t.is_punct('<')) {
+            start = match self.after_angle_brackets(start) {
+                Some(start) => start,
+                None => return vec![],
+            };
+        }
+        if !tokens.get(start).map_or(false, |t| t.is_punct(':')) {
+            return vec![];
+        }
+
+        let mut result = vec![];
+        let mut bound_start = start + 1;
+        for j in start + 1..tokens.len() {
+            let t = &tokens[j];
+            let at_end = t.is_punct('{') || t.is_punct(';') || t.is_ident(&self.text, "where");
+            if at_end || t.is_punct('+') && self.angle_depth(start, j) == 0 {
+                result.extend(self.last_name(bound_start, j));
+                bound_start = j + 1;
+            }
+            if at_end {
+                break;
+            }
+        }
+        result
+    }
+
+    // The index after the `>` closing the `<` at `open`.
+    fn after_angle_brackets(&self, open: usize) -> Option<usize> {
+        let mut depth = 0;
+        for j in open..self.tokens.len() {
+            let t = &self.tokens[j];
+            if t.is_punct('<') {
+                depth += 1;
+            } else if t.is_punct('>') && !self.tokens[j - 1].is_punct('-') {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j + 1);
+                }
+            } else if t.is_punct('{') || t.is_punct(';') {
+                return None;
+            }
+        }
+        None
+    }
+
+    // The depth of angle brackets at `end`, counting from `start`.
+    fn angle_depth(&self, start: usize, end: usize) -> i32 {
+        let mut depth = 0;
+        for j in start..end {
+            if self.tokens[j].is_punct('<') {
+                depth += 1;
+            } else if self.tokens[j].is_punct('>') && j > start
+                && !self.tokens[j - 1].is_punct('-')
+            {
+                depth -= 1;
+            }
+        }
+        depth
+    }
+
+    // The span of the last name in the type or path in `start..end`, outside of
+    // any generic arguments, e.g., `Bar` in `&foo::Bar<Baz>`.
+    fn last_name(&self, start: usize, end: usize) -> Option<Span> {
+        (start..end)
+            .rev()
+            .find(|&j| {
+                self.tokens[j].kind == TokenKind::Ident && self.angle_depth(start, j) == 0
+                    && !["dyn", "mut", "const"].contains(&self.tokens[j].text(&self.text))
+            })
+            .map(|j| self.span(&self.tokens[j]))
+    }
+
+    // The spans of the names called in the body of `def`.
+    fn calls(&self, def: &Def) -> Vec<Span> {
+        let name = match self.token_at(&def.span) {
+            Some(name) => name,
+            None => return vec![],
+        };
+        let (_, end) = match outline::item_extent(&self.text, &self.tokens, name, def.kind) {
+            Some(extent) => extent,
+            None => return vec![],
+        };
+
+        self.tokens
+            .iter()
+            .enumerate()
+            .skip(name + 1)
+            .take_while(|&(_, t)| t.end <= end)
+            .filter(|&(i, t)| t.kind == TokenKind::Ident && self.is_called(i))
+            .map(|(_, t)| self.span(t))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text<'a>(source: &'a Source, span: &Span) -> &'a str {
+        let offset = |pos| source.line_index.position_to_offset(pos).unwrap();
+        &source.text[offset(span.range.start())..offset(span.range.end())]
+    }
+
+    fn span_of(source: &Source, name: &str) -> Span {
+        let offset = source.text.find(name).unwrap();
+        let i = source.tokens.iter().position(|t| t.start == offset).unwrap();
+        source.span(&source.tokens[i])
+    }
+
+    #[test]
+    fn test_impl_header() {
+        let source = Source::new(
+            Path::new("lib.rs"),
+            "impl<T: Fn() -> u8> foo::Baz<T> for &'a mut Bar<T> where T: Copy {}\n\
+             impl Qux<u8> { fn f() {} }\n"
+                .to_owned(),
+        );
+        let header = |name| {
+            let (trait_name, self_name) = source.impl_header(&span_of(&source, name)).unwrap();
+            (
+                trait_name.map(|s| text(&source, &s).to_owned()),
+                self_name.map(|s| text(&source, &s).to_owned()),
+            )
+        };
+        let baz_bar = (Some("Baz".to_owned()), Some("Bar".to_owned()));
+        assert_eq!(header("impl<"), baz_bar);
+        assert_eq!(header("Bar"), baz_bar);
+        assert_eq!(header("Qux"), (None, Some("Qux".to_owned())));
+        assert_eq!(source.impl_header(&span_of(&source, "f()")), None);
+    }
+
+    #[test]
+    fn test_supertrait_names() {
+        let source = Source::new(
+            Path::new("lib.rs"),
+            "trait Foo<T: Into<u8>>: Bar + baz::Baz<T> + 'static where T: Copy {}\n\
+             trait Qux {}\n"
+                .to_owned(),
+        );
+        let names = |name| {
+            source
+                .supertrait_names(&span_of(&source, name))
+                .iter()
+                .map(|s| text(&source, s).to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("Foo"), vec!["Bar", "Baz"]);
+        assert_eq!(names("Qux"), Vec::<String>::new());
+    }
+}
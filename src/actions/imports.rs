@@ -0,0 +1,808 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding and adding `use` declarations, for code actions which edit imports.
+//!
+//! This works on the tokens of the current text of a file, so that imports can
+//! be added to code which doesn't compile yet.
+
+use actions::lex::{self, Token, TokenKind};
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// A `use` declaration directly within a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UseItem {
+    /// The byte offset of the start of the declaration, including any
+    /// attributes, doc comments and visibility.
+    pub start: usize,
+    /// The byte offset just after the `;`.
+    pub end: usize,
+    /// The tree of paths imported, e.g., `std::{fmt, io}`, with any whitespace
+    /// collapsed to single spaces.
+    pub tree: String,
+    /// The visibility, e.g., `pub(crate) `, if the declaration has no
+    /// attributes or comments.
+    plain_visibility: Option<String>,
+    /// The token indices of the tree.
+    tree_tokens: (usize, usize),
+}
+
+impl UseItem {
+    /// The first segment of the imported paths, e.g., `std`.
+    pub fn root(&self) -> &str {
+        path_root(&self.tree)
+    }
+}
+
+fn path_root(path: &str) -> &str {
+    path.trim_left_matches("::").split("::").next().unwrap()
+}
+
+/// A path imported by a `use` declaration, e.g., `a::b` from `use a::{b, c};`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImportedPath {
+    /// The path, including any rename, e.g., `a::b as c`, `a::*` or `a::self`.
+    path: String,
+    /// The byte range of the last segment of the path and any rename.
+    start: usize,
+    end: usize,
+}
+
+/// A module in the tokens of a file, split into items. Trait and impl bodies
+/// can be split the same way.
+pub(super) struct Module<'a> {
+    src: &'a str,
+    tokens: &'a [Token],
+    pairs: &'a HashMap<usize, usize>,
+    /// The token indices of the module's body, the whole file if it isn't an
+    /// inline module.
+    body: (usize, usize),
+    /// The items in the module's body, as ranges of token indices. Each
+    /// includes its attributes and outer doc comments, but not other comments.
+    pub(super) items: Vec<(usize, usize)>,
+}
+
+/// The token indices of the bodies of the inline modules in `tokens`, which
+/// are `(0, tokens.len())` for the file itself. Nested modules come after the
+/// modules containing them.
+fn module_bodies(
+    src: &str,
+    tokens: &[Token],
+    pairs: &HashMap<usize, usize>,
+) -> Vec<(usize, usize)> {
+    let mut bodies = vec![(0, tokens.len())];
+    for i in 0..tokens.len().saturating_sub(2) {
+        if tokens[i].is_ident(src, "mod") && tokens[i + 1].kind == TokenKind::Ident
+            && tokens[i + 2].is_punct('{')
+        {
+            if let Some(&close) = pairs.get(&(i + 2)) {
+                bodies.push((i + 3, close));
+            }
+        }
+    }
+    bodies
+}
+
+/// The token indices of the body of the innermost module containing `offset`.
+fn module_body(
+    src: &str,
+    tokens: &[Token],
+    pairs: &HashMap<usize, usize>,
+    offset: usize,
+) -> (usize, usize) {
+    let contains = |&(start, end): &(usize, usize)| {
+        start == 0 || (tokens[start - 1].end <= offset && offset <= tokens[end].start)
+    };
+    *module_bodies(src, tokens, pairs).iter().rev().find(|b| contains(b)).unwrap()
+}
+
+impl<'a> Module<'a> {
+    pub(super) fn new(
+        src: &'a str,
+        tokens: &'a [Token],
+        pairs: &'a HashMap<usize, usize>,
+        body: (usize, usize),
+    ) -> Module<'a> {
+        let mut module = Module {
+            src,
+            tokens,
+            pairs,
+            body,
+            items: vec![],
+        };
+        module.items = module.split_items();
+        module
+    }
+
+    fn split_items(&self) -> Vec<(usize, usize)> {
+        let mut items = vec![];
+        let mut start = None;
+        let mut i = self.body.0;
+        while i < self.body.1 {
+            let t = &self.tokens[i];
+            if t.is_comment() {
+                if start.is_none() && is_outer_doc_comment(self.src, t) {
+                    start = Some(i);
+                }
+                i += 1;
+                continue;
+            }
+            let item_start = *start.get_or_insert(i);
+
+            if t.is_punct('#') {
+                let is_inner = self.tokens.get(i + 1).map_or(false, |t| t.is_punct('!'));
+                let open = if is_inner { i + 2 } else { i + 1 };
+                if let Some(close) = self.closing(open) {
+                    if is_inner {
+                        items.push((item_start, close + 1));
+                        start = None;
+                    }
+                    i = close + 1;
+                    continue;
+                }
+            }
+
+            match self.closing(i) {
+                Some(close) => {
+                    i = close + 1;
+                    // A block ends an item, unless it is followed by a `;` as in
+                    // `use foo::{a, b};` or `const X: S = S {};`.
+                    if t.is_punct('{') && !self.tokens.get(i).map_or(false, |t| t.is_punct(';')) {
+                        items.push((item_start, i));
+                        start = None;
+                    }
+                }
+                None => {
+                    i += 1;
+                    if t.is_punct(';') {
+                        items.push((item_start, i));
+                        start = None;
+                    }
+                }
+            }
+        }
+        if let Some(start) = start {
+            items.push((start, self.body.1));
+        }
+        items
+    }
+
+    /// The index of the token after any attributes, doc comments and
+    /// visibility at the start of `item`.
+    pub(super) fn item_keyword(&self, item: (usize, usize)) -> Option<usize> {
+        let mut i = item.0;
+        while i < item.1 {
+            let t = &self.tokens[i];
+            if t.is_comment() {
+                i += 1;
+            } else if t.is_punct('#') {
+                i = match self.closing(i + 1) {
+                    Some(close) => close + 1,
+                    None => return None,
+                };
+            } else if t.is_ident(self.src, "pub") {
+                i += 1;
+                // `pub(crate)`, etc.
+                if self.tokens.get(i).map_or(false, |t| t.is_punct('(')) {
+                    if let Some(close) = self.closing(i) {
+                        i = close + 1;
+                    }
+                }
+            } else {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn use_item(&self, item: (usize, usize)) -> Option<UseItem> {
+        let keyword = self.item_keyword(item)?;
+        if !self.tokens[keyword].is_ident(self.src, "use") {
+            return None;
+        }
+        let last = item.1 - 1;
+        if keyword + 1 >= last || !self.tokens[last].is_punct(';') {
+            return None;
+        }
+        let start = self.tokens[item.0].start;
+        let prefix = &self.src[start..self.tokens[keyword].start];
+        let plain_visibility = if prefix.contains('#') || prefix.contains('/') {
+            None
+        } else {
+            Some(prefix.split_whitespace().map(|s| s.to_owned() + " ").collect())
+        };
+        let tree = &self.src[self.tokens[keyword + 1].start..self.tokens[last - 1].end];
+        Some(UseItem {
+            start,
+            end: self.tokens[last].end,
+            tree: tree.split_whitespace().collect::<Vec<_>>().join(" "),
+            plain_visibility,
+            tree_tokens: (keyword + 1, last),
+        })
+    }
+
+    /// The `use` declarations, in groups separated by blank lines or other
+    /// items.
+    fn use_groups(&self) -> Vec<Vec<UseItem>> {
+        let mut groups: Vec<Vec<UseItem>> = vec![];
+        let mut prev: Option<(usize, usize)> = None;
+        for (n, &item) in self.items.iter().enumerate() {
+            let u = match self.use_item(item) {
+                Some(u) => u,
+                None => continue,
+            };
+            let end = u.end;
+            match prev {
+                Some((prev_n, prev_end))
+                    if prev_n + 1 == n && self.src[prev_end..u.start].matches('\n').count() < 2 =>
+                {
+                    groups.last_mut().unwrap().push(u)
+                }
+                _ => groups.push(vec![u]),
+            }
+            prev = Some((n, end));
+        }
+        groups
+    }
+
+    /// Adds the paths imported by the use tree in the tokens `start..end` to
+    /// `paths`, each prefixed with `prefix`. `None` if the tree isn't valid.
+    fn flatten_tree(
+        &self,
+        start: usize,
+        end: usize,
+        prefix: &str,
+        paths: &mut Vec<ImportedPath>,
+    ) -> Option<()> {
+        let mut path = prefix.to_owned();
+        let mut i = start;
+        while i < end {
+            let t = &self.tokens[i];
+            let is_path_sep = |i: usize| {
+                i + 1 < end && self.tokens[i].is_punct(':') && self.tokens[i + 1].is_punct(':')
+            };
+            if is_path_sep(i) {
+                path.push_str("::");
+                i += 2;
+            } else if t.is_punct('*') && i + 1 == end {
+                path.push('*');
+                paths.push(ImportedPath { path, start: t.start, end: t.end });
+                return Some(());
+            } else if t.is_punct('{') && self.closing(i) == Some(end - 1) {
+                let mut tree_start = i + 1;
+                let mut j = i + 1;
+                while j < end - 1 {
+                    if let Some(close) = self.closing(j) {
+                        j = close + 1;
+                        continue;
+                    }
+                    if self.tokens[j].is_punct(',') {
+                        if j > tree_start {
+                            self.flatten_tree(tree_start, j, &path, paths)?;
+                        }
+                        tree_start = j + 1;
+                    }
+                    j += 1;
+                }
+                if end - 1 > tree_start {
+                    self.flatten_tree(tree_start, end - 1, &path, paths)?;
+                }
+                return Some(());
+            } else if t.kind == TokenKind::Ident {
+                path.push_str(t.text(self.src));
+                if i + 1 == end {
+                    paths.push(ImportedPath { path, start: t.start, end: t.end });
+                    return Some(());
+                }
+                if i + 3 == end && self.tokens[i + 1].is_ident(self.src, "as")
+                    && self.tokens[i + 2].kind == TokenKind::Ident
+                {
+                    let alias = &self.tokens[i + 2];
+                    path.push_str(" as ");
+                    path.push_str(alias.text(self.src));
+                    paths.push(ImportedPath { path, start: t.start, end: alias.end });
+                    return Some(());
+                }
+                if !is_path_sep(i + 1) {
+                    return None;
+                }
+                i += 1;
+            } else {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// The replacement for `group` with its imports merged, sorted if
+    /// `reorder`, and without duplicates or imports within the `unused` ranges.
+    fn organize_group(
+        &self,
+        group: &[UseItem],
+        unused: &[(usize, usize)],
+        reorder: bool,
+    ) -> Option<(usize, usize, String)> {
+        let start = group[0].start;
+        let end = group[group.len() - 1].end;
+        // Comments between the declarations would be lost.
+        if self.src[start..end].contains("//") || self.src[start..end].contains("/*") {
+            return None;
+        }
+
+        // The imported paths of each visibility and parent module, in order.
+        let mut imports: Vec<(&str, String, Vec<String>)> = vec![];
+        for u in group {
+            let visibility = match u.plain_visibility {
+                Some(ref visibility) => visibility.as_str(),
+                None => return None,
+            };
+            let mut paths = vec![];
+            self.flatten_tree(u.tree_tokens.0, u.tree_tokens.1, "", &mut paths)?;
+            for p in paths {
+                if unused.iter().any(|&(s, e)| s <= p.start && p.end <= e) {
+                    continue;
+                }
+                let (parent, name) = match p.path.rfind("::") {
+                    Some(i) if i > 0 && !p.path[..i].contains(' ') => {
+                        (p.path[..i].to_owned(), p.path[i + 2..].to_owned())
+                    }
+                    _ => (String::new(), p.path),
+                };
+                let existing = imports.iter().position(|&(v, ref parent_, _)| {
+                    v == visibility && *parent_ == parent && !parent.is_empty()
+                });
+                match existing {
+                    Some(n) => imports[n].2.push(name),
+                    None => imports.push((visibility, parent, vec![name])),
+                }
+            }
+        }
+
+        let mut lines: Vec<(String, String)> = imports
+            .into_iter()
+            .map(|(visibility, parent, mut names)| {
+                if reorder {
+                    names.sort_by(|a, b| (a != "self", a).cmp(&(b != "self", b)));
+                }
+                let mut seen = HashSet::new();
+                names.retain(|name| seen.insert(name.clone()));
+                let path = if parent.is_empty() {
+                    names.remove(0)
+                } else if names.len() == 1 && names[0] != "self" {
+                    format!("{}::{}", parent, names[0])
+                } else {
+                    format!("{}::{{{}}}", parent, names.join(", "))
+                };
+                let line = format!("{}use {};", visibility, path);
+                (path, line)
+            })
+            .collect();
+        if reorder {
+            lines.sort();
+        }
+        let mut seen = HashSet::new();
+        lines.retain(|line| seen.insert(line.1.clone()));
+
+        if lines.is_empty() {
+            // Remove the lines of the group, and a blank line after it.
+            let start = line_start(self.src, start);
+            let mut end = self.src[end..].find('\n').map_or(self.src.len(), |i| end + i + 1);
+            if self.src[end..].starts_with('\n') {
+                end += 1;
+            }
+            return Some((start, end, String::new()));
+        }
+
+        let indent = indentation(self.src, line_start(self.src, start));
+        let separator = format!("\n{}", indent);
+        let text = lines.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join(&separator);
+        if self.src[start..end] == text {
+            None
+        } else {
+            Some((start, end, text))
+        }
+    }
+
+    /// Is `item` an inner attribute or `extern crate`, which come before
+    /// imports?
+    fn is_preamble(&self, item: (usize, usize)) -> bool {
+        if self.tokens[item.0].is_punct('#')
+            && self.tokens.get(item.0 + 1).map_or(false, |t| t.is_punct('!'))
+        {
+            return true;
+        }
+        match self.item_keyword(item) {
+            Some(i) => {
+                self.tokens[i].is_ident(self.src, "extern")
+                    && self.tokens.get(i + 1).map_or(false, |t| t.is_ident(self.src, "crate"))
+            }
+            None => false,
+        }
+    }
+
+    /// The index of the delimiter closing the one at `i`, if it opens a group.
+    fn closing(&self, i: usize) -> Option<usize> {
+        match self.pairs.get(&i) {
+            Some(&close) if close > i => Some(close),
+            _ => None,
+        }
+    }
+
+    /// The byte offset of the start of the body.
+    fn body_start(&self) -> usize {
+        if self.body.0 == 0 {
+            0
+        } else {
+            self.tokens[self.body.0 - 1].end
+        }
+    }
+}
+
+fn is_outer_doc_comment(src: &str, t: &Token) -> bool {
+    t.is_doc_comment(src) && !t.text(src).starts_with("//!") && !t.text(src).starts_with("/*!")
+}
+
+pub(super) fn line_start(src: &str, offset: usize) -> usize {
+    src[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+pub(super) fn indentation(src: &str, line_start: usize) -> &str {
+    let line = &src[line_start..];
+    let len = line.len() - line.trim_left_matches(|c| c == ' ' || c == '\t').len();
+    &line[..len]
+}
+
+/// The `use` declarations in the innermost module of `src` containing `offset`.
+pub fn use_items(src: &str, offset: usize) -> Vec<UseItem> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let module = Module::new(src, &tokens, &pairs, module_body(src, &tokens, &pairs, offset));
+    module.items.iter().filter_map(|&item| module.use_item(item)).collect()
+}
+
+/// Where to add `use path;` to the innermost module of `src` containing
+/// `offset`, as a byte offset and the text to insert there. `None` if `path` is
+/// already imported, or there's nowhere to put it.
+///
+/// The import is added to the group of imports, separated by blank lines, with
+/// the same first segment as `path`, or to the last group, keeping it sorted.
+pub fn insert_use(src: &str, offset: usize, path: &str) -> Option<(usize, String)> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let module = Module::new(src, &tokens, &pairs, module_body(src, &tokens, &pairs, offset));
+    let groups = module.use_groups();
+    if groups.iter().flat_map(|g| g).any(|u| u.tree == path) {
+        return None;
+    }
+
+    if groups.is_empty() {
+        // Before the first item which isn't an inner attribute or `extern crate`.
+        let &(first, _) = module.items.iter().find(|&&item| !module.is_preamble(item))?;
+        let at = line_start(src, tokens[first].start);
+        if at < module.body_start() {
+            return None;
+        }
+        return Some((at, format!("{}use {};\n\n", indentation(src, at), path)));
+    }
+
+    let group = groups
+        .iter()
+        .find(|g| g.iter().any(|u| u.root() == path_root(path)))
+        .unwrap_or_else(|| groups.last().unwrap());
+    match group.iter().find(|u| u.tree.as_str() > path) {
+        Some(next) => {
+            let at = line_start(src, next.start);
+            Some((at, format!("{}use {};\n", indentation(src, at), path)))
+        }
+        None => {
+            let last = group.last().unwrap();
+            let indent = indentation(src, line_start(src, last.start));
+            let text = format!("{}use {};", indent, path);
+            match src[last.end..].find('\n') {
+                Some(newline) => Some((last.end + newline + 1, text + "\n")),
+                None => Some((src.len(), format!("\n{}", text))),
+            }
+        }
+    }
+}
+
+/// Merges imports from the same module, sorts them if `reorder`, and removes
+/// duplicates and the imports whose last segment is in one of the `unused`
+/// byte ranges, in each group of `use` declarations in `src`.
+///
+/// Returns the byte range to replace and the text to replace it with, or `None`
+/// if there's nothing to change. Groups with attributes or comments are left
+/// alone.
+pub fn organize_imports(
+    src: &str,
+    unused: &[(usize, usize)],
+    reorder: bool,
+) -> Option<(usize, usize, String)> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let mut edits = vec![];
+    for body in module_bodies(src, &tokens, &pairs) {
+        let module = Module::new(src, &tokens, &pairs, body);
+        for group in module.use_groups() {
+            edits.extend(module.organize_group(&group, unused, reorder));
+        }
+    }
+    if edits.is_empty() {
+        return None;
+    }
+
+    // Combine the edits into one, modules nested in others are later in the
+    // list but their groups are still disjoint.
+    edits.sort_by_key(|&(start, _, _)| start);
+    let start = edits[0].0;
+    let end = edits[edits.len() - 1].1;
+    let mut text = String::new();
+    let mut pos = start;
+    for (edit_start, edit_end, edit_text) in edits {
+        text.push_str(&src[pos..edit_start]);
+        text.push_str(&edit_text);
+        pos = edit_end;
+    }
+    Some((start, end, text))
+}
+
+/// The byte offsets of the `*` of every glob import in `src`, e.g., `use foo::*;`
+/// or `use foo::{bar::*, baz};`.
+///
+/// With `skip_preludes`, globs importing a module named `prelude` are left out,
+/// and with `skip_test_modules`, `use super::*` in `#[cfg(test)]` modules.
+pub fn glob_imports(src: &str, skip_preludes: bool, skip_test_modules: bool) -> Vec<usize> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let mut result = vec![];
+    for i in 2..tokens.len() {
+        if !tokens[i].is_punct('*') || !tokens[i - 1].is_punct(':')
+            || !tokens[i - 2].is_punct(':')
+        {
+            continue;
+        }
+        let module = if i >= 3 && tokens[i - 3].kind == TokenKind::Ident {
+            tokens[i - 3].text(src)
+        } else {
+            ""
+        };
+        if skip_preludes && module == "prelude" {
+            continue;
+        }
+        if skip_test_modules && module == "super" && in_test_module(src, &tokens, &pairs, i) {
+            continue;
+        }
+        result.push(tokens[i].start);
+    }
+    result
+}
+
+/// Is the token at `i` in an inline module with a `#[cfg(test)]` attribute?
+fn in_test_module(src: &str, tokens: &[Token], pairs: &HashMap<usize, usize>, i: usize) -> bool {
+    let contains_token = |m: usize| {
+        m + 2 < tokens.len() && tokens[m].is_ident(src, "mod")
+            && tokens[m + 1].kind == TokenKind::Ident && tokens[m + 2].is_punct('{')
+            && pairs.get(&(m + 2)).map_or(false, |&close| close > i)
+    };
+    // The innermost module is the closest one before the token.
+    let mut j = match (0..i).rev().find(|&m| contains_token(m)) {
+        Some(m) => m,
+        None => return false,
+    };
+
+    // Walk back over the visibility, comments and attributes of the module.
+    while j > 0 {
+        let prev = &tokens[j - 1];
+        if prev.is_comment() || prev.is_ident(src, "pub") {
+            j -= 1;
+            continue;
+        }
+        let open = match pairs.get(&(j - 1)) {
+            Some(&open) if open < j - 1 => open,
+            _ => return false,
+        };
+        if prev.is_punct(')') && open > 0 && tokens[open - 1].is_ident(src, "pub") {
+            // `pub(crate)`, etc.
+            j = open - 1;
+        } else if prev.is_punct(']') && open > 0 && tokens[open - 1].is_punct('#') {
+            let attr: String = src[tokens[open].start..prev.end]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            if attr == "[cfg(test)]" {
+                return true;
+            }
+            j = open - 1;
+        } else {
+            return false;
+        }
+    }
+    false
+}
+
+/// The directory of the package containing `file`, the closest one with a
+/// `Cargo.toml`.
+pub fn package_dir(file: &Path) -> Option<&Path> {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d.join("Cargo.toml").is_file() {
+            return Some(d);
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn insert(src: &str, at: &str, path: &str) -> Option<String> {
+        let offset = src.find(at).unwrap();
+        insert_use(src, offset, path).map(|(offset, text)| {
+            let mut src = src.to_owned();
+            src.insert_str(offset, &text);
+            src
+        })
+    }
+
+    #[test]
+    fn test_use_items() {
+        let src = "//! Docs\n\
+                   use a::b;\n\
+                   #[cfg(test)]\n\
+                   pub(crate) use c::{\n    d,\n    e,\n};\n\
+                   fn f() { use g; }\n\
+                   struct S { x: u32 }\n\
+                   const X: S = S { x: 0 };\n\
+                   use h;\n";
+        let trees: Vec<_> = use_items(src, 0).into_iter().map(|u| u.tree).collect();
+        assert_eq!(trees, vec!["a::b", "c::{ d, e, }", "h"]);
+        let items = use_items(src, 0);
+        assert!(src[items[1].start..].starts_with("#[cfg(test)]"));
+        assert!(src[..items[1].end].ends_with("};"));
+    }
+
+    #[test]
+    fn test_insert_use_sorted() {
+        let src = "use std::fmt;\nuse std::io;\n\nuse foo::Bar;\nuse zed;\n\nfn f() { Baz }\n";
+        assert_eq!(
+            insert(src, "Baz", "std::collections::HashMap").unwrap(),
+            "use std::collections::HashMap;\nuse std::fmt;\nuse std::io;\n\n\
+             use foo::Bar;\nuse zed;\n\nfn f() { Baz }\n"
+        );
+        assert_eq!(
+            insert(src, "Baz", "std::path::Path").unwrap(),
+            "use std::fmt;\nuse std::io;\nuse std::path::Path;\n\n\
+             use foo::Bar;\nuse zed;\n\nfn f() { Baz }\n"
+        );
+        // No group with the same root, added to the last.
+        assert_eq!(
+            insert(src, "Baz", "bar::Baz").unwrap(),
+            "use std::fmt;\nuse std::io;\n\n\
+             use bar::Baz;\nuse foo::Bar;\nuse zed;\n\nfn f() { Baz }\n"
+        );
+        assert_eq!(insert(src, "Baz", "foo::Bar"), None);
+    }
+
+    #[test]
+    fn test_insert_use_no_imports() {
+        let src = "// Copyright\n\n#![feature(x)]\nextern crate foo;\n\n/// Docs\nfn f() { Bar }\n";
+        assert_eq!(
+            insert(src, "Bar", "foo::Bar").unwrap(),
+            "// Copyright\n\n#![feature(x)]\nextern crate foo;\n\n\
+             use foo::Bar;\n\n/// Docs\nfn f() { Bar }\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_use_inline_module() {
+        let src = "use a;\n\nmod test {\n    use super::*;\n\n    fn f() { Bar }\n}\n";
+        assert_eq!(
+            insert(src, "Bar", "foo::Bar").unwrap(),
+            "use a;\n\nmod test {\n    use foo::Bar;\n    use super::*;\n\n    fn f() { Bar }\n}\n"
+        );
+        let src = "mod test {\n    fn f() { Bar }\n}\n";
+        assert_eq!(
+            insert(src, "Bar", "foo::Bar").unwrap(),
+            "mod test {\n    use foo::Bar;\n\n    fn f() { Bar }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_glob_imports() {
+        let src = "use a::*;\n\
+                   use b::{c::*, d};\n\
+                   use std::io::prelude::*;\n\
+                   fn f(x: u32) -> u32 { x * 2 }\n\
+                   #[cfg(test)]\n\
+                   mod test {\n    use super::*;\n}\n\
+                   mod other {\n    use super::*;\n}\n";
+        let globs = |skip_preludes, skip_test_modules| -> Vec<&str> {
+            glob_imports(src, skip_preludes, skip_test_modules)
+                .into_iter()
+                .map(|offset| {
+                    let line_start = line_start(src, offset);
+                    src[line_start..].lines().next().unwrap().trim()
+                })
+                .collect()
+        };
+        assert_eq!(
+            globs(false, false),
+            vec![
+                "use a::*;",
+                "use b::{c::*, d};",
+                "use std::io::prelude::*;",
+                "use super::*;",
+                "use super::*;",
+            ]
+        );
+        assert_eq!(
+            globs(true, true),
+            vec!["use a::*;", "use b::{c::*, d};", "use super::*;"]
+        );
+    }
+
+    fn organize(src: &str, unused: &[&str], reorder: bool) -> String {
+        let unused: Vec<_> = unused
+            .iter()
+            .map(|u| {
+                let start = src.find(u).unwrap();
+                (start, start + u.len())
+            })
+            .collect();
+        match organize_imports(src, &unused, reorder) {
+            Some((start, end, text)) => format!("{}{}{}", &src[..start], text, &src[end..]),
+            None => src.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_organize_imports() {
+        let src = "use std::io;\nuse foo::{Bar, baz as qux};\nuse std::fmt;\nuse std::io;\n\
+                   pub use foo::Quux;\n\nfn f() {}\n";
+        assert_eq!(
+            organize(src, &[], true),
+            "pub use foo::Quux;\nuse foo::{Bar, baz as qux};\nuse std::{fmt, io};\n\nfn f() {}\n"
+        );
+        assert_eq!(
+            organize(src, &[], false),
+            "use std::{io, fmt};\nuse foo::{Bar, baz as qux};\npub use foo::Quux;\n\nfn f() {}\n"
+        );
+        assert_eq!(
+            organize(src, &["fmt;"], true),
+            "pub use foo::Quux;\nuse foo::{Bar, baz as qux};\nuse std::io;\n\nfn f() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_organize_imports_groups() {
+        // Groups are kept apart, and each inline module is organized.
+        let src = "use b;\nuse a;\n\nuse c::{self, d::*};\nuse c::e;\n\n\
+                   mod test {\n    use super::*;\n    use x::y::{z};\n\n    fn f() {}\n}\n";
+        assert_eq!(
+            organize(src, &[], true),
+            "use a;\nuse b;\n\nuse c::d::*;\nuse c::{self, e};\n\n\
+             mod test {\n    use super::*;\n    use x::y::z;\n\n    fn f() {}\n}\n"
+        );
+
+        // Groups with comments and attributes are left alone, unused groups are removed.
+        let src = "// Imports\nuse b;\n// More\nuse a;\n\n#[cfg(test)]\nuse d;\nuse c;\n\n\
+                   use e;\n\nfn f() {}\n";
+        assert_eq!(
+            organize(src, &["e;"], true),
+            "// Imports\nuse b;\n// More\nuse a;\n\n#[cfg(test)]\nuse d;\nuse c;\n\nfn f() {}\n"
+        );
+        let src = "use a;\n";
+        assert_eq!(organize(src, &[], true), src);
+    }
+}
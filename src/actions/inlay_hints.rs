@@ -0,0 +1,438 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Inlay hints, for `textDocument/inlayHint`.
+//!
+//! The places to show hints are found in the current text of a file: bindings
+//! in `let` statements and closure parameters without a type annotation, and
+//! the arguments of calls. The hints themselves come from save-analysis, so
+//! they're only as accurate as the last build.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::run::LineIndex;
+use actions::signature;
+use analysis::{AnalysisHost, DefKind};
+use lsp_data::{ls_util, InlayHint, InlayHintKind};
+use span::{self, ZeroIndexed};
+use Span;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Finds the hints to show for the bindings and calls in `range` of `text`, the
+/// contents of `file`.
+pub fn inlay_hints(
+    analysis: &AnalysisHost,
+    file: &Path,
+    text: &str,
+    range: span::Range<ZeroIndexed>,
+) -> Vec<InlayHint> {
+    let tokens = lex::tokenize(text);
+    let line_index = LineIndex::new(text);
+    let start = line_index.position_to_offset(range.start()).unwrap_or(text.len());
+    let end = line_index.position_to_offset(range.end()).unwrap_or(text.len());
+
+    let position = |offset| ls_util::rls_to_position(line_index.offset_to_position(offset));
+    let span = |t: &Token| {
+        Span::from_positions(
+            line_index.offset_to_position(t.start),
+            line_index.offset_to_position(t.end),
+            file.to_owned(),
+        )
+    };
+
+    let mut hints = vec![];
+    for site in hint_sites(text, &tokens, start, end) {
+        match site {
+            HintSite::Binding(i) => {
+                let ty = match analysis.show_type(&span(&tokens[i])) {
+                    Ok(ty) => ty,
+                    Err(_) => continue,
+                };
+                if ty.is_empty() {
+                    continue;
+                }
+                hints.push(InlayHint {
+                    position: position(tokens[i].end),
+                    label: format!(": {}", ty),
+                    kind: Some(InlayHintKind::Type),
+                    padding_left: None,
+                    padding_right: None,
+                });
+            }
+            HintSite::Call { callee, is_method, args } => {
+                let def = match analysis
+                    .id(&span(&tokens[callee]))
+                    .and_then(|id| analysis.get_def(id))
+                {
+                    Ok(def) => def,
+                    Err(_) => continue,
+                };
+                if def.kind != DefKind::Function && def.kind != DefKind::Method {
+                    continue;
+                }
+                let mut params = match signature::parse_signature(&def.name, &def.value) {
+                    Some(sig) => sig.params,
+                    None => continue,
+                };
+                if is_method && params.first().map_or(false, |p| signature::is_self_param(p)) {
+                    params.remove(0);
+                }
+
+                for (param, &(first, last)) in params.iter().zip(args.iter()) {
+                    let name = match param_name(param) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let arg = &text[tokens[first].start..tokens[last].end];
+                    if is_obvious_arg(name, arg) {
+                        continue;
+                    }
+                    hints.push(InlayHint {
+                        position: position(tokens[first].start),
+                        label: format!("{}:", name),
+                        kind: Some(InlayHintKind::Parameter),
+                        padding_left: None,
+                        padding_right: Some(true),
+                    });
+                }
+            }
+        }
+    }
+    hints
+}
+
+/// A place in the source which may get hints.
+#[derive(Debug, PartialEq)]
+enum HintSite {
+    /// The index of a binding's name, to show its type after.
+    Binding(usize),
+    /// A call, to show parameter names before its arguments.
+    Call {
+        /// The index of the name of the function or method called.
+        callee: usize,
+        is_method: bool,
+        /// The indices of the first and last tokens of each argument.
+        args: Vec<(usize, usize)>,
+    },
+}
+
+// Finds the places which may get hints in the byte range `start..end` of `src`.
+fn hint_sites(src: &str, tokens: &[Token], start: usize, end: usize) -> Vec<HintSite> {
+    let finder = SiteFinder {
+        src,
+        tokens,
+        pairs: lex::delimiter_pairs(tokens),
+    };
+
+    let mut sites = vec![];
+    for (i, t) in tokens.iter().enumerate() {
+        if t.start < start || t.start >= end {
+            continue;
+        }
+        if t.is_ident(src, "let") {
+            sites.extend(finder.let_bindings(i).into_iter().map(HintSite::Binding));
+        } else if t.is_punct('|') && finder.starts_closure(i) {
+            sites.extend(finder.closure_params(i).into_iter().map(HintSite::Binding));
+        } else if t.is_punct('(') {
+            sites.extend(finder.call(i));
+        }
+    }
+    sites
+}
+
+struct SiteFinder<'a> {
+    src: &'a str,
+    tokens: &'a [Token],
+    pairs: HashMap<usize, usize>,
+}
+
+impl<'a> SiteFinder<'a> {
+    fn text(&self, i: usize) -> &'a str {
+        self.tokens[i].text(self.src)
+    }
+
+    // Are the tokens at `i` and `i + 1` the characters `a` and `b` with no
+    // space between them?
+    fn joined(&self, i: usize, a: char, b: char) -> bool {
+        let tokens = self.tokens;
+        i + 1 < tokens.len() && tokens[i].is_punct(a) && tokens[i + 1].is_punct(b)
+            && tokens[i].end == tokens[i + 1].start
+    }
+
+    // Is the token at `i` a `:` introducing a type, rather than part of `::`?
+    fn is_type_colon(&self, i: usize) -> bool {
+        self.tokens[i].is_punct(':') && !self.joined(i, ':', ':')
+            && !(i > 0 && self.joined(i - 1, ':', ':'))
+    }
+
+    // The index of the first token from `start` which satisfies `f`, skipping
+    // over delimited groups.
+    fn skip_to<F: Fn(usize) -> bool>(&self, start: usize, f: F) -> Option<usize> {
+        let mut i = start;
+        while i < self.tokens.len() {
+            if f(i) {
+                return Some(i);
+            }
+            match self.tokens[i].kind {
+                TokenKind::Punct('(') | TokenKind::Punct('[') | TokenKind::Punct('{') => {
+                    i = *self.pairs.get(&i)? + 1;
+                }
+                TokenKind::Punct(')') | TokenKind::Punct(']') | TokenKind::Punct('}') => {
+                    return None;
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    // The bindings of the `let` at `i`, unless its type is written out or is
+    // obvious from its initializer.
+    fn let_bindings(&self, i: usize) -> Vec<usize> {
+        let tokens = self.tokens;
+        let end = match self.skip_to(i + 1, |j| {
+            tokens[j].is_punct('=') || tokens[j].is_punct(';') || self.is_type_colon(j)
+        }) {
+            Some(end) => end,
+            None => return vec![],
+        };
+        if self.is_type_colon(end) || tokens[end].is_punct('=') && self.is_obvious_init(end + 1) {
+            return vec![];
+        }
+        self.bindings(i + 1, end)
+    }
+
+    // Is the `|` at `i` the start of a closure's parameters, rather than a
+    // binary or?
+    fn starts_closure(&self, i: usize) -> bool {
+        if i == 0 {
+            return true;
+        }
+        let prev = &self.tokens[i - 1];
+        match prev.kind {
+            TokenKind::Punct(c) => {
+                "(,={[;".contains(c) || c == '>' && i >= 2 && self.joined(i - 2, '=', '>')
+            }
+            TokenKind::Ident => ["move", "return"].contains(&prev.text(self.src)),
+            _ => false,
+        }
+    }
+
+    // The parameters without a type annotation of the closure whose parameter
+    // list starts at `open`.
+    fn closure_params(&self, open: usize) -> Vec<usize> {
+        let tokens = self.tokens;
+        let close = match self.skip_to(open + 1, |j| tokens[j].is_punct('|')) {
+            Some(close) if close > open + 1 => close,
+            _ => return vec![],
+        };
+
+        let mut result = vec![];
+        let mut start = open + 1;
+        while start < close {
+            let end = self.skip_to(start, |j| j == close || tokens[j].is_punct(','))
+                .unwrap_or(close);
+            if self.skip_to(start, |j| j == end || self.is_type_colon(j)) == Some(end) {
+                result.extend(self.bindings(start, end));
+            }
+            start = end + 1;
+        }
+        result
+    }
+
+    // The names bound by the pattern in `start..end`.
+    fn bindings(&self, start: usize, end: usize) -> Vec<usize> {
+        let tokens = self.tokens;
+        (start..end)
+            .filter(|&i| {
+                let text = self.text(i);
+                let next = if i + 1 < end { Some(&tokens[i + 1]) } else { None };
+                tokens[i].kind == TokenKind::Ident
+                    && text.starts_with(|c: char| c.is_lowercase() || c == '_') && text != "_"
+                    && !["mut", "ref", "box"].contains(&text)
+                    // Paths and tuple struct or struct patterns.
+                    && !(i >= 2 && self.joined(i - 2, ':', ':'))
+                    && !(i > 0 && tokens[i - 1].is_punct('.'))
+                    && next.map_or(true, |t| {
+                        !t.is_punct('(') && !t.is_punct('{') && !t.is_punct(':')
+                            && !t.is_punct('!')
+                    })
+            })
+            .collect()
+    }
+
+    // Is the type of the expression starting at `start` obvious, because it's a
+    // literal or a constructor, e.g., `Foo { .. }` or `Foo::new(..)`?
+    fn is_obvious_init(&self, start: usize) -> bool {
+        let tokens = self.tokens;
+        let ends_statement = |i: usize| i < tokens.len() && tokens[i].is_punct(';');
+
+        let mut i = start;
+        if i < tokens.len() && tokens[i].is_punct('-') {
+            i += 1;
+        }
+        if i >= tokens.len() {
+            return false;
+        }
+        if tokens[i].kind == TokenKind::Literal || ["true", "false"].contains(&self.text(i)) {
+            return ends_statement(i + 1);
+        }
+
+        // A path, followed by arguments or fields.
+        let mut segments = vec![];
+        while i < tokens.len() && tokens[i].kind == TokenKind::Ident {
+            segments.push(self.text(i));
+            if !self.joined(i + 1, ':', ':') {
+                break;
+            }
+            i += 3;
+        }
+        let close = match self.pairs.get(&(i + 1)) {
+            Some(&close) if tokens[i + 1].is_punct('(') || tokens[i + 1].is_punct('{') => close,
+            _ => return false,
+        };
+        if !ends_statement(close + 1) {
+            return false;
+        }
+        let is_type = |s: &str| s.starts_with(char::is_uppercase);
+        match segments.len() {
+            0 => false,
+            1 => is_type(segments[0]),
+            n => {
+                let (ty, function) = (segments[n - 2], segments[n - 1]);
+                is_type(function)
+                    || is_type(ty)
+                        && (["new", "default", "from"].contains(&function)
+                            || function.starts_with("new_") || function.starts_with("with_"))
+            }
+        }
+    }
+
+    // The call whose arguments start at `open`.
+    fn call(&self, open: usize) -> Option<HintSite> {
+        let tokens = self.tokens;
+        let close = *self.pairs.get(&open)?;
+        let callee = open.checked_sub(1)?;
+        if tokens[callee].kind != TokenKind::Ident || tokens[callee].end != tokens[open].start {
+            return None;
+        }
+        if callee > 0 && tokens[callee - 1].is_ident(self.src, "fn") {
+            return None;
+        }
+
+        let mut args = vec![];
+        let mut start = open + 1;
+        while start < close {
+            let end = self.skip_to(start, |j| j == close || tokens[j].is_punct(','))
+                .unwrap_or(close);
+            if end > start {
+                args.push((start, end - 1));
+            }
+            start = end + 1;
+        }
+        if args.is_empty() {
+            return None;
+        }
+        Some(HintSite::Call {
+            callee,
+            is_method: callee > 0 && tokens[callee - 1].is_punct('.'),
+            args,
+        })
+    }
+}
+
+// The name of a parameter from a signature, e.g., `x` from `mut x: u32`.
+fn param_name(param: &str) -> Option<&str> {
+    let mut name = param.splitn(2, ':').next()?.trim();
+    if name.starts_with("mut ") {
+        name = name["mut ".len()..].trim_left();
+    }
+    if name.is_empty() || name.starts_with('_') || name == "self"
+        || !name.chars().all(|c| c == '_' || c.is_alphanumeric())
+    {
+        return None;
+    }
+    Some(name)
+}
+
+// Does the argument `arg` already make the name of its parameter clear?
+fn is_obvious_arg(name: &str, arg: &str) -> bool {
+    let arg = arg.trim_left_matches(|c: char| c == '&' || c == '*').trim_left();
+    let arg = if arg.starts_with("mut ") {
+        &arg["mut ".len()..]
+    } else {
+        arg
+    };
+    arg == name || arg.ends_with(&format!(".{}", name)) || arg.ends_with(&format!(".{}()", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Describes the hint sites in `src` as text.
+    fn sites(src: &str) -> Vec<String> {
+        let tokens = lex::tokenize(src);
+        hint_sites(src, &tokens, 0, src.len())
+            .into_iter()
+            .map(|site| match site {
+                HintSite::Binding(i) => tokens[i].text(src).to_owned(),
+                HintSite::Call { callee, is_method, args } => {
+                    let args: Vec<_> = args
+                        .iter()
+                        .map(|&(first, last)| &src[tokens[first].start..tokens[last].end])
+                        .collect();
+                    let dot = if is_method { "." } else { "" };
+                    format!("{}{}({})", dot, tokens[callee].text(src), args.join("|"))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_let_bindings() {
+        assert_eq!(
+            sites(
+                "let a = foo; let (b, mut c) = bar; let d: u32 = 0; let e = 1; \
+                 let Some(f) = g else { return }; let h = Foo::new(); let i = Foo { x }; \
+                 let j = Foo::new().bar; let k = foo::Bar; let Point { x: l, .. } = p;"
+            ),
+            vec!["a", "b", "c", "f", "Some(f)", "j", "k", "l"]
+        );
+    }
+
+    #[test]
+    fn test_closure_params() {
+        assert_eq!(
+            sites("let a: F = |x, (y, z), w: u32| x | y; let b: F = move || 0;"),
+            vec!["x", "y", "z"]
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(
+            sites("fn f(x: u32) { foo(a, b.c(), (d, e)); x.bar(1); baz(); qux!(2); }"),
+            vec!["foo(a|b.c()|(d, e))", ".bar(1)"]
+        );
+    }
+
+    #[test]
+    fn test_param_name() {
+        assert_eq!(param_name("x: u32"), Some("x"));
+        assert_eq!(param_name("mut buf: &mut [u8]"), Some("buf"));
+        assert_eq!(param_name("_unused: u8"), None);
+        assert_eq!(param_name("(a, b): (u8, u8)"), None);
+        assert!(is_obvious_arg("len", "&self.len"));
+        assert!(is_obvious_arg("len", "len"));
+        assert!(!is_obvious_arg("len", "5"));
+    }
+}
@@ -0,0 +1,733 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Inlining a local variable into its uses, or a function into a call. What can
+//! be inlined comes from the text, and which identifiers refer to the variable,
+//! or to the function's parameters, from the analysis data.
+
+use actions::extract::{identifiers, reindent};
+use actions::imports::{indentation, line_start};
+use actions::lex::{self, Token, TokenKind};
+use actions::rename::is_keyword;
+use actions::requests::format_statements;
+use actions::run::LineIndex;
+use actions::InitActionContext;
+use analysis::DefKind;
+use Span;
+
+use std::collections::HashMap;
+
+/// A `let` statement binding a single variable.
+#[derive(Debug, PartialEq)]
+pub struct Binding {
+    /// The byte offset of the `let` keyword.
+    pub start: usize,
+    /// The byte offsets of the initializer.
+    pub initializer: (usize, usize),
+    /// The byte offsets of the text to remove with the statement, including
+    /// its line if it's on a line of its own.
+    pub removal: (usize, usize),
+}
+
+/// The `let` statement declaring the variable whose name starts at `name`, if
+/// the variable is all it binds, it isn't `mut` and it has an initializer.
+pub fn let_binding(src: &str, name: usize) -> Option<Binding> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let i = tokens.iter().position(|t| t.start == name)?;
+    if i == 0 || !tokens[i - 1].is_ident(src, "let") {
+        return None;
+    }
+
+    // Skip the type, up to `=`.
+    let mut eq = i + 1;
+    if tokens.get(eq)?.is_punct(':') {
+        let mut angle_depth = 0;
+        loop {
+            eq += 1;
+            match tokens.get(eq)?.kind {
+                TokenKind::Punct('<') => angle_depth += 1,
+                TokenKind::Punct('>') if !tokens[eq - 1].is_punct('-') => angle_depth -= 1,
+                TokenKind::Punct('=') if angle_depth == 0 => break,
+                TokenKind::Punct(';') => return None,
+                _ => eq = closing(&pairs, eq).unwrap_or(eq),
+            }
+        }
+    }
+    if !tokens[eq].is_punct('=') || tokens.get(eq + 1).map_or(true, |t| t.is_punct('=')) {
+        return None;
+    }
+
+    let mut semi = eq + 1;
+    while !tokens.get(semi)?.is_punct(';') {
+        semi = closing(&pairs, semi).unwrap_or(semi) + 1;
+    }
+    if semi == eq + 1 {
+        return None;
+    }
+
+    let start = tokens[i - 1].start;
+    let end = tokens[semi].end;
+    let line = line_start(src, start);
+    let rest = &src[end..];
+    let line_end = rest.find('\n').map_or(src.len(), |n| end + n + 1);
+    let removal = if src[line..start].trim().is_empty() && src[end..line_end].trim().is_empty() {
+        (line, line_end)
+    } else {
+        (start, end + rest.len() - rest.trim_left_matches(' ').len())
+    };
+    Some(Binding {
+        start,
+        initializer: (tokens[eq + 1].start, tokens[semi - 1].end),
+        removal,
+    })
+}
+
+fn closing(pairs: &HashMap<usize, usize>, i: usize) -> Option<usize> {
+    match pairs.get(&i) {
+        Some(&close) if close > i => Some(close),
+        _ => None,
+    }
+}
+
+/// Whether evaluating the expression between `start` and `end` may have side
+/// effects, so it can't be evaluated more than once, or not at all. Any call,
+/// macro, assignment, loop or `?` may.
+pub fn has_side_effects(src: &str, start: usize, end: usize) -> bool {
+    let tokens: Vec<_> = lex::tokenize(src)
+        .into_iter()
+        .filter(|t| start <= t.start && t.end <= end && !t.is_comment())
+        .collect();
+    tokens.iter().enumerate().any(|(i, t)| {
+        let prev = if i > 0 { tokens.get(i - 1) } else { None };
+        let next = tokens.get(i + 1);
+        match t.kind {
+            TokenKind::Punct('(') => prev.map_or(false, |p| match p.kind {
+                TokenKind::Ident => !is_keyword(p.text(src)),
+                TokenKind::Punct(c) => ")]>".contains(c),
+                _ => false,
+            }),
+            TokenKind::Punct('!') => {
+                prev.map_or(false, |p| p.kind == TokenKind::Ident)
+                    && next.map_or(false, |n| n.is_punct('(') || n.is_punct('[') || n.is_punct('{'))
+            }
+            TokenKind::Punct('?') => true,
+            // `=` or, e.g., `+=`, but not `==`, `<=` or `=>`.
+            TokenKind::Punct('=') => {
+                let is_comparison = prev.map_or(false, |p| "=!<>".contains(punct_char(p)))
+                    || next.map_or(false, |n| n.is_punct('=') || n.is_punct('>'));
+                !is_comparison
+            }
+            TokenKind::Ident => ["loop", "while", "for"].iter().any(|k| t.is_ident(src, k)),
+            _ => false,
+        }
+    })
+}
+
+/// Whether the token at `offset` is in a loop, or a closure, which starts
+/// after `start`, so it may be evaluated any number of times.
+pub fn is_in_loop_or_closure(src: &str, start: usize, offset: usize) -> bool {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let target = match tokens.iter().position(|t| t.start == offset) {
+        Some(target) => target,
+        None => return false,
+    };
+    let next = |i: usize| closing(&pairs, i).map_or(i + 1, |close| close + 1);
+    // The index of the first token after the expression starting at `i`.
+    let expr_end = |mut i: usize| {
+        while i < tokens.len() && !",;)]}".contains(punct_char(&tokens[i])) {
+            i = next(i);
+        }
+        i
+    };
+    // Whether `|` after the token at `i` is an operator, rather than starts a
+    // closure.
+    let is_operator = |i: usize| match tokens[i].kind {
+        TokenKind::Ident => !is_keyword(tokens[i].text(src)),
+        TokenKind::Literal => true,
+        TokenKind::Punct(c) => ")]}|".contains(c),
+        _ => false,
+    };
+
+    (0..target).filter(|&i| tokens[i].start >= start).any(|i| {
+        let t = &tokens[i];
+        let end = if ["loop", "while", "for"].iter().any(|k| t.is_ident(src, k)) {
+            let mut body = i + 1;
+            while body < tokens.len() && !tokens[body].is_punct('{') {
+                body = next(body);
+            }
+            closing(&pairs, body)
+        } else if t.is_punct('|') && (i == 0 || !is_operator(i - 1)) {
+            let mut params_end = i + 1;
+            while params_end < tokens.len() && !tokens[params_end].is_punct('|') {
+                params_end = next(params_end);
+            }
+            if params_end < tokens.len() {
+                Some(expr_end(params_end + 1))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        end.map_or(false, |end| target < end)
+    })
+}
+
+fn punct_char(t: &Token) -> char {
+    match t.kind {
+        TokenKind::Punct(c) => c,
+        _ => ' ',
+    }
+}
+
+/// The text replacing the expression between `start` and `end`, e.g., a use of
+/// a variable, with `expr`. `expr` is parenthesized unless it binds tighter
+/// than any operator, e.g., a path or call, or the expression is delimited,
+/// e.g., an argument. A field init shorthand, as in `Foo { x }`, keeps the
+/// field's name.
+pub fn replacement(src: &str, start: usize, end: usize, expr: &str) -> String {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let (i, last) = match (
+        tokens.iter().position(|t| t.start == start),
+        tokens.iter().position(|t| t.end == end),
+    ) {
+        (Some(i), Some(last)) if i <= last => (i, last),
+        _ => return format!("({})", expr),
+    };
+    let prev = if i > 0 { punct_char(&tokens[i - 1]) } else { ' ' };
+    let before_prev = if i > 1 { punct_char(&tokens[i - 2]) } else { ' ' };
+    let next = tokens.get(last + 1).map_or(' ', punct_char);
+
+    if i == last && "{,".contains(prev) && "},".contains(next) {
+        let open = (0..i)
+            .rev()
+            .find(|&j| tokens[j].is_punct('{') && closing(&pairs, j).map_or(false, |c| c > i));
+        if open.map_or(false, |open| is_struct_literal(src, &tokens, &pairs, open)) {
+            return format!("{}: {}", &src[start..end], expr);
+        }
+    }
+
+    // E.g., an argument, an initializer or a `match` arm's expression.
+    let after_assignment = prev == '=' && !"=!<>+-*/%&|^".contains(before_prev);
+    let after_arrow = prev == '>' && before_prev == '=';
+    let is_delimited = ("([{,;".contains(prev) || after_assignment || after_arrow)
+        && ")]},;".contains(next);
+    if is_delimited || is_atomic(expr) {
+        expr.to_owned()
+    } else {
+        format!("({})", expr)
+    }
+}
+
+/// Whether the braces opened at `open` are a struct expression, rather than a
+/// block.
+fn is_struct_literal(
+    src: &str,
+    tokens: &[Token],
+    pairs: &HashMap<usize, usize>,
+    open: usize,
+) -> bool {
+    let prev = match open.checked_sub(1).and_then(|i| tokens.get(i)) {
+        Some(prev) => prev,
+        None => return false,
+    };
+    if prev.kind != TokenKind::Ident || is_keyword(prev.text(src)) {
+        return false;
+    }
+    let mut i = open;
+    while i > 0 {
+        i -= 1;
+        let t = &tokens[i];
+        if t.is_punct(';') || t.is_punct('{') || t.is_punct('}') {
+            break;
+        }
+        if t.is_punct(')') || t.is_punct(']') {
+            i = pairs.get(&i).cloned().unwrap_or(i);
+        } else if ["if", "while", "for", "match", "fn"].iter().any(|k| t.is_ident(src, k)) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether `expr` binds tighter than any operator, so needs no parentheses
+/// wherever it's used, e.g., `x`, `1`, `foo::bar(x)[0].baz`.
+fn is_atomic(expr: &str) -> bool {
+    let tokens = lex::tokenize(expr);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let mut i = 0;
+    while i < tokens.len() {
+        let t = &tokens[i];
+        let is_atom = match t.kind {
+            TokenKind::Ident => {
+                let text = t.text(expr);
+                !is_keyword(text)
+                    || ["self", "Self", "super", "crate", "true", "false"].contains(&text)
+            }
+            TokenKind::Literal => true,
+            TokenKind::Punct('.') | TokenKind::Punct(':') => true,
+            // A call, index, tuple or parenthesized expression.
+            TokenKind::Punct('(') | TokenKind::Punct('[') => true,
+            // A macro.
+            TokenKind::Punct('!') => i > 0 && tokens.get(i + 1).map_or(false, |t| {
+                t.is_punct('(') || t.is_punct('[') || t.is_punct('{')
+            }),
+            _ => t.is_comment(),
+        };
+        if !is_atom {
+            return false;
+        }
+        i = closing(&pairs, i).unwrap_or(i) + 1;
+    }
+    !tokens.is_empty()
+}
+
+/// A function simple enough to be inlined. It isn't generic, its parameters
+/// are single identifiers, not `self`, and its body doesn't return early or
+/// refer to `self` or `Self`.
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    /// The byte offsets of the parameters' names.
+    pub params: Vec<(usize, usize)>,
+    /// The byte offsets of the body, between its braces, without surrounding
+    /// whitespace.
+    pub body: (usize, usize),
+    /// Whether the body is a single expression, rather than statements.
+    pub is_expression: bool,
+}
+
+/// The function whose name starts at `name`, if it is simple enough to be
+/// inlined.
+pub fn function(src: &str, name: usize) -> Option<Function> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let i = tokens.iter().position(|t| t.start == name)?;
+    if i == 0 || !tokens[i - 1].is_ident(src, "fn") || !tokens.get(i + 1)?.is_punct('(') {
+        return None;
+    }
+
+    let params_end = closing(&pairs, i + 1)?;
+    let mut params = vec![];
+    let mut param_start = true;
+    let mut j = i + 2;
+    while j < params_end {
+        let t = &tokens[j];
+        if param_start {
+            let is_name = t.kind == TokenKind::Ident && !is_keyword(t.text(src))
+                && tokens[j + 1].is_punct(':')
+                && !tokens[j + 2].is_punct(':');
+            if !is_name {
+                return None;
+            }
+            params.push((t.start, t.end));
+        }
+        param_start = t.is_punct(',');
+        j = closing(&pairs, j).unwrap_or(j) + 1;
+    }
+
+    let mut open = params_end + 1;
+    while !tokens.get(open)?.is_punct('{') {
+        if tokens[open].is_punct(';') {
+            return None;
+        }
+        open = closing(&pairs, open).unwrap_or(open) + 1;
+    }
+    let close = closing(&pairs, open)?;
+    let body = &tokens[open + 1..close];
+    let leaves = body.iter().any(|t| {
+        t.is_punct('?') || ["return", "self", "Self"].iter().any(|k| t.is_ident(src, k))
+    });
+    if leaves || body.is_empty() {
+        return None;
+    }
+
+    let mut is_expression = true;
+    let mut j = open + 1;
+    while j < close {
+        if tokens[j].is_punct(';') || tokens[j].is_ident(src, "let") {
+            is_expression = false;
+        }
+        j = closing(&pairs, j).unwrap_or(j) + 1;
+    }
+    Some(Function {
+        params,
+        body: (body[0].start, body[body.len() - 1].end),
+        is_expression,
+    })
+}
+
+/// A call of a function by its path, e.g., `foo::bar(x, 1)`.
+#[derive(Debug, PartialEq)]
+pub struct Call {
+    /// The byte offsets of the call, from its path to its closing parenthesis.
+    pub start: usize,
+    pub end: usize,
+    /// The byte offsets of the arguments, without surrounding whitespace.
+    pub args: Vec<(usize, usize)>,
+}
+
+/// The call of the function whose name, as the last segment of a path, starts
+/// at `name`. Method calls aren't included.
+pub fn call(src: &str, name: usize) -> Option<Call> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let i = tokens.iter().position(|t| t.start == name)?;
+    if tokens[i].kind != TokenKind::Ident || !tokens.get(i + 1)?.is_punct('(') {
+        return None;
+    }
+    let close = closing(&pairs, i + 1)?;
+
+    // The path's first segment.
+    let mut first = i;
+    while first >= 3
+        && tokens[first - 1].is_punct(':')
+        && tokens[first - 2].is_punct(':')
+        && tokens[first - 3].kind == TokenKind::Ident
+    {
+        first -= 3;
+    }
+    if first > 0 && tokens[first - 1].is_punct('.') {
+        return None;
+    }
+
+    let mut args = vec![];
+    let mut arg_start = i + 2;
+    let mut j = i + 2;
+    while j <= close {
+        if j == close || tokens[j].is_punct(',') {
+            if j > arg_start {
+                args.push((tokens[arg_start].start, tokens[j - 1].end));
+            }
+            arg_start = j + 1;
+        }
+        j = if j == close {
+            j + 1
+        } else {
+            closing(&pairs, j).unwrap_or(j) + 1
+        };
+    }
+    Some(Call {
+        start: tokens[first].start,
+        end: tokens[close].end,
+        args,
+    })
+}
+
+/// The byte offsets of the references to the definition at `span` in `text`,
+/// the contents of its file, excluding the definition itself.
+fn local_refs(
+    ctx: &InitActionContext,
+    span: &Span,
+    text: &str,
+) -> Result<Vec<(usize, usize)>, String> {
+    let line_index = LineIndex::new(text);
+    let refs = ctx.analysis
+        .find_all_refs(span, false, false)
+        .map_err(|_| "Failed to find references".to_owned())?;
+    let mut offsets = vec![];
+    for r in refs.iter().filter(|r| r.file == span.file && r.range != span.range) {
+        let start = line_index.position_to_offset(r.range.start());
+        let end = line_index.position_to_offset(r.range.end());
+        match (start, end) {
+            (Some(start), Some(end)) if start < end && end <= text.len() => {
+                offsets.push((start, end))
+            }
+            _ => return Err("Failed to find references".to_owned()),
+        }
+    }
+    offsets.sort();
+    offsets.dedup();
+    Ok(offsets)
+}
+
+/// The edits inlining the local variable at `span` into each of its uses, and
+/// removing its declaration. Its initializer is only duplicated, moved into a
+/// loop or closure, or dropped if it has no side effects; if it's unused, an
+/// initializer which may have side effects is kept as a statement.
+pub fn variable_edits(
+    ctx: &InitActionContext,
+    span: &Span,
+    text: &str,
+) -> Result<Vec<(usize, usize, String)>, String> {
+    let analysis = &ctx.analysis;
+    let def = analysis
+        .id(span)
+        .and_then(|id| analysis.get_def(id))
+        .map_err(|_| "No variable found".to_owned())?;
+    if def.kind != DefKind::Local || def.span.file != span.file {
+        return Err(format!("`{}` isn't a local variable", def.name));
+    }
+
+    let binding = LineIndex::new(text)
+        .position_to_offset(def.span.range.start())
+        .and_then(|decl| let_binding(text, decl))
+        .ok_or_else(|| {
+            format!("Cannot inline `{}`, it isn't declared alone by an immutable `let`", def.name)
+        })?;
+    let uses = local_refs(ctx, &def.span, text)?;
+    // References from macros don't always span the name itself.
+    if uses.iter().any(|&(start, end)| text.get(start..end) != Some(&def.name[..])) {
+        return Err(format!("Cannot inline `{}`, it is used in a macro", def.name));
+    }
+    let (init_start, init_end) = binding.initializer;
+    if has_side_effects(text, init_start, init_end) {
+        if uses.is_empty() {
+            return Ok(vec![(binding.start, init_start, String::new())]);
+        }
+        if uses.len() > 1 {
+            return Err(format!(
+                "Cannot inline `{}` into more than one use, its initializer may have side effects",
+                def.name
+            ));
+        }
+        if is_in_loop_or_closure(text, binding.removal.1, uses[0].0) {
+            return Err(format!(
+                "Cannot inline `{}` into a loop or closure, its initializer may have side effects",
+                def.name
+            ));
+        }
+    }
+
+    let initializer = &text[init_start..init_end];
+    let mut edits: Vec<_> = uses
+        .into_iter()
+        .map(|(start, end)| (start, end, replacement(text, start, end, initializer)))
+        .collect();
+    edits.push((binding.removal.0, binding.removal.1, String::new()));
+    Ok(edits)
+}
+
+/// The edits inlining the function called at `span`, which must be defined in
+/// the same file, into the call. Arguments which may have side effects are
+/// bound to the parameters before the function's body, others replace the
+/// parameters' uses.
+pub fn function_edits(
+    ctx: &InitActionContext,
+    span: &Span,
+    text: &str,
+) -> Result<Vec<(usize, usize, String)>, String> {
+    let analysis = &ctx.analysis;
+    let line_index = LineIndex::new(text);
+    let call = line_index
+        .position_to_offset(span.range.start())
+        .and_then(|offset| call(text, offset))
+        .ok_or_else(|| "No function call found".to_owned())?;
+    let def = analysis
+        .id(span)
+        .and_then(|id| analysis.get_def(id))
+        .map_err(|_| "No function found".to_owned())?;
+    if def.kind != DefKind::Function && def.kind != DefKind::Method {
+        return Err(format!("`{}` isn't a function", def.name));
+    }
+    if def.span.file != span.file {
+        return Err(format!("Cannot inline `{}`, it is defined in another file", def.name));
+    }
+    let function = line_index
+        .position_to_offset(def.span.range.start())
+        .and_then(|name| function(text, name))
+        .ok_or_else(|| {
+            format!(
+                "Cannot inline `{}`, it is generic, has a `self` or pattern parameter, \
+                 or returns early",
+                def.name
+            )
+        })?;
+    let (body_start, body_end) = function.body;
+    if body_start <= call.start && call.end <= body_end {
+        return Err(format!("Cannot inline `{}` into itself", def.name));
+    }
+    if function.params.len() != call.args.len() {
+        return Err(format!("Cannot inline `{}`, the call has the wrong arguments", def.name));
+    }
+
+    let mut param_uses = vec![];
+    for &(start, end) in &function.params {
+        let param = Span::from_positions(
+            line_index.offset_to_position(start),
+            line_index.offset_to_position(end),
+            span.file.clone(),
+        );
+        param_uses.push(local_refs(ctx, &param, text)?);
+    }
+    // The names in the body, besides the parameters, which could capture an
+    // argument's.
+    let body_names: Vec<_> = identifiers(text, body_start, body_end)
+        .into_iter()
+        .filter(|name| !param_uses.iter().any(|uses| uses.contains(name)))
+        .map(|(start, end)| &text[start..end])
+        .collect();
+
+    let mut substitutions = vec![];
+    let mut bound_params = vec![];
+    let mut bound_args = vec![];
+    for ((&(start, end), uses), &(arg_start, arg_end)) in
+        function.params.iter().zip(param_uses).zip(&call.args)
+    {
+        let arg = &text[arg_start..arg_end];
+        let is_captured = identifiers(text, arg_start, arg_end)
+            .into_iter()
+            .any(|(s, e)| body_names.contains(&&text[s..e]));
+        if is_captured || has_side_effects(text, arg_start, arg_end) {
+            bound_params.push(&text[start..end]);
+            bound_args.push(arg);
+            continue;
+        }
+        for (use_start, use_end) in uses {
+            if use_start < body_start || body_end < use_end {
+                continue;
+            }
+            let new_text = replacement(text, use_start, use_end, arg);
+            substitutions.push((use_start, use_end, new_text));
+        }
+    }
+
+    substitutions.sort();
+    let mut body = String::new();
+    let mut copied = body_start;
+    for (start, end, new_text) in substitutions {
+        body.push_str(&text[copied..start]);
+        body.push_str(&new_text);
+        copied = end;
+    }
+    body.push_str(&text[copied..body_end]);
+
+    let inlined = match bound_params.len() {
+        0 if function.is_expression => body,
+        0 => format!("{{\n{}\n}}", body),
+        1 => format!("{{\nlet {} = {};\n{}\n}}", bound_params[0], bound_args[0], body),
+        _ => format!(
+            "{{\nlet ({}) = ({});\n{}\n}}",
+            bound_params.join(", "),
+            bound_args.join(", "),
+            body
+        ),
+    };
+    let inlined = format_statements(&inlined, ctx)
+        .ok_or_else(|| format!("Failed to format the body of `{}`", def.name))?;
+    let indent = indentation(text, line_start(text, call.start));
+    let inlined = reindent(&inlined, indent);
+    let new_text = replacement(text, call.start, call.end, inlined.trim_left());
+    Ok(vec![(call.start, call.end, new_text)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_let_binding() {
+        let src = "fn f() {\n    let x: Vec<u8> = vec![1; 2];\n    g(x);\n}";
+        let binding = let_binding(src, src.find("x:").unwrap()).unwrap();
+        let (start, end) = binding.initializer;
+        assert_eq!(&src[start..end], "vec![1; 2]");
+        let line = src.find("    let").unwrap();
+        assert_eq!(binding.removal, (line, src.find("    g").unwrap()));
+
+        let src = "let a = 1; let b = a;";
+        let binding = let_binding(src, 4).unwrap();
+        assert_eq!(binding.removal, (0, src.find("let b").unwrap()));
+
+        for src in &["let mut a = 1;", "let (a, b) = c;", "let a;", "fn f(a: u8) {}"] {
+            let name = src.find('a').unwrap();
+            assert_eq!(let_binding(src, name), None, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_has_side_effects() {
+        let pure = ["a.b + 1", "&x[0] == y", "a <= b", "S { a: 1 }", "(a, -b) as u8"];
+        for expr in &pure {
+            assert!(!has_side_effects(expr, 0, expr.len()), "{:?}", expr);
+        }
+        let impure = ["f(x)", "a.b()", "vec![]", "x?", "a += 1", "loop {}", "g::<u8>(1)"];
+        for expr in &impure {
+            assert!(has_side_effects(expr, 0, expr.len()), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_is_in_loop_or_closure() {
+        let sources = [
+            ("let x = f(); for i in v { g(x); }", true),
+            ("let x = f(); while x > 0 {}", true),
+            ("let x = f(); loop { break x; }", true),
+            ("let x = f(); v.map(|i| i + x);", true),
+            ("let x = f(); v.map(move || { x });", true),
+            ("let x = f(); let c = || 1; g(x);", false),
+            ("let x = f(); for i in v {} g(a | x);", false),
+            ("loop { let x = f(); g(x); }", false),
+        ];
+        for &(src, expected) in &sources {
+            let start = src.find(';').unwrap() + 1;
+            let offset = src.rfind('x').unwrap();
+            assert_eq!(is_in_loop_or_closure(src, start, offset), expected, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_replacement() {
+        let at = |src: &str, name: &str, expr: &str| {
+            let start = src.find(name).unwrap();
+            replacement(src, start, start + name.len(), expr)
+        };
+        assert_eq!(at("f(x, 1)", "x", "a + b"), "a + b");
+        assert_eq!(at("let y = x;", "x", "a + b"), "a + b");
+        assert_eq!(at("x * 2", "x", "a + b"), "(a + b)");
+        assert_eq!(at("x.len()", "x", "a[0].b"), "a[0].b");
+        assert_eq!(at("x.len()", "x", "&a"), "(&a)");
+        assert_eq!(at("-x", "x", "f(1)"), "f(1)");
+        assert_eq!(at("let s = S { x, y: 1 };", "x", "1 + 2"), "x: 1 + 2");
+        assert_eq!(at("if c { x } else { y }", "x", "1 + 2"), "1 + 2");
+        assert_eq!(at("match a { A => x, _ => 0 }", "x", "1 + 2"), "1 + 2");
+        assert_eq!(at("f(x, 1) * 2", "f(x, 1)", "x + 1"), "(x + 1)");
+        assert_eq!(at("let y = f(x, 1);", "f(x, 1)", "x + 1"), "x + 1");
+    }
+
+    #[test]
+    fn test_function_and_call() {
+        let src = "fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\nfn g() { add(f(1, 2), 3); }";
+        let function = function(src, src.find("add").unwrap()).unwrap();
+        let a = src.find("a:").unwrap();
+        let b = src.find("b:").unwrap();
+        assert_eq!(function.params, vec![(a, a + 1), (b, b + 1)]);
+        let body = src.find("a + b").unwrap();
+        assert_eq!(function.body, (body, body + 5));
+        assert!(function.is_expression);
+
+        let call = call(src, src.rfind("add").unwrap()).unwrap();
+        let args: Vec<_> = call.args.iter().map(|&(s, e)| &src[s..e]).collect();
+        assert_eq!(args, vec!["f(1, 2)", "3"]);
+        assert_eq!(&src[call.start..call.end], "add(f(1, 2), 3)");
+    }
+
+    #[test]
+    fn test_function_and_call_not_simple() {
+        let sources = [
+            "fn f<T>(t: T) {}",
+            "fn f(&self) {}",
+            "fn f((a, b): (u8, u8)) {}",
+            "fn f(x: u8) -> u8 { if x > 0 { return 1; } 0 }",
+            "fn f(x: Option<u8>) -> Option<u8> { Some(x? + 1) }",
+            "fn f();",
+        ];
+        for src in &sources {
+            assert_eq!(function(src, 3), None, "{:?}", src);
+        }
+        let src = "fn f(x: u8) { let y = x; g(y); }";
+        assert!(!function(src, 3).unwrap().is_expression);
+
+        let src = "foo::bar(1); x.bar(2);";
+        assert_eq!(call(src, 5).map(|c| (c.start, c.end)), Some((0, 11)));
+        assert_eq!(call(src, src.rfind("bar").unwrap()), None);
+    }
+}
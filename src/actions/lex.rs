@@ -0,0 +1,369 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A lightweight tokenizer for Rust source text.
+//!
+//! Some requests need a little syntactic structure (where a call starts, where
+//! comments are, ...) but must work on the current VFS contents, which may be
+//! unsaved or not compile at all. This lexer never fails; anything it doesn't
+//! recognise is returned as punctuation.
+
+use std::collections::HashMap;
+
+/// The kind of a `Token`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// An identifier or keyword.
+    Ident,
+    /// A lifetime or label, e.g., `'a`.
+    Lifetime,
+    /// A numeric, string, byte string or char literal.
+    Literal,
+    /// A `//` comment, including doc comments.
+    LineComment,
+    /// A `/* */` comment, including doc comments.
+    BlockComment,
+    /// Any other single character.
+    Punct(char),
+}
+
+/// A token in some source text, `start` and `end` are byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    /// The text of this token in `src`.
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.start..self.end]
+    }
+
+    pub fn is_punct(&self, c: char) -> bool {
+        self.kind == TokenKind::Punct(c)
+    }
+
+    pub fn is_ident(&self, src: &str, name: &str) -> bool {
+        self.kind == TokenKind::Ident && self.text(src) == name
+    }
+
+    pub fn is_comment(&self) -> bool {
+        self.kind == TokenKind::LineComment || self.kind == TokenKind::BlockComment
+    }
+
+    /// Is this an outer or inner doc comment (`///`, `//!`, `/**` or `/*!`)?
+    pub fn is_doc_comment(&self, src: &str) -> bool {
+        let text = self.text(src);
+        match self.kind {
+            TokenKind::LineComment => {
+                (text.starts_with("///") && !text.starts_with("////")) || text.starts_with("//!")
+            }
+            TokenKind::BlockComment => {
+                (text.starts_with("/**") && !text.starts_with("/***") && text != "/**/")
+                    || text.starts_with("/*!")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Split `src` into tokens. Whitespace is skipped.
+pub fn tokenize(src: &str) -> Vec<Token> {
+    let mut lexer = Lexer {
+        src,
+        pos: 0,
+    };
+    let mut tokens = vec![];
+    while let Some(token) = lexer.next_token() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Maps the index of each opening delimiter (`(`, `[` or `{`) in `tokens` to the
+/// index of its closing delimiter, and vice versa. Unbalanced delimiters, which
+/// are common in code being edited, are left out.
+pub fn delimiter_pairs(tokens: &[Token]) -> HashMap<usize, usize> {
+    let mut pairs = HashMap::new();
+    let mut stack: Vec<(usize, char)> = vec![];
+    for (i, t) in tokens.iter().enumerate() {
+        let expected_open = match t.kind {
+            TokenKind::Punct(c) if c == '(' || c == '[' || c == '{' => {
+                stack.push((i, c));
+                continue;
+            }
+            TokenKind::Punct(')') => '(',
+            TokenKind::Punct(']') => '[',
+            TokenKind::Punct('}') => '{',
+            _ => continue,
+        };
+        if let Some(depth) = stack.iter().rposition(|&(_, c)| c == expected_open) {
+            let (open, _) = stack[depth];
+            stack.truncate(depth);
+            pairs.insert(open, i);
+            pairs.insert(i, open);
+        }
+    }
+    pairs
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat_while<F: Fn(char) -> bool>(&mut self, f: F) {
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.eat_while(char::is_whitespace);
+        let start = self.pos;
+        let c = self.peek()?;
+
+        let kind = if self.rest().starts_with("//") {
+            self.eat_while(|c| c != '\n');
+            TokenKind::LineComment
+        } else if self.rest().starts_with("/*") {
+            self.block_comment();
+            TokenKind::BlockComment
+        } else if self.rest().starts_with("r\"") || self.rest().starts_with("r#\"")
+            || self.rest().starts_with("br\"") || self.rest().starts_with("br#\"")
+        {
+            if c == 'b' {
+                self.bump();
+            }
+            self.bump();
+            self.raw_string();
+            TokenKind::Literal
+        } else if self.rest().starts_with("b\"") || self.rest().starts_with("b'") {
+            self.bump();
+            let quote = self.bump().unwrap();
+            self.quoted(quote);
+            TokenKind::Literal
+        } else if is_ident_start(c) {
+            self.eat_while(is_ident_continue);
+            TokenKind::Ident
+        } else if c.is_digit(10) {
+            self.number();
+            TokenKind::Literal
+        } else if c == '"' {
+            self.bump();
+            self.quoted('"');
+            TokenKind::Literal
+        } else if c == '\'' {
+            self.quote_or_lifetime()
+        } else {
+            self.bump();
+            TokenKind::Punct(c)
+        };
+
+        Some(Token {
+            kind,
+            start,
+            end: self.pos,
+        })
+    }
+
+    fn block_comment(&mut self) {
+        let mut depth = 0;
+        while !self.rest().is_empty() {
+            if self.rest().starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if self.rest().starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return;
+                }
+            } else {
+                self.bump();
+            }
+        }
+    }
+
+    // Expects to be positioned after the `r` (or `br`).
+    fn raw_string(&mut self) {
+        let mut hashes = 0;
+        while self.peek() == Some('#') {
+            self.bump();
+            hashes += 1;
+        }
+        // Opening `"`.
+        self.bump();
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        match self.rest().find(&terminator) {
+            Some(i) => self.pos += i + terminator.len(),
+            None => self.pos = self.src.len(),
+        }
+    }
+
+    // Expects to be positioned after the opening quote.
+    fn quoted(&mut self, quote: char) {
+        while let Some(c) = self.bump() {
+            if c == '\\' {
+                self.bump();
+            } else if c == quote {
+                return;
+            }
+        }
+    }
+
+    fn number(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if is_ident_continue(c) => {
+                    self.bump();
+                }
+                // Don't eat the `.` of a range (`1..2`) or a method call (`1.foo()`).
+                Some('.') if self.peek_nth(1).map_or(false, |c| c.is_digit(10)) => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn quote_or_lifetime(&mut self) -> TokenKind {
+        // Opening `'`.
+        self.bump();
+        match (self.peek(), self.peek_nth(1)) {
+            (Some('\\'), _) | (Some(_), Some('\'')) => {
+                self.quoted('\'');
+                TokenKind::Literal
+            }
+            (Some(c), _) if is_ident_start(c) => {
+                self.eat_while(is_ident_continue);
+                TokenKind::Lifetime
+            }
+            _ => TokenKind::Punct('\''),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(src)
+            .into_iter()
+            .map(|t| (t.kind, t.text(src)))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            kinds("fn foo<'a>(x: &'a str) -> char { 'x' }"),
+            vec![
+                (TokenKind::Ident, "fn"),
+                (TokenKind::Ident, "foo"),
+                (TokenKind::Punct('<'), "<"),
+                (TokenKind::Lifetime, "'a"),
+                (TokenKind::Punct('>'), ">"),
+                (TokenKind::Punct('('), "("),
+                (TokenKind::Ident, "x"),
+                (TokenKind::Punct(':'), ":"),
+                (TokenKind::Punct('&'), "&"),
+                (TokenKind::Lifetime, "'a"),
+                (TokenKind::Ident, "str"),
+                (TokenKind::Punct(')'), ")"),
+                (TokenKind::Punct('-'), "-"),
+                (TokenKind::Punct('>'), ">"),
+                (TokenKind::Ident, "char"),
+                (TokenKind::Punct('{'), "{"),
+                (TokenKind::Literal, "'x'"),
+                (TokenKind::Punct('}'), "}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_literals_and_comments() {
+        assert_eq!(
+            kinds(r##"/* a /* b */ */ "(\")" r#"(""# b'\'' 1..2 1.5 // (x"##),
+            vec![
+                (TokenKind::BlockComment, "/* a /* b */ */"),
+                (TokenKind::Literal, r#""(\")""#),
+                (TokenKind::Literal, r##"r#"(""#"##),
+                (TokenKind::Literal, r"b'\''"),
+                (TokenKind::Literal, "1"),
+                (TokenKind::Punct('.'), "."),
+                (TokenKind::Punct('.'), "."),
+                (TokenKind::Literal, "2"),
+                (TokenKind::Literal, "1.5"),
+                (TokenKind::LineComment, "// (x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delimiter_pairs() {
+        let src = "f(a[0], { b }) ) (";
+        let tokens = tokenize(src);
+        let pairs = delimiter_pairs(&tokens);
+        let close = |c: &str| {
+            let i = src.find(c).unwrap();
+            let open = tokens.iter().position(|t| t.start == i).unwrap();
+            pairs.get(&open).map(|&close| tokens[close].start)
+        };
+        assert_eq!(close("("), Some(13));
+        assert_eq!(close("["), Some(5));
+        assert_eq!(close("{"), Some(12));
+        assert_eq!(close(") ("), None);
+        assert_eq!(pairs.get(&tokens.len().saturating_sub(1)), None);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let src = "/// a\n//// b\n//! c\n/** d */\n/**/\n// e";
+        let docs: Vec<_> = tokenize(src)
+            .into_iter()
+            .map(|t| t.is_doc_comment(src))
+            .collect();
+        assert_eq!(docs, vec![true, false, true, true, false, false]);
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Adding the arms missing from a `match` on an enum, which rustc reports as
+//! E0004.
+
+use analysis::DefKind;
+
+use actions::imports::{indentation, line_start};
+use actions::lex::{self, Token, TokenKind};
+
+use std::collections::HashMap;
+
+/// The names of the variants in the patterns rustc reports as not covered in
+/// an E0004 error, e.g., `B` and `C` from "non-exhaustive patterns: `Foo::B(_)`
+/// and `C { .. }` not covered".
+pub fn uncovered_variants(message: &str) -> Vec<String> {
+    let line = message.lines().next().unwrap_or("");
+    // The patterns are the odd pieces, between backticks.
+    line.split('`')
+        .enumerate()
+        .filter(|&(i, _)| i % 2 == 1)
+        .filter_map(|(_, pattern)| {
+            let path = pattern
+                .split(|c| c == '(' || c == '{' || c == ' ')
+                .next()
+                .unwrap_or("");
+            let name = path.rsplit("::").next().unwrap_or("");
+            let is_ident = name.starts_with(|c: char| c.is_alphabetic())
+                && name.chars().all(|c| c == '_' || c.is_alphanumeric());
+            if is_ident {
+                Some(name.to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// A pattern matching any value of a variant, given its save-analysis kind and
+/// value, e.g., `Foo::B(u8, String)` for a tuple variant, which is written with
+/// the path prefix `prefix`, e.g., `Foo::`.
+pub fn variant_pattern(prefix: &str, name: &str, kind: DefKind, value: &str) -> String {
+    if kind == DefKind::StructVariant {
+        return format!("{}{} {{ .. }}", prefix, name);
+    }
+    let fields = match (value.find('('), value.rfind(')')) {
+        (Some(open), Some(close)) if open < close => &value[open + 1..close],
+        _ => return format!("{}{}", prefix, name),
+    };
+    let fields = if fields.trim().is_empty() {
+        0
+    } else {
+        top_level_commas(fields) + 1
+    };
+    format!("{}{}({})", prefix, name, vec!["_"; fields].join(", "))
+}
+
+// The number of commas in a list of types which separate its elements.
+fn top_level_commas(types: &str) -> usize {
+    let mut depth = 0;
+    let mut commas = 0;
+    let mut prev = ' ';
+    for c in types.chars() {
+        match c {
+            '(' | '[' | '<' => depth += 1,
+            // Not `->`, as in `fn() -> u8`.
+            ')' | ']' | '>' if prev != '-' => depth -= 1,
+            ',' if depth == 0 => commas += 1,
+            _ => {}
+        }
+        prev = c;
+    }
+    commas
+}
+
+/// The path of a variant in a pattern of a `match` arm.
+#[derive(Debug, PartialEq)]
+pub struct VariantPath {
+    /// The text before the variant's name, e.g., `Foo::`, or empty if it's
+    /// been imported.
+    pub prefix: String,
+    /// The byte offsets of the variant's name.
+    pub name: (usize, usize),
+}
+
+/// A `match` expression.
+#[derive(Debug)]
+pub struct Match {
+    /// The byte offsets of the last token of the scrutinee, if it's an
+    /// identifier, e.g., `kind` in `match self.kind`.
+    pub scrutinee: Option<(usize, usize)>,
+    /// The variants matched by the arms' patterns.
+    pub variants: Vec<VariantPath>,
+    start: usize,
+    /// The byte offsets of the braces around the arms.
+    body: (usize, usize),
+    arms: Vec<Arm>,
+}
+
+#[derive(Debug)]
+struct Arm {
+    start: usize,
+    end: usize,
+    /// Whether a comma must be added after the arm to add another.
+    needs_comma: bool,
+}
+
+/// The `match` expression whose `match` keyword or scrutinee starts at
+/// `offset`.
+pub fn match_expr(src: &str, offset: usize) -> Option<Match> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let closing = |i: usize| match pairs.get(&i) {
+        Some(&close) if close > i => Some(close),
+        _ => None,
+    };
+
+    let first = tokens.iter().position(|t| t.start >= offset)?;
+    let keyword = if tokens[first].is_ident(src, "match") {
+        first
+    } else if first > 0 && tokens[first - 1].is_ident(src, "match") {
+        first - 1
+    } else {
+        return None;
+    };
+
+    let mut open = keyword + 1;
+    while !tokens.get(open)?.is_punct('{') {
+        open = closing(open).unwrap_or(open) + 1;
+    }
+    let close = closing(open)?;
+    if open == keyword + 1 {
+        return None;
+    }
+    let scrutinee = &tokens[open - 1];
+    let scrutinee = if scrutinee.kind == TokenKind::Ident {
+        Some((scrutinee.start, scrutinee.end))
+    } else {
+        None
+    };
+
+    let mut arms = vec![];
+    let mut variants = vec![];
+    let mut i = open + 1;
+    while i < close {
+        let t = &tokens[i];
+        if t.is_comment() {
+            i += 1;
+            continue;
+        }
+        if t.is_punct('#') {
+            if let Some(attr_close) = closing(i + 1) {
+                i = attr_close + 1;
+                continue;
+            }
+        }
+
+        // The pattern, up to a guard or `=>`.
+        let mut pattern_end = None;
+        let mut arrow = None;
+        let mut j = i;
+        while j + 1 < close {
+            if tokens[j].is_punct('=') && tokens[j + 1].is_punct('>')
+                && tokens[j].end == tokens[j + 1].start
+            {
+                arrow = Some(j);
+                break;
+            }
+            if pattern_end.is_none() && tokens[j].is_ident(src, "if") {
+                pattern_end = Some(j);
+            }
+            j = closing(j).unwrap_or(j) + 1;
+        }
+        let arrow = match arrow {
+            Some(arrow) => arrow,
+            None => break,
+        };
+        variants.extend(pattern_variants(
+            src,
+            &tokens,
+            &pairs,
+            (i, pattern_end.unwrap_or(arrow)),
+        ));
+
+        // The expression, a block or up to a comma.
+        let mut end = arrow + 2;
+        let needs_comma = if tokens.get(end).map_or(false, |t| t.is_punct('{')) {
+            end = closing(end).unwrap_or(end) + 1;
+            false
+        } else {
+            while end < close && !tokens[end].is_punct(',') {
+                end = closing(end).unwrap_or(end) + 1;
+            }
+            end >= close
+        };
+        if end < close && tokens[end].is_punct(',') {
+            end += 1;
+        }
+        let end = end.min(close);
+        arms.push(Arm {
+            start: tokens[i].start,
+            end: tokens[end - 1].end,
+            needs_comma,
+        });
+        i = end;
+    }
+
+    Some(Match {
+        scrutinee,
+        variants,
+        start: tokens[keyword].start,
+        body: (tokens[open].start, tokens[close].start),
+        arms,
+    })
+}
+
+// The variant paths at the top level of each alternative of the pattern in
+// `tokens[start..end]`, e.g., `A` and `Foo::B` in `A | &Foo::B(..)`.
+fn pattern_variants(
+    src: &str,
+    tokens: &[Token],
+    pairs: &HashMap<usize, usize>,
+    (start, end): (usize, usize),
+) -> Vec<VariantPath> {
+    let mut variants = vec![];
+    let mut i = start;
+    while i < end {
+        while i < end && (tokens[i].is_punct('|') || tokens[i].is_punct('&')) {
+            i += 1;
+        }
+        let path_start = i;
+        let mut name = None;
+        while i < end && tokens[i].kind == TokenKind::Ident {
+            name = Some(i);
+            if i + 2 < end && tokens[i + 1].is_punct(':') && tokens[i + 2].is_punct(':') {
+                i += 3;
+            } else {
+                i += 1;
+                break;
+            }
+        }
+        if let Some(name) = name {
+            let t = &tokens[name];
+            variants.push(VariantPath {
+                prefix: src[tokens[path_start].start..t.start].to_owned(),
+                name: (t.start, t.end),
+            });
+        }
+        // Skip to the next alternative.
+        while i < end && !tokens[i].is_punct('|') {
+            i = match pairs.get(&i) {
+                Some(&close) if close > i => close + 1,
+                _ => i + 1,
+            };
+        }
+    }
+    variants
+}
+
+impl Match {
+    /// The edit adding `arms` after the existing arms, as the byte offsets of
+    /// the text to replace and its replacement. `indent` is a level of
+    /// indentation, used if there are no arms yet.
+    pub fn insert_arms(&self, src: &str, arms: &[String], indent: &str) -> (usize, usize, String) {
+        match self.arms.last() {
+            Some(last) => {
+                let arm_indent = indentation(src, line_start(src, self.arms[0].start));
+                let mut text = if last.needs_comma {
+                    ",".to_owned()
+                } else {
+                    String::new()
+                };
+                for arm in arms {
+                    text.push_str(&format!("\n{}{}", arm_indent, arm));
+                }
+                (last.end, last.end, text)
+            }
+            None => {
+                let match_indent = indentation(src, line_start(src, self.start));
+                let mut text = String::new();
+                for arm in arms {
+                    text.push_str(&format!("\n{}{}{}", match_indent, indent, arm));
+                }
+                text.push('\n');
+                text.push_str(match_indent);
+                (self.body.0 + 1, self.body.1, text)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_uncovered_variants() {
+        assert_eq!(
+            uncovered_variants("non-exhaustive patterns: `Foo::B(_)` and `C { .. }` not covered"),
+            vec!["B", "C"]
+        );
+        assert_eq!(
+            uncovered_variants("non-exhaustive patterns: `_` not covered"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            uncovered_variants("non-exhaustive patterns: `-2147483648i32..=0i32` not covered"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_variant_pattern() {
+        assert_eq!(variant_pattern("Foo::", "A", DefKind::TupleVariant, "Foo::A"), "Foo::A");
+        let value = "Foo::B(u8, HashMap<K, V>, fn(u8) -> u8)";
+        assert_eq!(variant_pattern("", "B", DefKind::TupleVariant, value), "B(_, _, _)");
+        assert_eq!(variant_pattern("", "C", DefKind::TupleVariant, "Foo::C()"), "C()");
+        assert_eq!(
+            variant_pattern("Foo::", "D", DefKind::StructVariant, "Foo::D { x, y }"),
+            "Foo::D { .. }"
+        );
+    }
+
+    #[test]
+    fn test_match_expr() {
+        let src = "fn f(x: &Foo) -> u8 {
+    match *x {
+        Foo::A | Foo::B(1, _) => 1,
+        &Foo::C { .. } if true => { 2 }
+        // comment
+        D => 3
+    }
+}";
+        let m = match_expr(src, src.find("*x").unwrap()).unwrap();
+        assert_eq!(m.scrutinee.map(|(s, e)| &src[s..e]), Some("x"));
+        let variants: Vec<_> = m.variants
+            .iter()
+            .map(|v| format!("{}{}", v.prefix, &src[v.name.0..v.name.1]))
+            .collect();
+        assert_eq!(variants, vec!["Foo::A", "Foo::B", "Foo::C", "D"]);
+
+        let end = src.find("D => 3").unwrap() + "D => 3".len();
+        assert_eq!(
+            m.insert_arms(src, &["E => 4,".to_owned()], "    "),
+            (end, end, ",\n        E => 4,".to_owned())
+        );
+
+        let src = "fn f() {\n    match x {}\n}";
+        let m = match_expr(src, src.find("match").unwrap()).unwrap();
+        let body = src.find("{}").unwrap() + 1;
+        assert_eq!(
+            m.insert_arms(src, &["A => 1,".to_owned(), "B => 2,".to_owned()], "    "),
+            (body, body, "\n        A => 1,\n        B => 2,\n    ".to_owned())
+        );
+
+        assert!(match_expr("if x {}", 0).is_none());
+    }
+}
@@ -23,6 +23,8 @@ use walkdir::WalkDir;
 
 use actions::post_build::{BuildResults, PostBuildHandler, AnalysisQueue};
 use actions::progress::{BuildProgressNotifier, BuildDiagnosticsNotifier};
+use actions::run::CargoTargets;
+use actions::semantic_tokens::SemanticTokensCache;
 use build::*;
 use lsp_data;
 use lsp_data::*;
@@ -59,7 +61,23 @@ pub mod requests;
 pub mod notifications;
 pub mod progress;
 pub mod diagnostics;
+pub mod diff;
 pub mod run;
+pub mod lex;
+pub mod extract;
+pub mod folding;
+pub mod hierarchy;
+pub mod imports;
+pub mod inlay_hints;
+pub mod inline;
+pub mod match_arms;
+pub mod rename;
+pub mod selection;
+pub mod semantic_tokens;
+pub mod outline;
+pub mod signature;
+pub mod test_runner;
+pub mod trait_items;
 
 /// Persistent context shared across all requests and notifications.
 pub enum ActionContext {
@@ -97,6 +115,7 @@ impl ActionContext {
                     current_project,
                     uninit.pid,
                     init_options.cmd_run,
+                    init_options.cmd_show_references,
                 );
                 ctx.init(init_options, out);
                 ctx
@@ -148,10 +167,16 @@ pub struct InitActionContext {
     pub quiescent: Arc<AtomicBool>,
 
     prev_changes: Arc<Mutex<HashMap<PathBuf, u64>>>,
+    // The semantic tokens last sent for each file, for computing deltas.
+    semantic_tokens: Arc<Mutex<SemanticTokensCache>>,
+    // The Cargo targets of the project, read when first needed and cleared
+    // when a manifest changes.
+    cargo_targets: Arc<Mutex<Option<Arc<CargoTargets>>>>,
 
     config: Arc<Mutex<Config>>,
     client_capabilities: Arc<lsp_data::ClientCapabilities>,
     client_supports_cmd_run: bool,
+    client_supports_cmd_show_references: bool,
     /// Whether the server is performing cleanup (after having received
     /// 'shutdown' request), just before final 'exit' request.
     pub shut_down: Arc<AtomicBool>,
@@ -191,6 +216,7 @@ impl InitActionContext {
         current_project: PathBuf,
         pid: u32,
         client_supports_cmd_run: bool,
+        client_supports_cmd_show_references: bool,
     ) -> InitActionContext {
         let build_queue = BuildQueue::new(vfs.clone(), config.clone());
         let analysis_queue = Arc::new(AnalysisQueue::init());
@@ -206,8 +232,11 @@ impl InitActionContext {
             shown_cargo_error: Arc::new(AtomicBool::new(false)),
             quiescent: Arc::new(AtomicBool::new(false)),
             prev_changes: Arc::new(Mutex::new(HashMap::new())),
+            semantic_tokens: Arc::new(Mutex::new(SemanticTokensCache::default())),
+            cargo_targets: Arc::new(Mutex::new(None)),
             client_capabilities: Arc::new(client_capabilities),
             client_supports_cmd_run,
+            client_supports_cmd_show_references,
             shut_down: Arc::new(AtomicBool::new(false)),
             pid,
         }
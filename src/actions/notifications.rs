@@ -11,6 +11,7 @@
 //! One-way notifications that the RLS receives from the client.
 
 use actions::{InitActionContext, FileWatch, VersionOrdering};
+use actions::semantic_tokens;
 use vfs::Change;
 use config::Config;
 use serde::Deserialize;
@@ -44,9 +45,45 @@ impl BlockingNotificationAction for Initialized {
     // dynamically register some options.
     fn handle<O: Output>(_params: Self::Params, ctx: &mut InitActionContext, out: O) -> Result<(), ()> {
         const WATCH_ID: &str = "rls-watch";
+        const IMPLEMENTATION_ID: &str = "rls-implementation";
+        const TYPE_DEFINITION_ID: &str = "rls-type-definition";
+        const FOLDING_RANGE_ID: &str = "rls-folding-range";
+        const SELECTION_RANGE_ID: &str = "rls-selection-range";
+        const SEMANTIC_TOKENS_ID: &str = "rls-semantic-tokens";
+        const INLAY_HINT_ID: &str = "rls-inlay-hint";
+        const CALL_HIERARCHY_ID: &str = "rls-call-hierarchy";
+        const TYPE_HIERARCHY_ID: &str = "rls-type-hierarchy";
+        const RENAME_ID: &str = "rls-rename";
+        const WILL_RENAME_FILES_ID: &str = "rls-will-rename-files";
+
+        let semantic_tokens_options = SemanticTokensRegistrationOptions {
+            document_selector: None,
+            legend: semantic_tokens::legend(),
+            range: false,
+            full: SemanticTokensFullOptions { delta: true },
+        };
 
-        let id = out.provide_id();
-        let params = RegistrationParams {
+        // Renaming a source file or a directory may rename a module.
+        let will_rename_files_options = FileOperationRegistrationOptions {
+            filters: vec![
+                FileOperationFilter {
+                    scheme: Some("file".to_owned()),
+                    pattern: FileOperationPattern {
+                        glob: "**/*.rs".to_owned(),
+                        matches: Some(FileOperationPatternKind::File),
+                    },
+                },
+                FileOperationFilter {
+                    scheme: Some("file".to_owned()),
+                    pattern: FileOperationPattern {
+                        glob: "**".to_owned(),
+                        matches: Some(FileOperationPatternKind::Folder),
+                    },
+                },
+            ],
+        };
+
+        let mut params = RegistrationParams {
             registrations: vec![
                 Registration {
                     id: WATCH_ID.to_owned(),
@@ -56,7 +93,94 @@ impl BlockingNotificationAction for Initialized {
             ],
         };
 
-        let request = Request::<RegisterCapability>::new(id, params);
+        if ctx.client_capabilities.folding_range_dynamic_registration {
+            params.registrations.push(Registration {
+                id: FOLDING_RANGE_ID.to_owned(),
+                method: <FoldingRangeRequest as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        if ctx.client_capabilities.selection_range_dynamic_registration {
+            params.registrations.push(Registration {
+                id: SELECTION_RANGE_ID.to_owned(),
+                method: <SelectionRangeRequest as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        if ctx.client_capabilities.semantic_tokens_dynamic_registration {
+            params.registrations.push(Registration {
+                id: SEMANTIC_TOKENS_ID.to_owned(),
+                method: SEMANTIC_TOKENS_METHOD.to_owned(),
+                register_options: serde_json::to_value(semantic_tokens_options).ok(),
+            });
+        }
+
+        if ctx.client_capabilities.inlay_hint_dynamic_registration {
+            params.registrations.push(Registration {
+                id: INLAY_HINT_ID.to_owned(),
+                method: <InlayHintRequest as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        if ctx.client_capabilities.call_hierarchy_dynamic_registration {
+            params.registrations.push(Registration {
+                id: CALL_HIERARCHY_ID.to_owned(),
+                method: <CallHierarchyPrepare as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        if ctx.client_capabilities.type_hierarchy_dynamic_registration {
+            params.registrations.push(Registration {
+                id: TYPE_HIERARCHY_ID.to_owned(),
+                method: <TypeHierarchyPrepare as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        if ctx.client_capabilities.will_rename_files_dynamic_registration {
+            params.registrations.push(Registration {
+                id: WILL_RENAME_FILES_ID.to_owned(),
+                method: <WillRenameFiles as LSPRequest>::METHOD.to_owned(),
+                register_options: serde_json::to_value(will_rename_files_options).ok(),
+            });
+        }
+
+        // FIXME these should be in the server capabilities, but
+        // languageserver-types doesn't support them yet, so they are only
+        // available to clients which support registering them dynamically.
+        if ctx.client_capabilities.implementation_dynamic_registration {
+            params.registrations.push(Registration {
+                id: IMPLEMENTATION_ID.to_owned(),
+                method: <GotoImplementation as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+        if ctx.client_capabilities.type_definition_dynamic_registration {
+            params.registrations.push(Registration {
+                id: TYPE_DEFINITION_ID.to_owned(),
+                method: <GotoTypeDefinition as LSPRequest>::METHOD.to_owned(),
+                register_options: None,
+            });
+        }
+
+        // languageserver-types doesn't support rename's `prepareProvider`
+        // option yet, so rename is registered here, rather than in the server
+        // capabilities, if the client lets us.
+        if ctx.client_capabilities.rename_dynamic_registration {
+            params.registrations.push(Registration {
+                id: RENAME_ID.to_owned(),
+                method: <request::Rename as LSPRequest>::METHOD.to_owned(),
+                register_options: serde_json::to_value(RenameOptions {
+                    prepare_provider: true,
+                }).ok(),
+            });
+        }
+
+        let request = Request::<RegisterCapability>::new(out.provide_id(), params);
         out.request(request);
         Ok(())
     }
@@ -268,6 +392,7 @@ impl BlockingNotificationAction for DidChangeWatchedFiles {
         let file_watch = FileWatch::new(&ctx);
 
         if params.changes.iter().any(|c| file_watch.is_relevant(c)) {
+            *ctx.cargo_targets.lock().unwrap() = None;
             ctx.build_current_project(BuildPriority::Cargo, &out);
         }
 
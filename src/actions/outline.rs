@@ -0,0 +1,485 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Building the tree of `DocumentSymbol`s for `textDocument/documentSymbol`.
+//!
+//! Save-analysis only tells us where the names of items are, so the extents of
+//! items, and `impl` blocks (which aren't defs), come from the text of the file.
+//! Symbols are nested by the containment of their extents.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::run::LineIndex;
+use analysis::DefKind;
+use lsp_data::{ls_util, source_kind_from_def_kind, DocumentSymbol, Range, SymbolKind};
+use span::{self, ZeroIndexed};
+
+use std::cmp::Reverse;
+
+/// A def in the file, from save-analysis.
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: DefKind,
+    pub detail: Option<String>,
+    /// The range of the def's name.
+    pub range: span::Range<ZeroIndexed>,
+}
+
+/// Arrange `symbols` in `text` into a tree.
+pub fn document_symbols(text: &str, symbols: Vec<Symbol>) -> Vec<DocumentSymbol> {
+    let line_index = LineIndex::new(text);
+    let tokens = lex::tokenize(text);
+    let range = |start, end| {
+        Range::new(
+            ls_util::rls_to_position(line_index.offset_to_position(start)),
+            ls_util::rls_to_position(line_index.offset_to_position(end)),
+        )
+    };
+
+    let mut nodes = vec![];
+    // Symbols whose names can't be found in the text, which are listed after
+    // the others.
+    let mut unplaced = vec![];
+    for symbol in symbols {
+        if symbol.kind == DefKind::Local {
+            continue;
+        }
+        let selection_range = ls_util::rls_to_range(symbol.range);
+        let name_start = line_index.position_to_offset(symbol.range.start());
+        let name_end = line_index.position_to_offset(symbol.range.end());
+        let name_token =
+            name_start.and_then(|offset| tokens.iter().position(|t| t.start == offset));
+        let extent = match name_token {
+            Some(i) => Some(
+                item_extent(text, &tokens, i, symbol.kind)
+                    .unwrap_or((tokens[i].start, tokens[i].end)),
+            ),
+            None => name_start.and_then(|start| name_end.map(|end| (start, end))),
+        };
+        let mut document_symbol = DocumentSymbol {
+            name: symbol.name,
+            detail: symbol.detail,
+            kind: source_kind_from_def_kind(symbol.kind),
+            deprecated: None,
+            range: selection_range,
+            selection_range,
+            children: None,
+        };
+        match extent {
+            Some((start, end)) => {
+                if name_token.is_some() {
+                    document_symbol.range = range(start, end);
+                }
+                nodes.push((start, end, document_symbol));
+            }
+            None => unplaced.push(document_symbol),
+        }
+    }
+
+    for block in impl_blocks(text, &tokens) {
+        nodes.push((block.start, block.end, DocumentSymbol {
+            name: block.name,
+            detail: None,
+            kind: SymbolKind::Namespace,
+            deprecated: None,
+            range: range(block.start, block.end),
+            selection_range: range(block.header.0, block.header.1),
+            children: None,
+        }));
+    }
+
+    let mut symbols = nest(nodes);
+    symbols.extend(unplaced);
+    symbols
+}
+
+// Nests symbols given with their start and end offsets by containment.
+fn nest(mut nodes: Vec<(usize, usize, DocumentSymbol)>) -> Vec<DocumentSymbol> {
+    nodes.sort_by_key(|&(start, end, _)| (start, Reverse(end)));
+
+    // Nodes are sorted so that a node's ancestors are on the stack.
+    let mut parents = vec![None; nodes.len()];
+    let mut stack: Vec<usize> = vec![];
+    for (i, &(start, end, _)) in nodes.iter().enumerate() {
+        while let Some(&top) = stack.last() {
+            let (top_start, top_end, _) = nodes[top];
+            if top_start <= start && end <= top_end {
+                break;
+            }
+            stack.pop();
+        }
+        parents[i] = stack.last().cloned();
+        stack.push(i);
+    }
+
+    // Children come after their parents, so attaching them in reverse completes
+    // each node before it is attached itself.
+    let mut symbols: Vec<_> = nodes.into_iter().map(|(_, _, s)| Some(s)).collect();
+    let mut roots = vec![];
+    for i in (0..symbols.len()).rev() {
+        let symbol = symbols[i].take().unwrap();
+        match parents[i] {
+            Some(parent) => symbols[parent]
+                .as_mut()
+                .unwrap()
+                .children
+                .get_or_insert_with(Vec::new)
+                .push(symbol),
+            None => roots.push(symbol),
+        }
+    }
+    reverse(&mut roots);
+    roots
+}
+
+fn reverse(symbols: &mut Vec<DocumentSymbol>) {
+    symbols.reverse();
+    for symbol in symbols {
+        if let Some(ref mut children) = symbol.children {
+            reverse(children);
+        }
+    }
+}
+
+/// The start and end offsets of the item whose name is `tokens[name]`, including
+/// its attributes and doc comments.
+pub fn item_extent(
+    src: &str,
+    tokens: &[Token],
+    name: usize,
+    kind: DefKind,
+) -> Option<(usize, usize)> {
+    let start = item_start(src, tokens, name);
+    let is_field = match kind {
+        DefKind::Field | DefKind::TupleVariant | DefKind::StructVariant => true,
+        _ => false,
+    };
+    // Items whose body may contain braces before the end, e.g., `const X: Foo = Foo {};`
+    let ends_with_semicolon = match kind {
+        DefKind::Const | DefKind::Static | DefKind::Type => true,
+        _ => false,
+    };
+
+    let mut depth = 0;
+    // Generic arguments in field types may contain commas.
+    let mut angle_depth = 0;
+    for i in name + 1..tokens.len() {
+        let t = &tokens[i];
+        match t.kind {
+            TokenKind::Punct('(') | TokenKind::Punct('[') => depth += 1,
+            TokenKind::Punct('{') if depth == 0 && !is_field && !ends_with_semicolon => {
+                let end = matching_brace(tokens, i).unwrap_or(tokens.len() - 1);
+                return Some((start, tokens[end].end));
+            }
+            TokenKind::Punct('{') => depth += 1,
+            TokenKind::Punct(')') | TokenKind::Punct(']') | TokenKind::Punct('}') => {
+                if depth == 0 {
+                    // The end of the enclosing item.
+                    return if is_field {
+                        Some((start, tokens[i - 1].end))
+                    } else {
+                        None
+                    };
+                }
+                depth -= 1;
+            }
+            TokenKind::Punct('<') if is_field => angle_depth += 1,
+            TokenKind::Punct('>') if is_field && angle_depth > 0 => {
+                // Not the `->` of a `Fn() -> T`.
+                if !tokens[i - 1].is_punct('-') {
+                    angle_depth -= 1;
+                }
+            }
+            TokenKind::Punct(';') if depth == 0 => return Some((start, t.end)),
+            TokenKind::Punct(',') if depth == 0 && angle_depth == 0 && is_field => {
+                return Some((start, tokens[i - 1].end));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The start of an item, given the index of its name (or first keyword), walking
+// back over qualifiers, attributes and doc comments.
+fn item_start(src: &str, tokens: &[Token], name: usize) -> usize {
+    let mut start = tokens[name].start;
+    let mut i = name;
+    while i > 0 {
+        let t = &tokens[i - 1];
+        match t.kind {
+            // `pub(crate) unsafe extern "C" fn`
+            TokenKind::Ident
+            | TokenKind::Literal
+            | TokenKind::Punct('(')
+            | TokenKind::Punct(')') => {
+                start = t.start;
+                i -= 1;
+            }
+            TokenKind::Punct(']') => {
+                let mut depth = 0;
+                let mut open = None;
+                for j in (0..i).rev() {
+                    if tokens[j].is_punct(']') {
+                        depth += 1;
+                    } else if tokens[j].is_punct('[') {
+                        depth -= 1;
+                        if depth == 0 {
+                            open = Some(j);
+                            break;
+                        }
+                    }
+                }
+                match open {
+                    Some(open) if open > 0 && tokens[open - 1].is_punct('#') => {
+                        start = tokens[open - 1].start;
+                        i = open - 1;
+                    }
+                    _ => break,
+                }
+            }
+            _ if t.is_doc_comment(src) => {
+                let text = t.text(src);
+                if text.starts_with("//!") || text.starts_with("/*!") {
+                    break;
+                }
+                start = t.start;
+                i -= 1;
+            }
+            _ if t.is_comment() => i -= 1,
+            _ => break,
+        }
+    }
+    start
+}
+
+// The index of the `}` matching the `{` at `open`.
+fn matching_brace(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        if t.is_punct('{') {
+            depth += 1;
+        } else if t.is_punct('}') {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug, PartialEq)]
+struct ImplBlock {
+    /// E.g., `impl Foo for Bar<T>`.
+    name: String,
+    start: usize,
+    end: usize,
+    /// The extent of the trait and type.
+    header: (usize, usize),
+}
+
+fn impl_blocks(src: &str, tokens: &[Token]) -> Vec<ImplBlock> {
+    let mut result = vec![];
+    for (i, t) in tokens.iter().enumerate() {
+        if !t.is_ident(src, "impl") {
+            continue;
+        }
+        // Skip `impl Trait` types.
+        let item_level = i == 0 || {
+            let prev = &tokens[i - 1];
+            prev.is_punct('}') || prev.is_punct('{') || prev.is_punct(';') || prev.is_punct(']')
+                || prev.is_comment() || prev.is_ident(src, "unsafe")
+                || prev.is_ident(src, "default")
+        };
+        if !item_level {
+            continue;
+        }
+
+        // Skip generics, `impl<T: Fn() -> u8>`.
+        let mut header_start = i + 1;
+        if tokens.get(header_start).map_or(false, |t| t.is_punct('<')) {
+            let mut depth = 0;
+            while header_start < tokens.len() {
+                let t = &tokens[header_start];
+                header_start += 1;
+                if t.is_punct('<') {
+                    depth += 1;
+                } else if t.is_punct('>') && !tokens[header_start - 2].is_punct('-') {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut header_end = None;
+        let mut open = None;
+        for (j, t) in tokens.iter().enumerate().skip(header_start) {
+            if t.is_ident(src, "where") && header_end.is_none() {
+                header_end = Some(j);
+            } else if t.is_punct('{') {
+                open = Some(j);
+                break;
+            } else if t.is_punct(';') || t.is_punct('}') {
+                break;
+            }
+        }
+        let open = match open {
+            Some(open) => open,
+            None => continue,
+        };
+        let header_end = header_end.unwrap_or(open);
+        if header_end <= header_start {
+            continue;
+        }
+
+        let header = (tokens[header_start].start, tokens[header_end - 1].end);
+        let name = src[header.0..header.1]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let end = matching_brace(tokens, open).unwrap_or(tokens.len() - 1);
+        result.push(ImplBlock {
+            name: format!("impl {}", name),
+            start: item_start(src, tokens, i),
+            end: tokens[end].end,
+            header,
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lsp_data::Position;
+
+    // The text of the item named `name`.
+    fn extent<'a>(src: &'a str, name: &str, kind: DefKind) -> Option<&'a str> {
+        let tokens = lex::tokenize(src);
+        let i = tokens.iter().position(|t| t.is_ident(src, name)).unwrap();
+        item_extent(src, &tokens, i, kind).map(|(start, end)| &src[start..end])
+    }
+
+    #[test]
+    fn test_item_extent() {
+        let src = "
+            /// Docs.
+            #[derive(Debug)]
+            pub(crate) struct Foo<T> {
+                a: HashMap<u8, T>,
+                pub b: [u8; 4]
+            }
+            // Not docs.
+            const X: Foo = Foo { a: 1, b: 2 };
+            enum E { A(u32, u32), B { x: u32 } }
+            fn f<F: Fn() -> u8>(f: F) -> u8 where F: Copy { { f() } }
+            mod m;
+        ";
+        assert_eq!(
+            extent(src, "Foo", DefKind::Struct).unwrap().lines().next(),
+            Some("/// Docs.")
+        );
+        assert!(
+            extent(src, "Foo", DefKind::Struct)
+                .unwrap()
+                .ends_with("b: [u8; 4]\n            }")
+        );
+        assert_eq!(extent(src, "a", DefKind::Field), Some("a: HashMap<u8, T>"));
+        assert_eq!(extent(src, "b", DefKind::Field), Some("pub b: [u8; 4]"));
+        assert_eq!(extent(src, "X", DefKind::Const), Some("const X: Foo = Foo { a: 1, b: 2 };"));
+        assert_eq!(extent(src, "A", DefKind::TupleVariant), Some("A(u32, u32)"));
+        assert_eq!(extent(src, "B", DefKind::StructVariant), Some("B { x: u32 }"));
+        assert_eq!(
+            extent(src, "f", DefKind::Function),
+            Some("fn f<F: Fn() -> u8>(f: F) -> u8 where F: Copy { { f() } }")
+        );
+        assert_eq!(extent(src, "m", DefKind::Mod), Some("mod m;"));
+    }
+
+    #[test]
+    fn test_impl_blocks() {
+        let src = "
+            impl<T: Fn() -> u8> Foo for
+                Bar<T> where T: Copy {
+                fn f() -> impl Iterator<Item = u8> {}
+            }
+            unsafe impl Send for Bar {}
+        ";
+        let blocks: Vec<_> = impl_blocks(src, &lex::tokenize(src))
+            .into_iter()
+            .map(|b| (b.name, &src[b.start..b.end], &src[b.header.0..b.header.1]))
+            .collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].0, "impl Foo for Bar<T>");
+        assert!(blocks[0].1.starts_with("impl<T"));
+        assert!(blocks[0].1.ends_with("{}\n            }"));
+        assert_eq!(blocks[0].2, "Foo for\n                Bar<T>");
+        assert_eq!(blocks[1].0, "impl Send for Bar");
+        assert_eq!(blocks[1].1, "unsafe impl Send for Bar {}");
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let symbol = |name: &str, line, start, end| Symbol {
+            name: name.to_owned(),
+            kind: DefKind::Function,
+            detail: None,
+            range: ls_util::range_to_rls(Range::new(
+                Position::new(line, start),
+                Position::new(line, end),
+            )),
+        };
+        let text = "const S: &str = \"é\"; fn f() {}\nm!();";
+        // `g`'s name isn't in the text, e.g., as it's from a macro.
+        let symbols = document_symbols(text, vec![symbol("f", 0, 24, 25), symbol("g", 1, 7, 8)]);
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].name, "f");
+        assert_eq!(symbols[0].range, Range::new(Position::new(0, 21), Position::new(0, 30)));
+        assert_eq!(symbols[1].name, "g");
+        assert_eq!(symbols[1].range, symbols[1].selection_range);
+    }
+
+    #[test]
+    fn test_nest() {
+        fn symbol(name: &str) -> DocumentSymbol {
+            DocumentSymbol {
+                name: name.to_owned(),
+                detail: None,
+                kind: SymbolKind::Function,
+                deprecated: None,
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                selection_range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                children: None,
+            }
+        }
+        fn names(symbols: &[DocumentSymbol]) -> Vec<String> {
+            symbols
+                .iter()
+                .map(|s| match s.children {
+                    Some(ref children) => format!("{}({})", s.name, names(children).join(" ")),
+                    None => s.name.clone(),
+                })
+                .collect()
+        }
+
+        let nodes = vec![
+            (20, 30, symbol("c")),
+            (0, 40, symbol("m")),
+            (5, 15, symbol("a")),
+            (7, 8, symbol("b")),
+            (50, 60, symbol("d")),
+            (20, 30, symbol("e")),
+        ];
+        assert_eq!(names(&nest(nodes)), vec!["m(a(b) c(e))", "d"]);
+    }
+}
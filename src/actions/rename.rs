@@ -0,0 +1,224 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for renaming, beyond finding the references to rename.
+
+use analysis::DefKind;
+
+use std::path::{Path, PathBuf};
+
+/// Rust's keywords, including those reserved for future use, none of which can
+/// be used as a name.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s)
+}
+
+/// Checks that a definition can be renamed to `name`, returning the reason if
+/// it can't.
+pub fn check_new_name(name: &str) -> Result<(), String> {
+    if is_keyword(name) {
+        return Err(format!("`{}` is a keyword", name));
+    }
+    let mut chars = name.chars();
+    let is_identifier = match chars.next() {
+        Some('_') => name.len() > 1,
+        Some(c) => c.is_alphabetic(),
+        None => false,
+    } && chars.all(|c| c == '_' || c.is_alphanumeric());
+    if !is_identifier {
+        return Err(format!("`{}` is not a valid identifier", name));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Namespace {
+    Type,
+    Value,
+    Macro,
+    Field,
+}
+
+impl Namespace {
+    fn from_def_kind(k: DefKind) -> Namespace {
+        match k {
+            DefKind::Mod
+            | DefKind::Struct
+            | DefKind::Union
+            | DefKind::Enum
+            | DefKind::Tuple
+            | DefKind::TupleVariant
+            | DefKind::StructVariant
+            | DefKind::Trait
+            | DefKind::Type
+            | DefKind::ExternType => Namespace::Type,
+            DefKind::Function
+            | DefKind::Method
+            | DefKind::Local
+            | DefKind::Static
+            | DefKind::Const => Namespace::Value,
+            DefKind::Macro => Namespace::Macro,
+            DefKind::Field => Namespace::Field,
+        }
+    }
+}
+
+/// Whether two definitions of kinds `a` and `b` in the same scope would
+/// conflict if they had the same name.
+pub fn conflicts(a: DefKind, b: DefKind) -> bool {
+    Namespace::from_def_kind(a) == Namespace::from_def_kind(b)
+}
+
+/// The files and directories to move, as pairs of old and new paths, when
+/// renaming the module `name`, declared in `decl_file`, to `new_name`.
+///
+/// `module_file` is the file containing the module's items, as recorded in the
+/// module's definition, which is `decl_file` for an inline module. Nothing is
+/// moved for inline modules, or for modules whose file isn't named after them,
+/// i.e., those with a `#[path]` attribute.
+pub fn module_moves(
+    decl_file: &Path,
+    module_file: &Path,
+    name: &str,
+    new_name: &str,
+) -> Vec<(PathBuf, PathBuf)> {
+    if module_file == decl_file {
+        return vec![];
+    }
+    let (dir, file_name) = match (module_file.parent(), module_file.file_name()) {
+        (Some(dir), Some(file_name)) => (dir, file_name),
+        _ => return vec![],
+    };
+
+    if file_name == "mod.rs" {
+        // `name/mod.rs`, move the whole directory.
+        return match dir.parent() {
+            Some(parent) if dir.file_name().map_or(false, |d| d == name) => {
+                vec![(dir.to_owned(), parent.join(new_name))]
+            }
+            _ => vec![],
+        };
+    }
+
+    if module_file.file_stem().map_or(true, |stem| stem != name)
+        || module_file.extension().map_or(true, |ext| ext != "rs")
+    {
+        return vec![];
+    }
+    // `name.rs`, and its submodules in `name/` if there are any.
+    let mut moves = vec![(module_file.to_owned(), dir.join(format!("{}.rs", new_name)))];
+    let submodule_dir = dir.join(name);
+    if submodule_dir.is_dir() {
+        moves.push((submodule_dir, dir.join(new_name)));
+    }
+    moves
+}
+
+/// The module renamed by moving the file or directory `old_path` to `new_path`,
+/// as its old and new names, and the file containing it, as recorded in its
+/// definition.
+///
+/// Only renames within a directory which keep the module's layout, `foo.rs` to
+/// `bar.rs` or `foo/` (containing `mod.rs`) to `bar/`, rename a module.
+pub fn renamed_module(old_path: &Path, new_path: &Path) -> Option<(String, String, PathBuf)> {
+    if old_path.parent() != new_path.parent() {
+        return None;
+    }
+    let is_source = |p: &Path| p.extension().map_or(false, |ext| ext == "rs");
+    let (old_name, new_name, module_file) = if is_source(old_path) && is_source(new_path) {
+        (old_path.file_stem()?, new_path.file_stem()?, old_path.to_owned())
+    } else if old_path.extension().is_none() && new_path.extension().is_none() {
+        (old_path.file_name()?, new_path.file_name()?, old_path.join("mod.rs"))
+    } else {
+        return None;
+    };
+
+    let (old_name, new_name) = (old_name.to_str()?, new_name.to_str()?);
+    if ["lib", "main", "mod"].contains(&old_name) || check_new_name(new_name).is_err() {
+        return None;
+    }
+    Some((old_name.to_owned(), new_name.to_owned(), module_file))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_new_name() {
+        assert!(check_new_name("foo_bar2").is_ok());
+        assert!(check_new_name("_foo").is_ok());
+        assert!(check_new_name("Größe").is_ok());
+        assert_eq!(check_new_name("fn"), Err("`fn` is a keyword".to_owned()));
+        assert_eq!(check_new_name("self"), Err("`self` is a keyword".to_owned()));
+        for name in &["", "_", "2foo", "foo-bar", "foo bar", "foo::bar"] {
+            assert!(check_new_name(name).is_err(), "accepted {:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_conflicts() {
+        assert!(conflicts(DefKind::Struct, DefKind::Enum));
+        assert!(conflicts(DefKind::Function, DefKind::Const));
+        assert!(!conflicts(DefKind::Struct, DefKind::Function));
+        assert!(!conflicts(DefKind::Macro, DefKind::Function));
+    }
+
+    #[test]
+    fn test_module_moves() {
+        let root = Path::new("/nonexistent/src");
+        let lib = root.join("lib.rs");
+        assert_eq!(
+            module_moves(&lib, &root.join("foo.rs"), "foo", "bar"),
+            vec![(root.join("foo.rs"), root.join("bar.rs"))]
+        );
+        assert_eq!(
+            module_moves(&lib, &root.join("foo").join("mod.rs"), "foo", "bar"),
+            vec![(root.join("foo"), root.join("bar"))]
+        );
+        // An inline module in `lib.rs`.
+        assert_eq!(module_moves(&lib, &lib, "foo", "bar"), vec![]);
+        // `#[path = "other.rs"] mod foo;`
+        assert_eq!(module_moves(&lib, &root.join("other.rs"), "foo", "bar"), vec![]);
+        assert_eq!(
+            module_moves(&lib, &root.join("other").join("mod.rs"), "foo", "bar"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_renamed_module() {
+        let root = Path::new("/nonexistent/src");
+        assert_eq!(
+            renamed_module(&root.join("foo.rs"), &root.join("bar.rs")),
+            Some(("foo".to_owned(), "bar".to_owned(), root.join("foo.rs")))
+        );
+        assert_eq!(
+            renamed_module(&root.join("foo"), &root.join("bar")),
+            Some(("foo".to_owned(), "bar".to_owned(), root.join("foo").join("mod.rs")))
+        );
+        // Moved to another directory.
+        assert_eq!(renamed_module(&root.join("foo.rs"), &root.join("a").join("foo.rs")), None);
+        // No longer a Rust source file.
+        assert_eq!(renamed_module(&root.join("foo.rs"), &root.join("foo.txt")), None);
+        // Crate roots aren't modules.
+        assert_eq!(renamed_module(&root.join("lib.rs"), &root.join("bar.rs")), None);
+        // Not an identifier.
+        assert_eq!(renamed_module(&root.join("foo.rs"), &root.join("foo-bar.rs")), None);
+    }
+}
@@ -18,16 +18,21 @@ use racer;
 use rustfmt::{format_input, FileLines, FileName, Input as FmtInput, Range as RustfmtRange};
 use serde_json;
 use span;
+use Span;
 
 use actions::work_pool;
 use actions::work_pool::WorkDescription;
-use actions::run::collect_run_actions;
+use actions::run::{self, collect_run_actions, LineIndex, TargetKind};
+use actions::test_runner::{RunTestsParams, TestRun};
+use actions::diagnostics::{self, Suggestion};
+use actions::{diff, extract, folding, hierarchy, imports, inlay_hints, inline, lex, match_arms,
+              outline, rename, selection, semantic_tokens, signature, trait_items};
 use lsp_data;
 use lsp_data::*;
 use server;
 use server::{Ack, Output, Request, RequestAction, ResponseError};
 use jsonrpc_core::types::ErrorCode;
-use analysis::SymbolQuery;
+use analysis::{AnalysisHost, Def, DefKind, SymbolQuery};
 
 use lsp_data::request::ApplyWorkspaceEdit;
 pub use lsp_data::request::{
@@ -45,12 +50,26 @@ pub use lsp_data::request::{
     RangeFormatting,
     ResolveCompletionItem as ResolveCompletion,
     CodeLensRequest,
+    CodeLensResolve as ResolveCodeLens,
+    SignatureHelpRequest as SignatureHelp,
 };
-pub use lsp_data::FindImpls;
+pub use lsp_data::{CallHierarchyIncomingCalls as IncomingCalls,
+                   CallHierarchyOutgoingCalls as OutgoingCalls,
+                   CallHierarchyPrepare as PrepareCallHierarchy, FindImpls,
+                   FoldingRangeRequest as FoldingRanges,
+                   GotoImplementation as Implementation, GotoTypeDefinition as TypeDefinition,
+                   InlayHintRequest as InlayHints, PrepareRenameRequest as PrepareRename,
+                   SelectionRangeRequest as SelectionRanges,
+                   TypeHierarchyPrepare as PrepareTypeHierarchy,
+                   TypeHierarchySubtypes as Subtypes, TypeHierarchySupertypes as Supertypes,
+                   WillRenameFiles,
+                   SemanticTokensFullDeltaRequest as SemanticTokensFullDelta,
+                   SemanticTokensFullRequest as SemanticTokensFull};
 
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::atomic::Ordering;
+use walkdir::WalkDir;
 
 
 /// Represent the result of a deglob action for a single wildcard import.
@@ -65,6 +84,78 @@ pub struct DeglobResult {
     pub new_text: String,
 }
 
+/// The arguments of the `rls.deglobAll` command.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DeglobAllParams {
+    /// The file to deglob, or every file in the workspace if `None`.
+    pub uri: Option<Url>,
+    /// Keep wildcard imports of modules named `prelude`.
+    pub skip_preludes: bool,
+    /// Keep `use super::*` in `#[cfg(test)]` modules.
+    pub skip_test_modules: bool,
+}
+
+/// The arguments of the `rls.fixAll` command.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FixAllParams {
+    /// The file to fix, or every file with diagnostics if `None`.
+    pub uri: Option<Url>,
+    /// Only apply the suggestions of diagnostics with this lint or error code,
+    /// e.g., `unused_mut` or `E0308`.
+    pub code: Option<String>,
+}
+
+/// The arguments of the `rls.extract` command.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractParams {
+    pub uri: Url,
+    /// The selected expression or statements.
+    pub range: Range,
+    pub kind: ExtractKind,
+}
+
+/// What to extract the selection into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExtractKind {
+    /// A `let` binding, before the statement containing the selection.
+    Variable,
+    /// A function, after the one containing the selection.
+    Function,
+}
+
+/// The arguments of the `rls.inline` command.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineParams {
+    pub uri: Url,
+    /// The position of the variable, or of the name of the called function.
+    pub position: Position,
+    pub kind: InlineKind,
+}
+
+/// What to inline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InlineKind {
+    /// A local variable, into each of its uses.
+    Variable,
+    /// A function, into a call.
+    Function,
+}
+
+/// The data of a reference or implementation count code lens, which is computed
+/// when the lens is resolved.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CodeLensData {
+    References(TextDocumentPositionParams),
+    Implementations(TextDocumentPositionParams),
+}
+
 impl RequestAction for WorkspaceSymbol {
     type Response = Vec<SymbolInformation>;
 
@@ -99,10 +190,10 @@ impl RequestAction for WorkspaceSymbol {
 }
 
 impl RequestAction for Symbols {
-    type Response = Vec<SymbolInformation>;
+    type Response = DocumentSymbolResponse;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
-        Ok(vec![])
+        Ok(DocumentSymbolResponse::Flat(vec![]))
     }
 
     fn handle(
@@ -113,9 +204,33 @@ impl RequestAction for Symbols {
 
         let file_path = parse_file_path!(&params.text_document.uri, "symbols")?;
 
-        let symbols = analysis.symbols(&file_path).unwrap_or_else(|_| vec![]);
+        let mut symbols = analysis.symbols(&file_path).unwrap_or_else(|_| vec![]);
+
+        if ctx.client_capabilities.hierarchical_document_symbol_support {
+            if let Ok(FileContents::Text(text)) = ctx.vfs.load_file(&file_path) {
+                // With `all_targets` the same file may be part of several crates.
+                symbols.sort_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+                symbols.dedup_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+                let symbols = symbols
+                    .into_iter()
+                    .map(|s| outline::Symbol {
+                        detail: analysis
+                            .get_def(s.id)
+                            .ok()
+                            .map(|def| def.value)
+                            .and_then(|value| if value.is_empty() { None } else { Some(value) }),
+                        name: s.name,
+                        kind: s.kind,
+                        range: s.span.range,
+                    })
+                    .collect();
+                return Ok(DocumentSymbolResponse::Nested(
+                    outline::document_symbols(&text, symbols),
+                ));
+            }
+        }
 
-        Ok(
+        Ok(DocumentSymbolResponse::Flat(
             symbols
                 .into_iter()
                 .map(|s| {
@@ -129,7 +244,262 @@ impl RequestAction for Symbols {
                     }
                 })
                 .collect(),
-        )
+        ))
+    }
+}
+
+impl RequestAction for FoldingRanges {
+    type Response = Vec<FoldingRange>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "folding_ranges")?;
+
+        match ctx.vfs.load_file(&file_path) {
+            Ok(FileContents::Text(text)) => Ok(folding::folding_ranges(&text)),
+            Ok(FileContents::Binary(_)) => Self::fallback_response(),
+            Err(e) => Err(ResponseError::Message(ErrorCode::InternalError, e.to_string())),
+        }
+    }
+}
+
+impl RequestAction for SelectionRanges {
+    type Response = Vec<SelectionRange>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "selection_ranges")?;
+
+        match ctx.vfs.load_file(&file_path) {
+            Ok(FileContents::Text(text)) => {
+                Ok(selection::selection_ranges(&text, &params.positions))
+            }
+            Ok(FileContents::Binary(_)) => Self::fallback_response(),
+            Err(e) => Err(ResponseError::Message(ErrorCode::InternalError, e.to_string())),
+        }
+    }
+}
+
+impl RequestAction for SemanticTokensFull {
+    type Response = SemanticTokens;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(SemanticTokens {
+            result_id: None,
+            data: vec![],
+        })
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "semantic_tokens")?;
+        let data = semantic_token_data(&ctx, &file_path)?;
+        let result_id = ctx.semantic_tokens.lock().unwrap().insert(file_path, data.clone());
+        Ok(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })
+    }
+}
+
+impl RequestAction for SemanticTokensFullDelta {
+    type Response = SemanticTokensFullDeltaResult;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        SemanticTokensFull::fallback_response().map(SemanticTokensFullDeltaResult::Tokens)
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "semantic_tokens_delta")?;
+        let data = semantic_token_data(&ctx, &file_path)?;
+
+        let mut cache = ctx.semantic_tokens.lock().unwrap();
+        let edits = cache
+            .get(&file_path, &params.previous_result_id)
+            .map(|previous| semantic_tokens::diff(previous, &data));
+        let result_id = Some(cache.insert(file_path, data.clone()));
+        Ok(match edits {
+            Some(edits) => {
+                SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta { result_id, edits })
+            }
+            // The client has a result we no longer know about, so it gets all
+            // the tokens instead.
+            None => SemanticTokensFullDeltaResult::Tokens(SemanticTokens { result_id, data }),
+        })
+    }
+}
+
+// Classifies and encodes the identifiers in the current contents of a file.
+fn semantic_token_data(
+    ctx: &InitActionContext,
+    file_path: &Path,
+) -> Result<Vec<u32>, ResponseError> {
+    match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => {
+            let tokens = semantic_tokens::semantic_tokens(&ctx.analysis, &ctx.vfs, file_path, text);
+            Ok(semantic_tokens::encode(&tokens))
+        }
+        Ok(FileContents::Binary(_)) => Ok(vec![]),
+        Err(e) => Err(ResponseError::Message(ErrorCode::InternalError, e.to_string())),
+    }
+}
+
+impl RequestAction for InlayHints {
+    type Response = Vec<InlayHint>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "inlay_hints")?;
+
+        match ctx.vfs.load_file(&file_path) {
+            Ok(FileContents::Text(text)) => Ok(inlay_hints::inlay_hints(
+                &ctx.analysis,
+                &file_path,
+                &text,
+                ls_util::range_to_rls(params.range),
+            )),
+            Ok(FileContents::Binary(_)) => Self::fallback_response(),
+            Err(e) => Err(ResponseError::Message(ErrorCode::InternalError, e.to_string())),
+        }
+    }
+}
+
+impl RequestAction for PrepareCallHierarchy {
+    type Response = Vec<CallHierarchyItem>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "prepare_call_hierarchy")?;
+        let span = ctx.convert_pos_to_span(file_path, params.position);
+
+        let analysis = &ctx.analysis;
+        match analysis.id(&span).and_then(|id| analysis.get_def(id)) {
+            Ok(ref def) if hierarchy::is_callable(def.kind) => {
+                Ok(vec![hierarchy::Sources::new(&ctx.vfs).item(def)])
+            }
+            _ => Self::fallback_response(),
+        }
+    }
+}
+
+impl RequestAction for IncomingCalls {
+    type Response = Vec<CallHierarchyIncomingCall>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.item.uri, "incoming_calls")?;
+        let span = Span::from_range(ls_util::range_to_rls(params.item.selection_range), file_path);
+        Ok(hierarchy::incoming_calls(&ctx.analysis, &ctx.vfs, &span))
+    }
+}
+
+impl RequestAction for OutgoingCalls {
+    type Response = Vec<CallHierarchyOutgoingCall>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.item.uri, "outgoing_calls")?;
+        let span = Span::from_range(ls_util::range_to_rls(params.item.selection_range), file_path);
+        Ok(hierarchy::outgoing_calls(&ctx.analysis, &ctx.vfs, &span))
+    }
+}
+
+impl RequestAction for PrepareTypeHierarchy {
+    type Response = Vec<TypeHierarchyItem>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "prepare_type_hierarchy")?;
+        let span = ctx.convert_pos_to_span(file_path, params.position);
+
+        let analysis = &ctx.analysis;
+        match analysis.id(&span).and_then(|id| analysis.get_def(id)) {
+            Ok(ref def) if hierarchy::is_type(def.kind) => {
+                Ok(vec![hierarchy::Sources::new(&ctx.vfs).item(def)])
+            }
+            _ => Self::fallback_response(),
+        }
+    }
+}
+
+impl RequestAction for Supertypes {
+    type Response = Vec<TypeHierarchyItem>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.item.uri, "supertypes")?;
+        let span = Span::from_range(ls_util::range_to_rls(params.item.selection_range), file_path);
+        Ok(hierarchy::supertypes(&ctx.analysis, &ctx.vfs, &span))
+    }
+}
+
+impl RequestAction for Subtypes {
+    type Response = Vec<TypeHierarchyItem>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.item.uri, "subtypes")?;
+        let span = Span::from_range(ls_util::range_to_rls(params.item.selection_range), file_path);
+        Ok(hierarchy::subtypes(&ctx.analysis, &ctx.vfs, &span))
     }
 }
 
@@ -185,26 +555,141 @@ impl RequestAction for FindImpls {
         ctx: InitActionContext,
         params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
-        let file_path = parse_file_path!(&params.text_document.uri, "find_impls")?;
+        find_impls(ctx, params)
+    }
+}
+
+impl RequestAction for Implementation {
+    type Response = Vec<Location>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        find_impls(ctx, params)
+    }
+}
+
+fn find_impls(
+    ctx: InitActionContext,
+    params: TextDocumentPositionParams,
+) -> Result<Vec<Location>, ResponseError> {
+    let file_path = parse_file_path!(&params.text_document.uri, "find_impls")?;
+    let span = ctx.convert_pos_to_span(file_path, params.position);
+    let analysis = ctx.analysis;
+
+    let type_id = analysis.id(&span).map_err(|_| ResponseError::Empty)?;
+    let result = analysis.find_impls(type_id).map(|spans| {
+        spans
+            .into_iter()
+            .map(|x| ls_util::rls_to_location(&x))
+            .collect()
+    });
+
+    trace!("find_impls: {:?}", result);
+
+    result.map_err(|_| {
+        ResponseError::Message(
+            ErrorCode::InternalError,
+            "Find Implementations failed to complete successfully".into(),
+        )
+    })
+}
+
+impl RequestAction for TypeDefinition {
+    type Response = Vec<Location>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(vec![])
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "type_definition")?;
         let span = ctx.convert_pos_to_span(file_path, params.position);
         let analysis = ctx.analysis;
 
-        let type_id = analysis.id(&span).map_err(|_| ResponseError::Empty)?;
-        let result = analysis.find_impls(type_id).map(|spans| {
-            spans
-                .into_iter()
-                .map(|x| ls_util::rls_to_location(&x))
-                .collect()
-        });
+        let ty = analysis.show_type(&span).unwrap_or_else(|_| String::new());
+        let result: Vec<_> = type_defs(&analysis, &ty)
+            .iter()
+            .map(|def| ls_util::rls_to_location(&def.span))
+            .collect();
+        trace!("type_definition: {:?} -> {:?}", ty, result);
+        Ok(result)
+    }
+}
 
-        trace!("find_impls: {:?}", result);
+// The definitions of the outermost named type in `ty`, as printed by
+// `show_type`.
+fn type_defs(analysis: &AnalysisHost, ty: &str) -> Vec<Def> {
+    let path = match type_path(ty) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let name = path.rsplit("::").next().unwrap();
 
-        result.map_err(|_| {
-            ResponseError::Message(
-                ErrorCode::InternalError,
-                "Find Implementations failed to complete successfully".into(),
-            )
+    let defs: Vec<_> = analysis
+        .search_for_id(name)
+        .unwrap_or_else(|_| vec![])
+        .into_iter()
+        .filter_map(|id| analysis.get_def(id).ok())
+        .filter(|def| match def.kind {
+            DefKind::Struct
+            | DefKind::Enum
+            | DefKind::Union
+            | DefKind::Trait
+            | DefKind::Type
+            | DefKind::ExternType => true,
+            _ => false,
         })
+        .collect();
+
+    // The type may be printed with its full path, prefer definitions with
+    // a matching qualname.
+    let (matching, others): (Vec<_>, Vec<_>) = defs
+        .into_iter()
+        .partition(|def| def.qualname.trim_left_matches("::").ends_with(path));
+    if matching.is_empty() { others } else { matching }
+}
+
+// Extracts the path of the outermost named type from a type as printed by
+// `show_type`, e.g., `foo::Bar` from `&mut foo::Bar<u32>`.
+fn type_path(ty: &str) -> Option<&str> {
+    let mut ty = ty.trim();
+    loop {
+        let prev = ty;
+        for prefix in &["&", "*const ", "*mut ", "mut ", "dyn ", "impl "] {
+            if ty.starts_with(*prefix) {
+                ty = ty[prefix.len()..].trim_left();
+            }
+        }
+        // A lifetime, e.g., `&'a Foo`.
+        if ty.starts_with('\'') {
+            ty = ty.trim_left_matches(|c: char| c == '\'' || c == '_' || c.is_alphanumeric())
+                .trim_left();
+        }
+        // The element type of an array or slice.
+        if ty.starts_with('[') {
+            ty = &ty[1..];
+        }
+        if ty == prev {
+            break;
+        }
+    }
+
+    let end = ty.find(|c: char| !(c == ':' || c == '_' || c.is_alphanumeric()))
+        .unwrap_or(ty.len());
+    let path = ty[..end].trim_right_matches(':');
+    if path.is_empty() || path.chars().next().unwrap().is_numeric() {
+        None
+    } else {
+        Some(path)
     }
 }
 
@@ -295,6 +780,118 @@ impl RequestAction for References {
     }
 }
 
+impl RequestAction for SignatureHelp {
+    type Response = Option<lsp_data::SignatureHelp>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let file_path = parse_file_path!(&params.text_document.uri, "signature_help")?;
+
+        // Work on the VFS text so that we can help while the user is typing,
+        // i.e., when the code is incomplete and doesn't build.
+        let text = match ctx.vfs.load_file(&file_path) {
+            Ok(FileContents::Text(text)) => text,
+            _ => return Self::fallback_response(),
+        };
+        let line_index = LineIndex::new(&text);
+        let position = ls_util::position_to_rls(params.position);
+        let cursor = match line_index.position_to_offset(position) {
+            Some(cursor) => cursor,
+            None => return Self::fallback_response(),
+        };
+        let tokens = lex::tokenize(&text);
+        let call = match signature::find_call_context(&text, &tokens, cursor) {
+            Some(call) => call,
+            None => return Self::fallback_response(),
+        };
+
+        let callee_pos = line_index.offset_to_position(call.callee_offset);
+        let callee_pos = ls_util::rls_to_position(callee_pos);
+        let span = ctx.convert_pos_to_span(file_path.clone(), callee_pos);
+        trace!("signature_help: {:?} {:?}", span, call);
+
+        let analysis = ctx.analysis;
+        let vfs = ctx.vfs;
+
+        // If configured start racer concurrently and fallback to racer result
+        let racer_receiver = {
+            if ctx.config.lock().unwrap().goto_def_racer_fallback {
+                Some(work_pool::receive_from_thread(move || {
+                    let cache = racer::FileCache::new(vfs);
+                    let session = racer::Session::new(&cache);
+                    let location = pos_to_racer_location(callee_pos);
+
+                    racer::find_definition(file_path, location, &session)
+                        .map(|m| (m.matchstr, m.contextstr, m.docs))
+                }, WorkDescription("textDocument/signatureHelp-racer")))
+            } else {
+                None
+            }
+        };
+
+        let from_analysis = analysis
+            .id(&span)
+            .and_then(|id| analysis.get_def(id))
+            .ok()
+            .and_then(|def| {
+                signature::parse_signature(&def.name, &def.value).map(|sig| (sig, def.docs))
+            });
+        let found = match (from_analysis, racer_receiver) {
+            (Some(found), _) => found,
+            (None, Some(receiver)) => match receiver.recv() {
+                Ok(Some((name, context, docs))) => {
+                    match signature::parse_signature(&name, &context) {
+                        Some(sig) => (sig, docs),
+                        None => return Self::fallback_response(),
+                    }
+                }
+                _ => return Self::fallback_response(),
+            },
+            (None, None) => return Self::fallback_response(),
+        };
+
+        let (mut sig, docs) = found;
+        trace!("signature_help: {:?}", sig);
+        if call.is_method && sig.params.first().map_or(false, |p| signature::is_self_param(p)) {
+            sig.params.remove(0);
+        }
+
+        let documentation = if docs.is_empty() {
+            None
+        } else {
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: docs,
+            }))
+        };
+        let parameters = sig.params
+            .into_iter()
+            .map(|label| ParameterInformation {
+                label,
+                documentation: None,
+            })
+            .collect();
+
+        Ok(Some(lsp_data::SignatureHelp {
+            signatures: vec![
+                SignatureInformation {
+                    label: sig.label,
+                    documentation,
+                    parameters: Some(parameters),
+                },
+            ],
+            active_signature: Some(0),
+            active_parameter: Some(call.active_parameter as u64),
+        }))
+    }
+}
+
 impl RequestAction for Completion {
     type Response = Vec<CompletionItem>;
 
@@ -376,11 +973,36 @@ impl RequestAction for DocumentHighlight {
     }
 }
 
+impl RequestAction for PrepareRename {
+    type Response = Option<PrepareRenameResponse>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        ctx.block_on_build();
+
+        let file_path = parse_file_path!(&params.text_document.uri, "prepare_rename")?;
+        let span = ctx.convert_pos_to_span(file_path, params.position);
+
+        let (def, _) = rename_target(&ctx, &span)
+            .map_err(|msg| ResponseError::Message(ErrorCode::InvalidParams, msg))?;
+        Ok(Some(PrepareRenameResponse {
+            range: ls_util::rls_to_range(span.range),
+            placeholder: def.name,
+        }))
+    }
+}
+
 impl RequestAction for Rename {
-    type Response = WorkspaceEdit;
+    type Response = ResourceWorkspaceEdit;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
-        Ok(WorkspaceEdit {
+        Ok(ResourceWorkspaceEdit {
             changes: None,
             document_changes: None,
         })
@@ -398,55 +1020,201 @@ impl RequestAction for Rename {
         let file_path = parse_file_path!(&params.text_document.uri, "rename")?;
         let span = ctx.convert_pos_to_span(file_path, params.position);
 
-        let analysis = ctx.analysis;
+        let invalid = |msg| ResponseError::Message(ErrorCode::InvalidParams, msg);
+        rename::check_new_name(&params.new_name).map_err(&invalid)?;
+        let (def, result) = rename_target(&ctx, &span).map_err(&invalid)?;
+        check_rename_conflicts(&ctx, &def, &params.new_name).map_err(&invalid)?;
 
-        macro_rules! unwrap_or_fallback {
-            ($e: expr) => {
-                match $e {
-                    Ok(e) => e,
-                    Err(_) => {
-                        return Self::fallback_response();
-                    }
-                }
-            }
+        // Renaming a module defined in its own file must move that file too.
+        let moves = if def.kind == data::DefKind::Mod {
+            let module_file = ctx.current_project.join(&def.value);
+            rename::module_moves(&def.span.file, &module_file, &def.name, &params.new_name)
+        } else {
+            vec![]
+        };
+        if !moves.is_empty() && !ctx.client_capabilities.rename_file_support {
+            return Err(invalid(format!(
+                "Renaming module `{}` requires moving files, which the client doesn't support",
+                def.name
+            )));
         }
 
-        let id = unwrap_or_fallback!(analysis.crate_local_id(&span));
-        let def = unwrap_or_fallback!(analysis.get_def(id));
-        if def.name == "self" || def.name == "Self"
-            // FIXME(#578)
-            || def.kind == data::DefKind::Mod
-        {
+        let edits = rename_edits(&result, &params.new_name);
+
+        if !ctx.quiescent.load(Ordering::SeqCst) {
             return Self::fallback_response();
         }
 
-        let result = unwrap_or_fallback!(analysis.find_all_refs(&span, true, true));
+        if moves.is_empty() {
+            return Ok(ResourceWorkspaceEdit { changes: Some(edits), document_changes: None });
+        }
+
+        // The text edits refer to the files by their old names, so must be
+        // applied before any moves.
+        let mut document_changes: Vec<_> = edits
+            .into_iter()
+            .map(|(uri, edits)| {
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: VersionedTextDocumentIdentifier { uri, version: None },
+                    edits,
+                })
+            })
+            .collect();
+        for (old_path, new_path) in moves {
+            let (old_uri, new_uri) =
+                match (Url::from_file_path(old_path), Url::from_file_path(new_path)) {
+                    (Ok(old_uri), Ok(new_uri)) => (old_uri, new_uri),
+                    _ => return Self::fallback_response(),
+                };
+            document_changes.push(DocumentChangeOperation::Op(ResourceOp::Rename(RenameFile {
+                old_uri,
+                new_uri,
+            })));
+        }
+
+        Ok(ResourceWorkspaceEdit {
+            changes: None,
+            document_changes: Some(document_changes),
+        })
+    }
+}
+
+impl RequestAction for WillRenameFiles {
+    type Response = Option<WorkspaceEdit>;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Ok(None)
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        ctx.quiescent.store(true, Ordering::SeqCst);
+        ctx.block_on_build();
 
+        let analysis = &ctx.analysis;
         let mut edits: HashMap<Url, Vec<TextEdit>> = HashMap::new();
 
-        for item in &result {
-            let loc = ls_util::rls_to_location(item);
-            edits
-                .entry(loc.uri)
-                .or_insert_with(Vec::new)
-                .push(TextEdit {
-                    range: loc.range,
-                    new_text: params.new_name.clone(),
+        for file in &params.files {
+            let paths = (file.old_uri.to_file_path(), file.new_uri.to_file_path());
+            let (old_path, new_path) = match paths {
+                (Ok(old_path), Ok(new_path)) => (old_path, new_path),
+                _ => continue,
+            };
+            let (old_name, new_name, module_file) =
+                match rename::renamed_module(&old_path, &new_path) {
+                    Some(module) => module,
+                    None => continue,
+                };
+
+            let query = SymbolQuery::subsequence(&old_name);
+            let def = analysis
+                .query_defs(query)
+                .unwrap_or_else(|_| vec![])
+                .into_iter()
+                .find(|d| {
+                    d.kind == DefKind::Mod && d.name == old_name
+                        && ctx.current_project.join(&d.value) == module_file
                 });
+            let refs = match def.and_then(|d| analysis.find_all_refs(&d.span, true, true).ok()) {
+                Some(refs) => refs,
+                None => continue,
+            };
+            for (uri, file_edits) in rename_edits(&refs, &new_name) {
+                edits.entry(uri).or_insert_with(Vec::new).extend(file_edits);
+            }
         }
 
-        if !ctx.quiescent.load(Ordering::SeqCst) {
+        if !ctx.quiescent.load(Ordering::SeqCst) || edits.is_empty() {
             return Self::fallback_response();
         }
+        Ok(Some(WorkspaceEdit { changes: Some(edits), document_changes: None }))
+    }
+}
+
+/// The edits renaming each of `refs` to `new_name`.
+fn rename_edits(refs: &[Span], new_name: &str) -> HashMap<Url, Vec<TextEdit>> {
+    let mut edits: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+    for item in refs {
+        let loc = ls_util::rls_to_location(item);
+        edits
+            .entry(loc.uri)
+            .or_insert_with(Vec::new)
+            .push(TextEdit {
+                range: loc.range,
+                new_text: new_name.to_owned(),
+            });
+    }
+    edits
+}
+
+/// Finds the definition of the identifier at `span` and all references to it,
+/// or the reason it can't be renamed.
+fn rename_target(ctx: &InitActionContext, span: &Span) -> Result<(Def, Vec<Span>), String> {
+    let name = ctx.vfs.load_span(span.clone()).map_err(|e| e.to_string())?;
+    if name.is_empty() {
+        return Err("No identifier to rename".to_owned());
+    }
+    if rename::is_keyword(&name) {
+        return Err(format!("Cannot rename the keyword `{}`", name));
+    }
+
+    let analysis = &ctx.analysis;
+    let def = analysis
+        .id(span)
+        .and_then(|id| analysis.get_def(id))
+        .map_err(|_| format!("No definition found for `{}`", name))?;
+    if analysis.crate_local_id(span).is_err() {
+        return Err(format!("Cannot rename `{}`, it is defined outside this crate", name));
+    }
+
+    let refs = analysis
+        .find_all_refs(span, true, true)
+        .map_err(|_| format!("Failed to find references to `{}`", name))?;
+    // References from macros don't always span the name itself.
+    for r in &refs {
+        if ctx.vfs.load_span(r.clone()).ok().map_or(true, |text| text != def.name) {
+            return Err(format!("Cannot rename `{}`, it is used in a macro", def.name));
+        }
+    }
+    Ok((def, refs))
+}
 
-        Ok(WorkspaceEdit { changes: Some(edits), document_changes: None })
+/// Checks that renaming `def` to `new_name` doesn't conflict with a definition
+/// in the same scope.
+fn check_rename_conflicts(
+    ctx: &InitActionContext,
+    def: &Def,
+    new_name: &str,
+) -> Result<(), String> {
+    // Locals may shadow each other.
+    if def.kind == DefKind::Local {
+        return Ok(());
     }
+    let parent = match def.parent {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+
+    let query = SymbolQuery::subsequence(new_name);
+    let defs = ctx.analysis.query_defs(query).unwrap_or_else(|_| vec![]);
+    let conflict = defs.iter().any(|d| {
+        d.name == new_name && d.parent == Some(parent) && rename::conflicts(d.kind, def.kind)
+    });
+    if conflict {
+        return Err(format!("A definition named `{}` already exists in this scope", new_name));
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
 pub enum ExecuteCommandResponse {
     /// Response/client request containing workspace edits.
     ApplyEdit(ApplyWorkspaceEditParams),
+    /// Tests to run in the background, reporting results with notifications.
+    RunTests(TestRun),
 }
 
 impl server::Response for ExecuteCommandResponse {
@@ -462,6 +1230,9 @@ impl server::Response for ExecuteCommandResponse {
                 let request = Request::<ApplyWorkspaceEdit>::new(id, params);
                 out.request(request);
             }
+            ExecuteCommandResponse::RunTests(ref run) => {
+                run.clone().spawn(out.clone());
+            }
         }
 
         // The formal request response is a simple ACK, though the objective
@@ -477,15 +1248,26 @@ impl RequestAction for ExecuteCommand {
         Err(ResponseError::Empty)
     }
 
-    /// Currently supports "rls.applySuggestion", "rls.deglobImports".
+    /// Currently supports "rls.applySuggestion", "rls.fixAll", "rls.deglobImports",
+    /// "rls.deglobAll", "rls.extract", "rls.inline", "rls.runTests".
     fn handle(
         ctx: InitActionContext,
         params: ExecuteCommandParams,
     ) -> Result<Self::Response, ResponseError> {
         if params.command.starts_with("rls.applySuggestion") {
             apply_suggestion(&params.arguments).map(ExecuteCommandResponse::ApplyEdit)
+        } else if params.command.starts_with("rls.fixAll") {
+            fix_all(params.arguments, &ctx).map(ExecuteCommandResponse::ApplyEdit)
         } else if params.command.starts_with("rls.deglobImports") {
             apply_deglobs(params.arguments, &ctx).map(ExecuteCommandResponse::ApplyEdit)
+        } else if params.command.starts_with("rls.deglobAll") {
+            deglob_all(params.arguments, &ctx).map(ExecuteCommandResponse::ApplyEdit)
+        } else if params.command.starts_with("rls.extract") {
+            extract(params.arguments, &ctx).map(ExecuteCommandResponse::ApplyEdit)
+        } else if params.command.starts_with("rls.inline") {
+            inline(params.arguments, &ctx).map(ExecuteCommandResponse::ApplyEdit)
+        } else if params.command.starts_with("rls.runTests") {
+            run_tests(params.arguments, &ctx).map(ExecuteCommandResponse::RunTests)
         } else {
             debug!("Unknown command: {}", params.command);
             Err(ResponseError::Message(
@@ -508,6 +1290,112 @@ fn apply_suggestion(
     })
 }
 
+fn fix_all(
+    mut args: Vec<serde_json::Value>,
+    ctx: &InitActionContext,
+) -> Result<ApplyWorkspaceEditParams, ResponseError> {
+    ctx.quiescent.store(true, Ordering::SeqCst);
+    let params: FixAllParams = match args.len() {
+        0 => FixAllParams::default(),
+        1 => serde_json::from_value(args.remove(0)).map_err(|e| {
+            ResponseError::Message(ErrorCode::InvalidParams, e.to_string())
+        })?,
+        _ => {
+            return Err(ResponseError::Message(
+                ErrorCode::InvalidParams,
+                "Expected at most one argument".to_owned(),
+            ))
+        }
+    };
+
+    trace!("fix_all {:?}", params);
+
+    let file = match params.uri {
+        Some(ref uri) => Some(parse_file_path!(uri, "fix_all")?),
+        None => None,
+    };
+
+    let mut changes = HashMap::new();
+    let mut changed_files = vec![];
+    let build_results = ctx.previous_build_results.lock().unwrap();
+    for (path, results) in build_results.iter() {
+        if file.as_ref().map_or(false, |file| file != path) {
+            continue;
+        }
+        // The suggestions are for the file as it was built. It may have unsaved
+        // changes which were built, so it's loaded from the VFS, not the disk.
+        let text = match ctx.vfs.load_file(path) {
+            Ok(FileContents::Text(text)) => text,
+            _ => continue,
+        };
+        let uri = match Url::from_file_path(path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let line_index = LineIndex::new(&text);
+        let in_file = |pos| {
+            line_index
+                .position_to_offset(ls_util::position_to_rls(pos))
+                .map_or(false, |offset| offset <= text.len())
+        };
+        // If it has changed since, only the suggestions whose lines haven't
+        // changed still apply, the rest are left for after the next build.
+        let dirty = ctx.build_queue.is_file_dirty(path);
+        let lines: Vec<_> = text.lines().collect();
+        let code = params.code.as_ref().map(String::as_str);
+        let (current, out_of_date): (Vec<_>, Vec<_>) = fixable_suggestions(results, code)
+            .filter(|s| s.machine_applicable)
+            .partition(|s| !dirty || s.is_current(&lines));
+        if !out_of_date.is_empty() {
+            changed_files.push(path.display().to_string());
+        }
+        let text_edits: Vec<_> = diagnostics::fix_all_edits(current.into_iter())
+            .into_iter()
+            .filter(|fix| fix.iter().all(|edit| in_file(edit.range.end)))
+            .flat_map(|fix| fix)
+            .collect();
+        if !text_edits.is_empty() {
+            changes.insert(uri, text_edits);
+        }
+    }
+
+    if changes.is_empty() && !changed_files.is_empty() {
+        return Err(ResponseError::Message(
+            ErrorCode::InvalidParams,
+            format!(
+                "Cannot fix {}, the suggestions are out of date until the next build",
+                changed_files.join(", "),
+            ),
+        ));
+    }
+
+    let edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+    };
+
+    if !ctx.quiescent.load(Ordering::SeqCst) {
+        return Err(ResponseError::Empty);
+    }
+    Ok(ApplyWorkspaceEditParams { edit })
+}
+
+/// The suggestions of the diagnostics in `results`, of those with the lint or
+/// error code `code` if given.
+fn fixable_suggestions<'a>(
+    results: &'a [(Diagnostic, Vec<Suggestion>)],
+    code: Option<&'a str>,
+) -> impl Iterator<Item = &'a Suggestion> {
+    results
+        .iter()
+        .filter(move |(diag, _)| match (code, &diag.code) {
+            (None, _) => true,
+            (Some(code), Some(NumberOrString::String(diag_code))) => code == diag_code.as_str(),
+            _ => false,
+        })
+        .flat_map(|(_, suggestions)| suggestions)
+}
+
 fn apply_deglobs(args: Vec<serde_json::Value>, ctx: &InitActionContext) -> Result<ApplyWorkspaceEditParams, ResponseError> {
     ctx.quiescent.store(true, Ordering::SeqCst);
     let deglob_results: Vec<DeglobResult> = args.into_iter()
@@ -531,7 +1419,202 @@ fn apply_deglobs(args: Vec<serde_json::Value>, ctx: &InitActionContext) -> Resul
     // all deglob results will share the same URI
     let changes: HashMap<_, _> = vec![(uri, text_edits)]
         .into_iter()
-        .collect();
+        .collect();
+
+    let edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+    };
+
+    if !ctx.quiescent.load(Ordering::SeqCst) {
+        return Err(ResponseError::Empty);
+    }
+    Ok(ApplyWorkspaceEditParams { edit })
+}
+
+fn deglob_all(
+    mut args: Vec<serde_json::Value>,
+    ctx: &InitActionContext,
+) -> Result<ApplyWorkspaceEditParams, ResponseError> {
+    ctx.quiescent.store(true, Ordering::SeqCst);
+    let params: DeglobAllParams = match args.len() {
+        0 => DeglobAllParams::default(),
+        1 => serde_json::from_value(args.remove(0)).map_err(|e| {
+            ResponseError::Message(ErrorCode::InvalidParams, e.to_string())
+        })?,
+        _ => {
+            return Err(ResponseError::Message(
+                ErrorCode::InvalidParams,
+                "Expected at most one argument".to_owned(),
+            ))
+        }
+    };
+
+    trace!("deglob_all {:?}", params);
+
+    let files = match params.uri {
+        Some(ref uri) => vec![parse_file_path!(uri, "deglob_all")?],
+        None => WalkDir::new(&ctx.current_project)
+            .into_iter()
+            .filter_entry(|e| {
+                let name = e.file_name().to_string_lossy();
+                e.depth() == 0 || !(name == "target" || name.starts_with('.'))
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| e.path().extension().map_or(false, |ext| ext == "rs"))
+            .map(|e| e.path().to_owned())
+            .collect(),
+    };
+
+    let mut changes = HashMap::new();
+    for file in files {
+        let text = match ctx.vfs.load_file(&file) {
+            Ok(FileContents::Text(text)) => text,
+            _ => continue,
+        };
+        let uri = match Url::from_file_path(&file) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let line_index = LineIndex::new(&text);
+        let text_edits: Vec<_> =
+            imports::glob_imports(&text, params.skip_preludes, params.skip_test_modules)
+                .into_iter()
+                .filter_map(|offset| {
+                    let span = Span::from_positions(
+                        line_index.offset_to_position(offset),
+                        line_index.offset_to_position(offset + 1),
+                        file.clone(),
+                    );
+                    deglob(ctx, span)
+                })
+                .map(|res| TextEdit {
+                    range: res.location.range,
+                    new_text: res.new_text,
+                })
+                .collect();
+        if !text_edits.is_empty() {
+            changes.insert(uri, text_edits);
+        }
+    }
+
+    let edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+    };
+
+    if !ctx.quiescent.load(Ordering::SeqCst) {
+        return Err(ResponseError::Empty);
+    }
+    Ok(ApplyWorkspaceEditParams { edit })
+}
+
+fn extract(
+    mut args: Vec<serde_json::Value>,
+    ctx: &InitActionContext,
+) -> Result<ApplyWorkspaceEditParams, ResponseError> {
+    ctx.quiescent.store(true, Ordering::SeqCst);
+    if args.len() != 1 {
+        return Err(ResponseError::Message(
+            ErrorCode::InvalidParams,
+            "Expected a single argument".to_owned(),
+        ));
+    }
+    let params: ExtractParams = serde_json::from_value(args.remove(0)).map_err(|e| {
+        ResponseError::Message(ErrorCode::InvalidParams, e.to_string())
+    })?;
+
+    trace!("extract {:?}", params);
+
+    let file_path = parse_file_path!(&params.uri, "extract")?;
+    let invalid = |msg| ResponseError::Message(ErrorCode::InvalidParams, msg);
+    // Which variables the code uses is known for the file as it was built.
+    if ctx.build_queue.is_file_dirty(&file_path) {
+        return Err(invalid(format!(
+            "Cannot extract from {}, it has changed since the last build",
+            file_path.display()
+        )));
+    }
+    let text = match ctx.vfs.load_file(&file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return Err(invalid(format!("Cannot read {}", file_path.display()))),
+    };
+    let line_index = LineIndex::new(&text);
+    let offset = |pos| line_index.position_to_offset(ls_util::position_to_rls(pos));
+    let (start, end) = match (offset(params.range.start), offset(params.range.end)) {
+        (Some(start), Some(end)) if start < end && end <= text.len() => (start, end),
+        _ => return Err(invalid("Invalid range".to_owned())),
+    };
+
+    let edits = match params.kind {
+        ExtractKind::Variable => extract::variable_edits(ctx, &file_path, &text, start, end),
+        ExtractKind::Function => extract::function_edits(ctx, &file_path, &text, start, end),
+    };
+    let text_edits = offset_edits(&line_index, edits.map_err(&invalid)?);
+    let changes: HashMap<_, _> = vec![(params.uri, text_edits)].into_iter().collect();
+
+    let edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+    };
+
+    if !ctx.quiescent.load(Ordering::SeqCst) {
+        return Err(ResponseError::Empty);
+    }
+    Ok(ApplyWorkspaceEditParams { edit })
+}
+
+/// Converts edits, as the byte offsets of the text to replace and its
+/// replacement, to `TextEdit`s.
+fn offset_edits(line_index: &LineIndex, edits: Vec<(usize, usize, String)>) -> Vec<TextEdit> {
+    let to_position = |offset| ls_util::rls_to_position(line_index.offset_to_position(offset));
+    edits
+        .into_iter()
+        .map(|(start, end, new_text)| TextEdit {
+            range: Range::new(to_position(start), to_position(end)),
+            new_text,
+        })
+        .collect()
+}
+
+fn inline(
+    mut args: Vec<serde_json::Value>,
+    ctx: &InitActionContext,
+) -> Result<ApplyWorkspaceEditParams, ResponseError> {
+    ctx.quiescent.store(true, Ordering::SeqCst);
+    if args.len() != 1 {
+        return Err(ResponseError::Message(
+            ErrorCode::InvalidParams,
+            "Expected a single argument".to_owned(),
+        ));
+    }
+    let params: InlineParams = serde_json::from_value(args.remove(0)).map_err(|e| {
+        ResponseError::Message(ErrorCode::InvalidParams, e.to_string())
+    })?;
+
+    trace!("inline {:?}", params);
+
+    let file_path = parse_file_path!(&params.uri, "inline")?;
+    let invalid = |msg| ResponseError::Message(ErrorCode::InvalidParams, msg);
+    // The references are known for the file as it was built.
+    if ctx.build_queue.is_file_dirty(&file_path) {
+        return Err(invalid(format!(
+            "Cannot inline in {}, it has changed since the last build",
+            file_path.display()
+        )));
+    }
+    let text = match ctx.vfs.load_file(&file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return Err(invalid(format!("Cannot read {}", file_path.display()))),
+    };
+    let span = ctx.convert_pos_to_span(file_path.clone(), params.position);
+    let edits = match params.kind {
+        InlineKind::Variable => inline::variable_edits(ctx, &span, &text),
+        InlineKind::Function => inline::function_edits(ctx, &span, &text),
+    };
+    let text_edits = offset_edits(&LineIndex::new(&text), edits.map_err(&invalid)?);
+    let changes: HashMap<_, _> = vec![(params.uri, text_edits)].into_iter().collect();
 
     let edit = WorkspaceEdit {
         changes: Some(changes),
@@ -544,13 +1627,32 @@ fn apply_deglobs(args: Vec<serde_json::Value>, ctx: &InitActionContext) -> Resul
     Ok(ApplyWorkspaceEditParams { edit })
 }
 
+fn run_tests(
+    mut args: Vec<serde_json::Value>,
+    ctx: &InitActionContext,
+) -> Result<TestRun, ResponseError> {
+    if args.len() != 1 {
+        return Err(ResponseError::Message(
+            ErrorCode::InvalidParams,
+            "Expected a single argument".to_owned(),
+        ));
+    }
+    let params: RunTestsParams = serde_json::from_value(args.remove(0)).map_err(|e| {
+        ResponseError::Message(ErrorCode::InvalidParams, e.to_string())
+    })?;
+
+    trace!("run_tests {:?}", params);
+    TestRun::new(ctx, params)
+        .map_err(|msg| ResponseError::Message(ErrorCode::InvalidParams, msg))
+}
+
 /// Create `CodeActions` for fixes suggested by the compiler
 /// the results are appended to `code_actions_result`
 fn make_suggestion_fix_actions(
     params: &<CodeAction as lsp_data::request::Request>::Params,
     file_path: &Path,
     ctx: &InitActionContext,
-    code_actions_result: &mut <CodeAction as RequestAction>::Response,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
 ) {
     // search for compiler suggestions
     if let Some(results) = ctx.previous_build_results.lock().unwrap().get(file_path) {
@@ -570,9 +1672,501 @@ fn make_suggestion_fix_actions(
                 command: format!("rls.applySuggestion-{}", ctx.pid),
                 arguments: Some(vec![span, new_text]),
             };
-            code_actions_result.push(cmd);
+            code_actions_result.push(command_action(cmd, code_action_kind::QUICKFIX));
+        }
+    }
+}
+
+/// Create `CodeActions` for applying all of the compiler's machine applicable
+/// suggestions in the file, and those for each lint or error code reported in
+/// range, when there's more than one. The results are appended to
+/// `code_actions_result`
+fn make_fix_all_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    let build_results = ctx.previous_build_results.lock().unwrap();
+    let results = match build_results.get(file_path) {
+        Some(results) => results,
+        None => return,
+    };
+
+    let mut codes = vec![];
+    let mut fixable_in_range = false;
+    for (diag, suggestions) in results {
+        let is_fixable = suggestions.iter().any(|s| s.machine_applicable);
+        if !is_fixable || !diag.range.overlaps(&params.range) {
+            continue;
+        }
+        fixable_in_range = true;
+        match diag.code {
+            Some(NumberOrString::String(ref code)) if !code.is_empty() => codes.push(code.as_str()),
+            _ => {}
+        }
+    }
+    if !fixable_in_range {
+        return;
+    }
+    codes.sort();
+    codes.dedup();
+
+    let mut push_command = |title: String, code: Option<&str>| {
+        let fix_count = diagnostics::fix_all_edits(fixable_suggestions(results, code)).len();
+        if fix_count < 2 {
+            return;
+        }
+        let fix_all_params = FixAllParams {
+            uri: Some(params.text_document.uri.clone()),
+            code: code.map(str::to_owned),
+        };
+        let cmd = Command {
+            title,
+            command: format!("rls.fixAll-{}", ctx.pid),
+            arguments: Some(vec![serde_json::to_value(&fix_all_params).unwrap()]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::QUICKFIX));
+    };
+    for code in codes {
+        push_command(format!("Fix all `{}` in file", code), Some(code));
+    }
+    push_command("Fix all compiler suggestions in file".to_owned(), None);
+}
+
+/// Create a `CodeAction` for merging, sorting and removing unused imports in
+/// the file, the result is appended to `code_actions_result`
+fn make_organize_imports_action(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    // The unused imports are where they were when the file was built.
+    if ctx.build_queue.is_file_dirty(file_path) {
+        return;
+    }
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let line_index = LineIndex::new(&text);
+    let to_offset = |pos| line_index.position_to_offset(ls_util::position_to_rls(pos));
+
+    // The imports rustc reported as unused, and the spans it suggests removing.
+    let mut unused = vec![];
+    if let Some(results) = ctx.previous_build_results.lock().unwrap().get(file_path) {
+        let unused_imports = results.iter().filter(|(diag, _)| match diag.code {
+            Some(NumberOrString::String(ref code)) => code == "unused_imports",
+            _ => false,
+        });
+        for (diag, suggestions) in unused_imports {
+            let ranges = suggestions.iter().map(|s| s.range).chain(Some(diag.range));
+            for range in ranges {
+                if let (Some(start), Some(end)) = (to_offset(range.start), to_offset(range.end)) {
+                    unused.push((start, end));
+                }
+            }
+        }
+    }
+
+    let reorder = ctx.fmt_config().get_rustfmt_config().reorder_imports();
+    if let Some((start, end, new_text)) = imports::organize_imports(&text, &unused, reorder) {
+        let to_position = |offset| ls_util::rls_to_position(line_index.offset_to_position(offset));
+        let location = Location {
+            uri: params.text_document.uri.clone(),
+            range: Range::new(to_position(start), to_position(end)),
+        };
+        let cmd = Command {
+            title: "Organize imports".to_owned(),
+            command: format!("rls.applySuggestion-{}", ctx.pid),
+            arguments: Some(vec![
+                serde_json::to_value(&location).unwrap(),
+                serde_json::to_value(&new_text).unwrap(),
+            ]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::SOURCE_ORGANIZE_IMPORTS));
+    }
+}
+
+/// Create `CodeActions` for implementing the items missing from the trait
+/// impls in range, which rustc reports as E0046. The results are appended to
+/// `code_actions_result`
+fn make_trait_items_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    // The diagnostics are where they were when the file was built.
+    if ctx.build_queue.is_file_dirty(file_path) {
+        return;
+    }
+    let missing: Vec<_> = match ctx.previous_build_results.lock().unwrap().get(file_path) {
+        Some(results) => results
+            .iter()
+            .filter(|(diag, _)| match diag.code {
+                Some(NumberOrString::String(ref code)) => code == "E0046",
+                _ => false,
+            })
+            .filter(|(diag, _)| diag.range.overlaps(&params.range))
+            .map(|(diag, _)| (diag.range, trait_items::missing_item_names(&diag.message)))
+            .collect(),
+        None => return,
+    };
+    if missing.is_empty() {
+        return;
+    }
+
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let line_index = LineIndex::new(&text);
+    let analysis = &ctx.analysis;
+
+    for (range, names) in missing {
+        let trait_impl = line_index
+            .position_to_offset(ls_util::position_to_rls(range.start))
+            .and_then(|offset| trait_items::trait_impl(&text, offset));
+        let trait_impl = match trait_impl {
+            Some(trait_impl) => trait_impl,
+            None => continue,
+        };
+        let (name_start, name_end) = trait_impl.trait_name;
+        let name = Span::from_positions(
+            line_index.offset_to_position(name_start),
+            line_index.offset_to_position(name_end),
+            file_path.to_owned(),
+        );
+        let def = match analysis.id(&name).and_then(|id| analysis.get_def(id)) {
+            Ok(def) => def,
+            Err(_) => continue,
+        };
+        if def.kind != DefKind::Trait {
+            continue;
+        }
+
+        // The trait may be in another file, or another crate.
+        let trait_text = match ctx.vfs.load_file(&def.span.file) {
+            Ok(FileContents::Text(text)) => text,
+            _ => continue,
+        };
+        let stubs = match LineIndex::new(&trait_text).position_to_offset(def.span.range.start()) {
+            Some(offset) => {
+                trait_items::item_stubs(&trait_text, offset, &trait_impl.trait_args, &names)
+            }
+            None => continue,
+        };
+        if stubs.is_empty() {
+            continue;
+        }
+        let items = match format_impl_items(&stubs.join("\n\n"), ctx) {
+            Some(items) => items,
+            None => continue,
+        };
+
+        let (start, end, new_text) = trait_impl.insert_items(&text, &items);
+        let to_position = |offset| ls_util::rls_to_position(line_index.offset_to_position(offset));
+        let location = Location {
+            uri: params.text_document.uri.clone(),
+            range: Range::new(to_position(start), to_position(end)),
+        };
+        let cmd = Command {
+            title: format!("Implement missing items of `{}`", def.name),
+            command: format!("rls.applySuggestion-{}", ctx.pid),
+            arguments: Some(vec![
+                serde_json::to_value(&location).unwrap(),
+                serde_json::to_value(&new_text).unwrap(),
+            ]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::QUICKFIX));
+    }
+}
+
+/// Create `CodeActions` for adding an arm for each variant missing from the
+/// `match` expressions in range, which rustc reports as E0004. The results are
+/// appended to `code_actions_result`
+fn make_match_arms_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    // The diagnostics are where they were when the file was built.
+    if ctx.build_queue.is_file_dirty(file_path) {
+        return;
+    }
+    let uncovered: Vec<_> = match ctx.previous_build_results.lock().unwrap().get(file_path) {
+        Some(results) => results
+            .iter()
+            .filter(|(diag, _)| match diag.code {
+                Some(NumberOrString::String(ref code)) => code == "E0004",
+                _ => false,
+            })
+            .filter(|(diag, _)| diag.range.overlaps(&params.range))
+            .map(|(diag, _)| (diag.range, match_arms::uncovered_variants(&diag.message)))
+            .collect(),
+        None => return,
+    };
+    if uncovered.is_empty() {
+        return;
+    }
+
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let line_index = LineIndex::new(&text);
+    let analysis = &ctx.analysis;
+    let span = |(start, end)| {
+        Span::from_positions(
+            line_index.offset_to_position(start),
+            line_index.offset_to_position(end),
+            file_path.to_owned(),
+        )
+    };
+    let indent = {
+        let fmt_config = ctx.fmt_config();
+        let config = fmt_config.get_rustfmt_config();
+        if config.hard_tabs() {
+            "\t".to_owned()
+        } else {
+            " ".repeat(config.tab_spaces())
+        }
+    };
+
+    for (range, uncovered) in uncovered {
+        let match_expr = line_index
+            .position_to_offset(ls_util::position_to_rls(range.start))
+            .and_then(|offset| match_arms::match_expr(&text, offset));
+        let match_expr = match match_expr {
+            Some(match_expr) => match_expr,
+            None => continue,
+        };
+
+        // The enum, from a variant which is already matched, or else from the
+        // type of the scrutinee. The new arms name the variants the same way.
+        let matched_variant = match_expr
+            .variants
+            .iter()
+            .filter_map(|variant| {
+                let id = analysis.id(&span(variant.name)).ok()?;
+                let def = analysis.get_def(id).ok()?;
+                match def.kind {
+                    DefKind::TupleVariant | DefKind::StructVariant => {
+                        def.parent.map(|parent| (parent, variant))
+                    }
+                    _ => None,
+                }
+            })
+            .next();
+        let (enum_id, prefix) = match matched_variant {
+            Some((enum_id, variant)) => (enum_id, variant.prefix.clone()),
+            None => {
+                let ty = match match_expr.scrutinee {
+                    Some(scrutinee) => analysis.show_type(&span(scrutinee)).unwrap_or_default(),
+                    None => continue,
+                };
+                let enums: Vec<_> = type_defs(analysis, &ty)
+                    .into_iter()
+                    .filter(|def| def.kind == DefKind::Enum)
+                    .collect();
+                if enums.len() != 1 {
+                    continue;
+                }
+                match analysis.id(&enums[0].span) {
+                    Ok(enum_id) => (enum_id, format!("{}::", enums[0].name)),
+                    Err(_) => continue,
+                }
+            }
+        };
+
+        let matched: Vec<_> = match_expr
+            .variants
+            .iter()
+            .map(|variant| &text[variant.name.0..variant.name.1])
+            .collect();
+        let arms: Vec<_> = analysis
+            .for_each_child_def(enum_id, |_, def| {
+                if matched.contains(&&*def.name) && !uncovered.contains(&def.name) {
+                    return None;
+                }
+                let pattern = match_arms::variant_pattern(&prefix, &def.name, def.kind, &def.value);
+                Some(format!("{} => unimplemented!(),", pattern))
+            })
+            .unwrap_or_else(|_| vec![])
+            .into_iter()
+            .filter_map(|arm| arm)
+            .collect();
+        if arms.is_empty() {
+            continue;
+        }
+
+        let (start, end, new_text) = match_expr.insert_arms(&text, &arms, &indent);
+        let to_position = |offset| ls_util::rls_to_position(line_index.offset_to_position(offset));
+        let location = Location {
+            uri: params.text_document.uri.clone(),
+            range: Range::new(to_position(start), to_position(end)),
+        };
+        let cmd = Command {
+            title: "Add missing match arms".to_owned(),
+            command: format!("rls.applySuggestion-{}", ctx.pid),
+            arguments: Some(vec![
+                serde_json::to_value(&location).unwrap(),
+                serde_json::to_value(&new_text).unwrap(),
+            ]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::QUICKFIX));
+    }
+}
+
+/// Create `CodeActions` for extracting the selected expression into a variable,
+/// or the selected statements into a function. The results are appended to
+/// `code_actions_result`
+fn make_extract_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    if params.range.start == params.range.end || ctx.build_queue.is_file_dirty(file_path) {
+        return;
+    }
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let line_index = LineIndex::new(&text);
+    let offset = |pos| line_index.position_to_offset(ls_util::position_to_rls(pos));
+    let (start, end) = match (offset(params.range.start), offset(params.range.end)) {
+        (Some(start), Some(end)) if start < end && end <= text.len() => (start, end),
+        _ => return,
+    };
+
+    // The commands only check the selection's syntax here, the edits are
+    // computed when they're executed.
+    let mut push_command = |title: &str, kind| {
+        let args = ExtractParams {
+            uri: params.text_document.uri.clone(),
+            range: params.range,
+            kind,
+        };
+        let cmd = Command {
+            title: title.to_owned(),
+            command: format!("rls.extract-{}", ctx.pid),
+            arguments: Some(vec![serde_json::to_value(&args).unwrap()]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::REFACTOR_EXTRACT));
+    };
+    if extract::variable(&text, start, end).is_some() {
+        push_command("Extract into variable", ExtractKind::Variable);
+    }
+    if extract::statements(&text, start, end).is_some() {
+        push_command("Extract into function", ExtractKind::Function);
+    }
+}
+
+/// Create `CodeActions` for inlining the local variable, or the call of the
+/// function, at the start of the range. The results are appended to
+/// `code_actions_result`
+fn make_inline_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    if ctx.build_queue.is_file_dirty(file_path) {
+        return;
+    }
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let span = ctx.convert_pos_to_span(file_path.to_owned(), params.range.start);
+    let name = match ctx.vfs.load_span(span.clone()) {
+        Ok(ref name) if !name.is_empty() => name.clone(),
+        _ => return,
+    };
+
+    // Only the syntax is checked here, the edits are computed when the command
+    // is executed.
+    let line_index = LineIndex::new(&text);
+    let analysis = &ctx.analysis;
+    let decl = match analysis.id(&span).and_then(|id| analysis.get_def(id)) {
+        Ok(ref def) if def.kind == DefKind::Local && def.span.file == span.file => {
+            line_index.position_to_offset(def.span.range.start())
+        }
+        _ => None,
+    };
+    let is_let_binding = decl.and_then(|decl| inline::let_binding(&text, decl)).is_some();
+    let is_call = line_index
+        .position_to_offset(span.range.start())
+        .and_then(|offset| inline::call(&text, offset))
+        .is_some();
+
+    let mut push_command = |title: String, kind| {
+        let args = InlineParams {
+            uri: params.text_document.uri.clone(),
+            position: params.range.start,
+            kind,
+        };
+        let cmd = Command {
+            title,
+            command: format!("rls.inline-{}", ctx.pid),
+            arguments: Some(vec![serde_json::to_value(&args).unwrap()]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::REFACTOR_INLINE));
+    };
+    if is_let_binding {
+        push_command(format!("Inline variable `{}`", name), InlineKind::Variable);
+    }
+    if is_call {
+        push_command(format!("Inline call of `{}`", name), InlineKind::Function);
+    }
+}
+
+/// Formats `items` with the project's rustfmt config, as the items of an impl
+/// at the top level, i.e., indented by one level.
+pub fn format_impl_items(items: &str, ctx: &InitActionContext) -> Option<String> {
+    let input = FmtInput::Text(format!("impl Stubs {{\n{}\n}}\n", items));
+    let fmt_config = ctx.fmt_config();
+    let mut buf = Vec::<u8>::new();
+    match format_input(input, fmt_config.get_rustfmt_config(), Some(&mut buf)) {
+        Ok((ref summary, ..))
+            if !summary.has_operational_errors() && !summary.has_parsing_errors() => {}
+        Ok((summary, ..)) => {
+            debug!("format_impl_items: format_input failed: has errors, summary = {:?}", summary);
+            return None;
+        }
+        Err(e) => {
+            debug!("format_impl_items: format_input failed: {:?}", e);
+            return None;
         }
     }
+
+    // Strip the `impl Stubs {` and `}` lines.
+    let text = String::from_utf8(buf).ok()?;
+    let text = text.trim_right();
+    let start = text.find('\n')? + 1;
+    let end = text.rfind('\n')?;
+    if start > end {
+        return None;
+    }
+    Some(text[start..end].to_owned())
+}
+
+/// Formats `statements` with the project's rustfmt config, as if at the top
+/// level.
+pub fn format_statements(statements: &str, ctx: &InitActionContext) -> Option<String> {
+    let function = format_impl_items(&format!("fn stub() {{\n{}\n}}", statements), ctx)?;
+    // Strip the `fn stub() {` and `}` lines.
+    let start = function.find('\n')? + 1;
+    let end = function.rfind('\n')?;
+    if start > end {
+        return None;
+    }
+    Some(extract::reindent(&function[start..end], ""))
 }
 
 /// Create `CodeActions` for performing deglobbing when a wildcard import is found
@@ -581,7 +2175,7 @@ fn make_deglob_actions(
     params: &<CodeAction as lsp_data::request::Request>::Params,
     file_path: &Path,
     ctx: &InitActionContext,
-    code_actions_result: &mut <CodeAction as RequestAction>::Response,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
 ) {
     // search for a glob in the line
     if let Ok(line) = ctx.vfs
@@ -600,26 +2194,10 @@ fn make_deglob_actions(
                 span.range.col_start = span::Column::new_zero_indexed(index as u32);
                 span.range.col_end = span::Column::new_zero_indexed(index as u32 + 1);
 
-                // load the deglob type information
-                ctx.analysis.show_type(&span)
-                    .ok()
-                    .map(|ty| (ty, span))
-            })
-            .map(|(mut deglob_str, span)| {
-                // Handle multiple imports from one *
-                if deglob_str.contains(',') || deglob_str.is_empty() {
-                    deglob_str = format!("{{{}}}", sort_deglob_str(&deglob_str));
-                }
-
-                // Build result
-                let deglob_result = DeglobResult {
-                    location: ls_util::rls_to_location(&span),
-                    new_text: deglob_str,
-                };
-
-                // Convert to json
-                serde_json::to_value(&deglob_result).unwrap()
+                deglob(ctx, span)
             })
+            // Convert to json
+            .map(|deglob_result| serde_json::to_value(&deglob_result).unwrap())
             .collect();
 
         if !deglob_results.is_empty() {
@@ -632,11 +2210,149 @@ fn make_deglob_actions(
                 command: format!("rls.deglobImports-{}", ctx.pid),
                 arguments: Some(deglob_results),
             };
-            code_actions_result.push(cmd);
+            code_actions_result.push(command_action(cmd, code_action_kind::REFACTOR_REWRITE));
+
+            let deglob_all_params = DeglobAllParams {
+                uri: Some(params.text_document.uri.clone()),
+                ..DeglobAllParams::default()
+            };
+            let cmd = Command {
+                title: "Deglob all imports in file".to_owned(),
+                command: format!("rls.deglobAll-{}", ctx.pid),
+                arguments: Some(vec![serde_json::to_value(&deglob_all_params).unwrap()]),
+            };
+            code_actions_result.push(command_action(cmd, code_action_kind::REFACTOR_REWRITE));
         }
     };
 }
 
+/// Deglobs the wildcard import whose `*` is at `span`.
+fn deglob(ctx: &InitActionContext, span: Span) -> Option<DeglobResult> {
+    // load the deglob type information
+    let mut deglob_str = ctx.analysis.show_type(&span).ok()?;
+
+    // Handle multiple imports from one *
+    if deglob_str.contains(',') || deglob_str.is_empty() {
+        deglob_str = format!("{{{}}}", sort_deglob_str(&deglob_str));
+    }
+
+    Some(DeglobResult {
+        location: ls_util::rls_to_location(&span),
+        new_text: deglob_str,
+    })
+}
+
+/// Create `CodeActions` for importing an unresolved name at the start of the
+/// range, from the definitions in the workspace and its dependencies. The
+/// results are appended to `code_actions_result`
+fn make_import_actions(
+    params: &<CodeAction as lsp_data::request::Request>::Params,
+    file_path: &Path,
+    ctx: &InitActionContext,
+    code_actions_result: &mut Vec<lsp_data::CodeAction>,
+) {
+    let span = ctx.convert_pos_to_span(file_path.to_owned(), params.range.start);
+    let name = match ctx.vfs.load_span(span.clone()) {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    if rename::check_new_name(&name).is_err() || ctx.analysis.id(&span).is_ok() {
+        return;
+    }
+    let text = match ctx.vfs.load_file(file_path) {
+        Ok(FileContents::Text(text)) => text,
+        _ => return,
+    };
+    let line_index = LineIndex::new(&text);
+    let offset = match line_index.position_to_offset(span.range.start()) {
+        Some(offset) => offset,
+        None => return,
+    };
+    // Fields, methods and the later segments of paths are resolved elsewhere.
+    let before = match text.get(..offset) {
+        Some(before) => before.trim_right(),
+        None => return,
+    };
+    if before.ends_with('.') || before.ends_with("::") {
+        return;
+    }
+
+    let package = imports::package_dir(file_path);
+    let query = SymbolQuery::subsequence(&name);
+    let mut paths: Vec<_> = ctx
+        .analysis
+        .query_defs(query)
+        .unwrap_or_else(|_| vec![])
+        .into_iter()
+        .filter(|def| def.name == name)
+        .filter(|def| match def.kind {
+            DefKind::Mod
+            | DefKind::Struct
+            | DefKind::Enum
+            | DefKind::Union
+            | DefKind::Tuple
+            | DefKind::Trait
+            | DefKind::Type
+            | DefKind::Function
+            | DefKind::Const
+            | DefKind::Static => true,
+            _ => false,
+        })
+        // The paths of items in the standard library are to where they're
+        // defined rather than re-exported, rustc suggests imports for those.
+        .filter(|def| !def.distro_crate && !def.qualname.contains('<'))
+        .filter(|def| is_pub_def(ctx, def))
+        .filter_map(|def| {
+            let path = def.qualname.trim_left_matches("::");
+            let def_package = imports::package_dir(&def.span.file);
+            if def_package == package {
+                return Some(path.to_owned());
+            }
+            let target = run::find_cargo_target(ctx, &def.span.file)?;
+            if target.kind != TargetKind::Lib {
+                return None;
+            }
+            Some(format!("{}::{}", target.name.replace('-', "_"), path))
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    for path in paths {
+        let (insert_offset, new_text) = match imports::insert_use(&text, offset, &path) {
+            Some(insert) => insert,
+            None => continue,
+        };
+        let position = ls_util::rls_to_position(line_index.offset_to_position(insert_offset));
+        let location = Location {
+            uri: params.text_document.uri.clone(),
+            range: Range::new(position, position),
+        };
+        let cmd = Command {
+            title: format!("Import `{}`", path),
+            command: format!("rls.applySuggestion-{}", ctx.pid),
+            arguments: Some(vec![
+                serde_json::to_value(&location).unwrap(),
+                serde_json::to_value(&new_text).unwrap(),
+            ]),
+        };
+        code_actions_result.push(command_action(cmd, code_action_kind::QUICKFIX));
+    }
+}
+
+/// Is `def` declared `pub`, so that it can be imported from other modules?
+fn is_pub_def(ctx: &InitActionContext, def: &Def) -> bool {
+    match ctx.vfs.load_line(&def.span.file, def.span.range.row_start) {
+        Ok(line) => line
+            .chars()
+            .take(def.span.range.col_start.0 as usize)
+            .collect::<String>()
+            .split_whitespace()
+            .any(|word| word.starts_with("pub")),
+        Err(_) => false,
+    }
+}
+
 // Ideally we'd use Rustfmt for this, but reparsing is a bit of a pain.
 fn sort_deglob_str(s: &str) -> String {
     let mut substrings = s.split(',').map(|s| s.trim()).collect::<Vec<_>>();
@@ -644,11 +2360,21 @@ fn sort_deglob_str(s: &str) -> String {
     substrings.join(", ")
 }
 
+/// A `CodeAction` of `kind` which runs `command`.
+fn command_action(command: Command, kind: &str) -> lsp_data::CodeAction {
+    lsp_data::CodeAction {
+        title: command.title.clone(),
+        kind: Some(kind.to_owned()),
+        edit: None,
+        command: Some(command),
+    }
+}
+
 impl RequestAction for CodeAction {
-    type Response = Vec<Command>;
+    type Response = CodeActionResponse;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
-        Ok(vec![])
+        Ok(CodeActionResponse::Commands(vec![]))
     }
 
     fn handle(
@@ -662,16 +2388,28 @@ impl RequestAction for CodeAction {
         let mut cmds = vec![];
         if ctx.build_ready() {
             make_suggestion_fix_actions(&params, &file_path, &ctx, &mut cmds);
+            make_fix_all_actions(&params, &file_path, &ctx, &mut cmds);
+            make_organize_imports_action(&params, &file_path, &ctx, &mut cmds);
         }
         if ctx.analysis_ready() {
             make_deglob_actions(&params, &file_path, &ctx, &mut cmds);
+            make_import_actions(&params, &file_path, &ctx, &mut cmds);
+            make_trait_items_actions(&params, &file_path, &ctx, &mut cmds);
+            make_match_arms_actions(&params, &file_path, &ctx, &mut cmds);
+            make_extract_actions(&params, &file_path, &ctx, &mut cmds);
+            make_inline_actions(&params, &file_path, &ctx, &mut cmds);
+        }
+        if ctx.client_capabilities.code_action_literal_support {
+            Ok(CodeActionResponse::Actions(cmds))
+        } else {
+            let cmds = cmds.into_iter().filter_map(|action| action.command).collect();
+            Ok(CodeActionResponse::Commands(cmds))
         }
-        Ok(cmds)
     }
 }
 
 impl RequestAction for Formatting {
-    type Response = [TextEdit; 1];
+    type Response = Vec<TextEdit>;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
         Err(ResponseError::Message(
@@ -689,7 +2427,7 @@ impl RequestAction for Formatting {
 }
 
 impl RequestAction for RangeFormatting {
-    type Response = [TextEdit; 1];
+    type Response = Vec<TextEdit>;
 
     fn fallback_response() -> Result<Self::Response, ResponseError> {
         Err(ResponseError::Message(
@@ -716,7 +2454,7 @@ fn reformat(
     selection: Option<Range>,
     opts: &FormattingOptions,
     ctx: &InitActionContext,
-) -> Result<[TextEdit; 1], ResponseError> {
+) -> Result<Vec<TextEdit>, ResponseError> {
     ctx.quiescent.store(true, Ordering::SeqCst);
     trace!(
         "Reformat: {:?} {:?} {} {}",
@@ -727,8 +2465,8 @@ fn reformat(
     );
     let path = parse_file_path!(&doc.uri, "reformat")?;
 
-    let input = match ctx.vfs.load_file(&path) {
-        Ok(FileContents::Text(s)) => FmtInput::Text(s),
+    let original = match ctx.vfs.load_file(&path) {
+        Ok(FileContents::Text(s)) => s,
         Ok(_) => {
             debug!("Reformat failed, found binary file");
             return Err(ResponseError::Message(
@@ -745,7 +2483,6 @@ fn reformat(
         }
     };
 
-    let range_whole_file = ls_util::range_from_vfs_file(&ctx.vfs, &path);
     let mut config = ctx.fmt_config().get_rustfmt_config().clone();
     if !config.was_set().hard_tabs() {
         config.set().hard_tabs(!opts.insert_spaces);
@@ -767,7 +2504,7 @@ fn reformat(
     };
 
     let mut buf = Vec::<u8>::new();
-    match format_input(input, &config, Some(&mut buf)) {
+    match format_input(FmtInput::Text(original.clone()), &config, Some(&mut buf)) {
         Ok((summary, ..)) => {
             // format_input returns Ok even if there are any errors, i.e., parsing errors.
             if !summary.has_operational_errors() && !summary.has_parsing_errors() {
@@ -782,14 +2519,14 @@ fn reformat(
                     ))
                 }
 
-                // If Rustfmt returns range of text that changed,
-                // we will be able to pass only range of changed text to the client.
-                Ok([
-                    TextEdit {
-                        range: range_whole_file,
-                        new_text: text,
-                    },
-                ])
+                // Only send the changed lines, replacing the whole file would
+                // move the cursor and lose the client's markers. Rustfmt may
+                // format more than the selection, e.g., the whole item.
+                let mut edits = diff::line_edits(&original, &text);
+                if let Some(r) = selection {
+                    edits.retain(|edit| is_in_selection(&edit.range, &r));
+                }
+                Ok(edits)
             } else {
                 debug!(
                     "reformat: format_input failed: has errors, summary = {:?}",
@@ -813,6 +2550,19 @@ fn reformat(
     }
 }
 
+/// Whether a formatting edit of `range` changes the text in `selection`. Line
+/// edits end at the start of the next line, so only insertions may touch the
+/// selection without changing it; an empty selection is a cursor in a line.
+fn is_in_selection(range: &Range, selection: &Range) -> bool {
+    if range.start == range.end {
+        selection.start <= range.start && range.start <= selection.end
+    } else if selection.start == selection.end {
+        range.start <= selection.start && selection.start < range.end
+    } else {
+        range.start < selection.end && selection.start < range.end
+    }
+}
+
 impl RequestAction for ResolveCompletion {
     type Response = CompletionItem;
 
@@ -874,9 +2624,10 @@ impl RequestAction for CodeLensRequest {
         params: Self::Params,
     ) -> Result<Self::Response, ResponseError> {
 
+        let file_path = parse_file_path!(&params.text_document.uri, "code_lens")?;
+
         let mut ret = Vec::new();
         if ctx.client_supports_cmd_run {
-            let file_path = parse_file_path!(&params.text_document.uri, "code_lens")?;
             for action in collect_run_actions(&ctx, &file_path) {
                 let command = Command {
                     title: action.label,
@@ -892,10 +2643,110 @@ impl RequestAction for CodeLensRequest {
                 ret.push(lens);
             }
         }
+
+        // The count lenses run `rls.showReferences`, which the client must provide.
+        if !ctx.client_supports_cmd_show_references {
+            return Ok(ret);
+        }
+
+        let mut symbols = ctx.analysis.symbols(&file_path).unwrap_or_else(|_| vec![]);
+        // With `all_targets` the same file may be part of several crates.
+        symbols.sort_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+        symbols.dedup_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+        for symbol in symbols {
+            let has_impls = match symbol.kind {
+                DefKind::Struct | DefKind::Enum | DefKind::Union | DefKind::Trait => true,
+                DefKind::Function | DefKind::Method => false,
+                _ => continue,
+            };
+            let range = ls_util::rls_to_range(symbol.span.range);
+            let position = TextDocumentPositionParams::new(
+                params.text_document.clone(),
+                range.start,
+            );
+            // The counts are expensive, so we only compute them when the
+            // lenses are resolved.
+            let mut data = vec![CodeLensData::References(position.clone())];
+            if has_impls {
+                data.push(CodeLensData::Implementations(position));
+            }
+            ret.extend(data.into_iter().map(|data| CodeLens {
+                range,
+                command: None,
+                data: Some(serde_json::to_value(&data).unwrap()),
+            }));
+        }
         Ok(ret)
     }
 }
 
+impl RequestAction for ResolveCodeLens {
+    type Response = CodeLens;
+
+    fn fallback_response() -> Result<Self::Response, ResponseError> {
+        Err(ResponseError::Empty)
+    }
+
+    fn handle(
+        ctx: InitActionContext,
+        params: Self::Params,
+    ) -> Result<Self::Response, ResponseError> {
+        let data = match params.data.clone().map(serde_json::from_value) {
+            Some(Ok(data)) => data,
+            // Not one of our lenses, nothing to resolve.
+            _ => return Ok(params),
+        };
+
+        let (position, locations, noun) = match data {
+            CodeLensData::References(position) => {
+                let file_path = parse_file_path!(&position.text_document.uri, "code_lens")?;
+                let span = ctx.convert_pos_to_span(file_path, position.position);
+                let locations: Vec<_> = ctx.analysis
+                    .find_all_refs(&span, false, false)
+                    .unwrap_or_else(|_| vec![])
+                    .iter()
+                    .map(|span| ls_util::rls_to_location(span))
+                    .collect();
+                (position, locations, "reference")
+            }
+            CodeLensData::Implementations(position) => {
+                let file_path = parse_file_path!(&position.text_document.uri, "code_lens")?;
+                let span = ctx.convert_pos_to_span(file_path, position.position);
+                let locations: Vec<_> = ctx.analysis
+                    .id(&span)
+                    .and_then(|id| ctx.analysis.find_impls(id))
+                    .unwrap_or_else(|_| vec![])
+                    .iter()
+                    .map(|span| ls_util::rls_to_location(span))
+                    .collect();
+                (position, locations, "implementation")
+            }
+        };
+
+        let title = format!(
+            "{} {}{}",
+            locations.len(),
+            noun,
+            if locations.len() == 1 { "" } else { "s" }
+        );
+        let command = Command {
+            title,
+            command: "rls.showReferences".to_string(),
+            arguments: Some(vec![
+                serde_json::to_value(&position.text_document.uri).unwrap(),
+                serde_json::to_value(&position.position).unwrap(),
+                serde_json::to_value(&locations).unwrap(),
+            ]),
+        };
+
+        Ok(CodeLens {
+            range: params.range,
+            command: Some(command),
+            data: None,
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -908,4 +2759,33 @@ mod test {
         assert_eq!(sort_deglob_str("b, a"), "a, b");
         assert_eq!(sort_deglob_str("foo, bar, baz"), "bar, baz, foo");
     }
+
+    #[test]
+    fn test_type_path() {
+        assert_eq!(type_path("Foo"), Some("Foo"));
+        assert_eq!(type_path("&'a mut foo::Bar<u32>"), Some("foo::Bar"));
+        assert_eq!(type_path("std::vec::Vec<Foo>"), Some("std::vec::Vec"));
+        assert_eq!(type_path("[Foo; 4]"), Some("Foo"));
+        assert_eq!(type_path("&[u8]"), Some("u8"));
+        assert_eq!(type_path("Box<dyn Fn()>"), Some("Box"));
+        assert_eq!(type_path("(u32, u32)"), None);
+        assert_eq!(type_path(""), None);
+    }
+
+    #[test]
+    fn test_is_in_selection() {
+        let range = |start, end| Range::new(Position::new(start, 0), Position::new(end, 0));
+        let selection = range(2, 4);
+        assert!(is_in_selection(&range(1, 3), &selection));
+        assert!(is_in_selection(&range(3, 4), &selection));
+        assert!(!is_in_selection(&range(1, 2), &selection));
+        assert!(!is_in_selection(&range(4, 5), &selection));
+        assert!(is_in_selection(&range(2, 2), &selection));
+        assert!(is_in_selection(&range(4, 4), &selection));
+        assert!(!is_in_selection(&range(5, 5), &selection));
+
+        let cursor = range(2, 2);
+        assert!(is_in_selection(&range(2, 3), &cursor));
+        assert!(!is_in_selection(&range(1, 2), &cursor));
+    }
 }
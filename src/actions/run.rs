@@ -1,55 +1,57 @@
 use actions::InitActionContext;
+use actions::lex::{self, Token, TokenKind};
+use analysis::DefKind;
+use cargo_metadata;
 use ordslice::Ext;
-use regex::Regex;
 use span::{Column, Position, Range, Row, ZeroIndexed};
 use vfs::FileContents;
 
-use std::{collections::HashMap, iter, path::Path};
+use std::{collections::HashMap, iter, path::{Path, PathBuf}, sync::Arc};
 
 pub fn collect_run_actions(ctx: &InitActionContext, file: &Path) -> Vec<RunAction> {
-    let text = match ctx.vfs.load_file(file) {
-        Ok(FileContents::Text(text)) => text,
-        Ok(FileContents::Binary(_)) => return Vec::new(),
-        Err(e) => {
-            error!("failed to collect run actions: {}", e);
-            return Vec::new();
-        }
+    let text = match load_text(ctx, file) {
+        Some(text) => text,
+        None => return Vec::new(),
     };
-    if !text.contains("#[test]") {
+
+    let runnables = find_runnables(ctx, file, &text);
+    if runnables.is_empty() {
         return Vec::new();
     }
+    let target = find_cargo_target(ctx, file);
+    trace!("collect_run_actions: {:?} belongs to {:?}", file, target);
 
-    lazy_static! {
-        static ref TEST_FN_RE: Regex = Regex::new(r"#\[test\]\s*\n\s*fn\s*(?P<name>\w+)").unwrap();
-    }
+    runnables
+        .into_iter()
+        .filter_map(|runnable| runnable.into_run_action(target.as_ref()))
+        .collect()
+}
 
-    let line_index = LineIndex::new(&text);
+/// Returns the libtest paths of the tests in `file` and the ranges of their names.
+pub fn collect_tests(ctx: &InitActionContext, file: &Path) -> Vec<(String, Range<ZeroIndexed>)> {
+    let text = match load_text(ctx, file) {
+        Some(text) => text,
+        None => return Vec::new(),
+    };
 
-    let mut ret = Vec::new();
-    for caps in TEST_FN_RE.captures_iter(&text) {
-        let group = caps.name("name").unwrap();
-        let target_element = Range::from_positions(
-            line_index.offset_to_position(group.start()),
-            line_index.offset_to_position(group.end()),
-        );
-        let test_name = group.as_str();
-        let run_action = RunAction {
-            label: "Run test".to_string(),
-            target_element,
-            cmd: Cmd {
-                binary: "cargo".to_string(),
-                args: vec![
-                    "test".to_string(),
-                    "--".to_string(),
-                    "--nocapture".to_string(),
-                    test_name.to_string(),
-                ],
-                env: iter::once(("RUST_BACKTRACE".to_string(), "short".to_string())).collect(),
-            },
-        };
-        ret.push(run_action);
+    find_runnables(ctx, file, &text)
+        .into_iter()
+        .filter_map(|runnable| match runnable.kind {
+            RunnableKind::Test(path) => Some((path, runnable.range)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn load_text(ctx: &InitActionContext, file: &Path) -> Option<String> {
+    match ctx.vfs.load_file(file) {
+        Ok(FileContents::Text(text)) => Some(text),
+        Ok(FileContents::Binary(_)) => None,
+        Err(e) => {
+            error!("failed to collect run actions: {}", e);
+            None
+        }
     }
-    ret
 }
 
 pub struct RunAction {
@@ -63,9 +65,483 @@ pub struct Cmd {
     pub binary: String,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    /// The Cargo target `args` select, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<CargoTarget>,
+}
+
+impl Cmd {
+    fn cargo(args: Vec<String>, target: Option<&CargoTarget>) -> Cmd {
+        Cmd {
+            binary: "cargo".to_string(),
+            args,
+            env: iter::once(("RUST_BACKTRACE".to_string(), "short".to_string())).collect(),
+            target: target.cloned(),
+        }
+    }
+}
+
+/// Something in a file which can be run.
+#[derive(Debug, PartialEq)]
+enum RunnableKind {
+    /// The `main` function of a binary or example.
+    Main,
+    /// A test function, with its path relative to its crate root as understood
+    /// by libtest, e.g., `tests::foo`.
+    Test(String),
+    /// A module containing tests.
+    TestModule(String),
+    /// A `#[bench]` function.
+    Bench(String),
+    /// The doc tests of the item with the given path.
+    Doctest(String),
+}
+
+#[derive(Debug)]
+struct Runnable {
+    /// Where the code lens should be shown.
+    range: Range<ZeroIndexed>,
+    kind: RunnableKind,
+}
+
+impl Runnable {
+    fn into_run_action(self, target: Option<&CargoTarget>) -> Option<RunAction> {
+        let target_args = |args: &mut Vec<String>| {
+            if let Some(target) = target {
+                args.extend(target.args());
+            }
+        };
+
+        let (label, args) = match self.kind {
+            RunnableKind::Main => {
+                let target = match target {
+                    Some(t) if t.kind == TargetKind::Bin || t.kind == TargetKind::Example => t,
+                    _ => return None,
+                };
+                let mut args = vec!["run".to_string()];
+                args.extend(target.args());
+                ("Run", args)
+            }
+            RunnableKind::Test(path) => {
+                let mut args = vec!["test".to_string()];
+                target_args(&mut args);
+                args.extend(vec![
+                    "--".to_string(),
+                    "--nocapture".to_string(),
+                    "--exact".to_string(),
+                    path,
+                ]);
+                ("Run test", args)
+            }
+            RunnableKind::TestModule(path) => {
+                let mut args = vec!["test".to_string()];
+                target_args(&mut args);
+                args.extend(vec![
+                    "--".to_string(),
+                    "--nocapture".to_string(),
+                    format!("{}::", path),
+                ]);
+                ("Run all tests", args)
+            }
+            RunnableKind::Bench(path) => {
+                let mut args = vec!["bench".to_string()];
+                target_args(&mut args);
+                args.extend(vec!["--".to_string(), "--exact".to_string(), path]);
+                ("Bench", args)
+            }
+            RunnableKind::Doctest(path) => {
+                // Only libraries have doc tests, and `--doc` can't be combined
+                // with other target selection flags.
+                let target = match target {
+                    Some(t) if t.kind == TargetKind::Lib => t,
+                    _ => return None,
+                };
+                let args = vec![
+                    "test".to_string(),
+                    "--doc".to_string(),
+                    "-p".to_string(),
+                    target.package.clone(),
+                    "--".to_string(),
+                    path,
+                ];
+                ("Run doctest", args)
+            }
+        };
+
+        Some(RunAction {
+            label: label.to_string(),
+            target_element: self.range,
+            cmd: Cmd::cargo(args, target),
+        })
+    }
 }
 
+/// A def found in the save-analysis data for a file.
+struct Item {
+    kind: DefKind,
+    /// Byte offset of the item's name.
+    offset: usize,
+    range: Range<ZeroIndexed>,
+    /// The path of the item relative to its crate root.
+    path: String,
+}
+
+// Finds everything in `file` which can be run, using the save-analysis data
+// for the structure of the file and the text for attributes and doc comments.
+fn find_runnables(ctx: &InitActionContext, file: &Path, text: &str) -> Vec<Runnable> {
+    let mut symbols = match ctx.analysis.symbols(file) {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            debug!("failed to collect run actions: {:?}", e);
+            return Vec::new();
+        }
+    };
+    // With `all_targets` the same file may be part of several crates.
+    symbols.sort_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+    symbols.dedup_by_key(|s| (s.span.range.row_start, s.span.range.col_start));
+
+    let line_index = LineIndex::new(text);
+    let tokens = lex::tokenize(text);
+
+    let items: Vec<Item> = symbols
+        .into_iter()
+        .filter_map(|s| {
+            let offset = line_index.position_to_offset(s.span.range.start())?;
+            let path = ctx.analysis
+                .get_def(s.id)
+                .map(|def| item_path(&def.qualname))
+                .unwrap_or(s.name);
+            Some(Item {
+                kind: s.kind,
+                offset,
+                range: s.span.range,
+                path,
+            })
+        })
+        .collect();
+
+    let mut runnables = vec![];
+    for item in items.iter().filter(|item| item.kind == DefKind::Function) {
+        let attributes = item_attributes(text, &tokens, item.offset);
+        let kind = if attributes.iter().any(|attr| is_test_attribute(attr)) {
+            RunnableKind::Test(item.path.clone())
+        } else if attributes.iter().any(|attr| attr == "bench") {
+            RunnableKind::Bench(item.path.clone())
+        } else if item.path == "main" {
+            RunnableKind::Main
+        } else {
+            continue;
+        };
+        runnables.push(Runnable {
+            range: item.range,
+            kind,
+        });
+    }
+
+    for item in items.iter().filter(|item| item.kind == DefKind::Mod && !item.path.is_empty()) {
+        let prefix = format!("{}::", item.path);
+        let has_tests = runnables.iter().any(|r| match r.kind {
+            RunnableKind::Test(ref path) => path.starts_with(&prefix),
+            _ => false,
+        });
+        if has_tests {
+            runnables.push(Runnable {
+                range: item.range,
+                kind: RunnableKind::TestModule(item.path.clone()),
+            });
+        }
+    }
+
+    for (doc_end, fence) in doctest_fences(text, &tokens) {
+        // Doc comments document the next item.
+        if let Some(item) = items.iter().find(|item| item.offset >= doc_end) {
+            runnables.push(Runnable {
+                range: Range::from_positions(
+                    line_index.offset_to_position(fence.0),
+                    line_index.offset_to_position(fence.1),
+                ),
+                kind: RunnableKind::Doctest(item.path.clone()),
+            });
+        }
+    }
+
+    runnables.sort_by_key(|r| (r.range.row_start, r.range.col_start));
+    runnables
+}
+
+/// Returns the paths of the attributes on the item whose name starts at
+/// `name_offset`, e.g., `["test", "should_panic"]`.
+fn item_attributes(src: &str, tokens: &[Token], name_offset: usize) -> Vec<String> {
+    let mut i = match tokens.iter().position(|t| t.start == name_offset) {
+        Some(i) => i,
+        None => return vec![],
+    };
+
+    // Skip the item keyword and qualifiers, e.g., `pub(crate) unsafe extern "C" fn`.
+    while i > 0 {
+        let t = &tokens[i - 1];
+        let is_qualifier = match t.kind {
+            TokenKind::Ident => true,
+            TokenKind::Literal => true,
+            TokenKind::Punct('(') | TokenKind::Punct(')') => true,
+            _ => false,
+        };
+        if !is_qualifier {
+            break;
+        }
+        i -= 1;
+    }
+
+    let mut attributes = vec![];
+    while i > 0 {
+        let t = &tokens[i - 1];
+        if t.is_comment() {
+            i -= 1;
+            continue;
+        }
+        if !t.is_punct(']') {
+            break;
+        }
+
+        // Find the matching `[`.
+        let mut depth = 0;
+        let mut open = None;
+        for j in (0..i).rev() {
+            if tokens[j].is_punct(']') {
+                depth += 1;
+            } else if tokens[j].is_punct('[') {
+                depth -= 1;
+                if depth == 0 {
+                    open = Some(j);
+                    break;
+                }
+            }
+        }
+        let open = match open {
+            Some(open) if open > 0 && tokens[open - 1].is_punct('#') => open,
+            _ => break,
+        };
+
+        let path: String = tokens[open + 1..i - 1]
+            .iter()
+            .take_while(|t| t.kind == TokenKind::Ident || t.is_punct(':'))
+            .map(|t| t.text(src))
+            .collect();
+        attributes.push(path);
+        i = open - 1;
+    }
+    attributes.reverse();
+    attributes
+}
+
+// `#[test]`, or a custom test attribute such as `#[tokio::test]`.
+fn is_test_attribute(path: &str) -> bool {
+    path == "test" || path.ends_with("::test")
+}
+
+// Converts a save-analysis qualname (`::tests::foo`, `<Foo>::bar`) into a path
+// relative to the crate root, as used by libtest and rustdoc.
+fn item_path(qualname: &str) -> String {
+    qualname
+        .trim_left_matches("::")
+        .chars()
+        .filter(|&c| c != '<' && c != '>')
+        .collect()
+}
+
+// Finds outer doc comments containing Rust code blocks. Returns the end offset
+// of each doc comment and the offsets of the first code fence in it.
+fn doctest_fences(src: &str, tokens: &[Token]) -> Vec<(usize, (usize, usize))> {
+    let is_outer_doc = |t: &Token| {
+        t.is_doc_comment(src) && !t.text(src).starts_with("//!") && !t.text(src).starts_with("/*!")
+    };
+
+    let mut result = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        if !is_outer_doc(&tokens[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < tokens.len() && is_outer_doc(&tokens[i]) {
+            i += 1;
+        }
+        let doc = &tokens[start..i];
+
+        let mut in_code_block = false;
+        let mut fence = None;
+        for t in doc {
+            let mut line_start = t.start;
+            for line in t.text(src).split('\n') {
+                let content = line.trim_left()
+                    .trim_left_matches("///")
+                    .trim_left_matches("/**")
+                    .trim_left_matches('*')
+                    .trim();
+                if content.starts_with("```") {
+                    if !in_code_block && fence.is_none() && is_rust_fence(&content[3..]) {
+                        let offset = line_start + (line.len() - line.trim_left().len());
+                        fence = Some((offset, line_start + line.trim_right().len()));
+                    }
+                    in_code_block = !in_code_block;
+                }
+                line_start += line.len() + 1;
+            }
+        }
+        if let Some(fence) = fence {
+            result.push((doc[doc.len() - 1].end, fence));
+        }
+    }
+    result
+}
+
+// Is the info string of a code block one which rustdoc will test?
+fn is_rust_fence(info: &str) -> bool {
+    info.split(|c| c == ',' || c == ' ')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .all(|attr| match attr {
+            "rust" | "should_panic" | "no_run" | "compile_fail" | "allow_fail" | "test_harness" => {
+                true
+            }
+            _ => attr.starts_with("edition"),
+        })
+}
+
+/// The kind of a Cargo target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Test,
+    Example,
+    Bench,
+}
+
+impl TargetKind {
+    fn from_cargo_kinds(kinds: &[String]) -> Option<TargetKind> {
+        kinds
+            .iter()
+            .filter_map(|kind| match kind.as_str() {
+                "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => {
+                    Some(TargetKind::Lib)
+                }
+                "bin" => Some(TargetKind::Bin),
+                "test" => Some(TargetKind::Test),
+                "example" => Some(TargetKind::Example),
+                "bench" => Some(TargetKind::Bench),
+                _ => None,
+            })
+            .next()
+    }
+}
+
+/// A Cargo target (a crate in a package).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CargoTarget {
+    pub package: String,
+    pub kind: TargetKind,
+    pub name: String,
+}
+
+impl CargoTarget {
+    /// Arguments which select this target for `cargo test`, `cargo run`, etc.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["-p".to_string(), self.package.clone()];
+        let flag = match self.kind {
+            TargetKind::Lib => {
+                args.push("--lib".to_string());
+                return args;
+            }
+            TargetKind::Bin => "--bin",
+            TargetKind::Test => "--test",
+            TargetKind::Example => "--example",
+            TargetKind::Bench => "--bench",
+        };
+        args.push(flag.to_string());
+        args.push(self.name.clone());
+        args
+    }
+}
+
+/// The Cargo targets of a workspace and the paths of their root files.
+pub type CargoTargets = Vec<(CargoTarget, PathBuf)>;
+
+/// Find the target that `file` belongs to in the project.
+pub fn find_cargo_target(ctx: &InitActionContext, file: &Path) -> Option<CargoTarget> {
+    let targets = cargo_targets(ctx)?;
+    select_target(file, &targets).cloned()
+}
+
+/// The Cargo targets of the project. These are cached, as reading them runs
+/// `cargo metadata`, until a manifest changes.
+pub fn cargo_targets(ctx: &InitActionContext) -> Option<Arc<CargoTargets>> {
+    let mut cached = ctx.cargo_targets.lock().unwrap();
+    if cached.is_none() {
+        *cached = read_cargo_targets(&ctx.current_project).map(Arc::new);
+    }
+    cached.clone()
+}
+
+fn read_cargo_targets(project: &Path) -> Option<CargoTargets> {
+    let manifest_path = project.join("Cargo.toml");
+    let metadata = match cargo_metadata::metadata(Some(&manifest_path)) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            debug!("read_cargo_targets: could not read metadata: {:?}", e);
+            return None;
+        }
+    };
+
+    let targets = metadata
+        .packages
+        .iter()
+        .flat_map(|package| {
+            package.targets.iter().filter_map(move |target| {
+                let kind = TargetKind::from_cargo_kinds(&target.kind)?;
+                let cargo_target = CargoTarget {
+                    package: package.name.clone(),
+                    kind,
+                    name: target.name.clone(),
+                };
+                Some((cargo_target, PathBuf::from(&target.src_path)))
+            })
+        })
+        .collect();
+    Some(targets)
+}
+
+// We don't know the module tree of a target, so pick the target whose root
+// file is `file` or, failing that, whose root directory most closely contains
+// `file`. Libraries are preferred over binaries, etc.
+fn select_target<'a>(
+    file: &Path,
+    targets: &'a [(CargoTarget, PathBuf)],
+) -> Option<&'a CargoTarget> {
+    if let Some(&(ref target, _)) = targets.iter().find(|&&(_, ref src_path)| src_path == file) {
+        return Some(target);
+    }
+
+    targets
+        .iter()
+        .filter_map(|&(ref target, ref src_path)| {
+            let root_dir = src_path.parent()?;
+            if file.starts_with(root_dir) {
+                Some((root_dir.components().count(), target))
+            } else {
+                None
+            }
+        })
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.kind.cmp(&a.1.kind)))
+        .map(|(_, target)| target)
+}
+
+/// Converts between byte offsets in a text and positions, whose columns are
+/// counted in characters.
 pub struct LineIndex {
+    text: String,
     newlines: Vec<usize>,
 }
 
@@ -77,16 +553,183 @@ impl LineIndex {
             .filter(|&(_i, b)| b == b'\n')
             .map(|(i, _b)| i + 1);
         let newlines = iter::once(0).chain(newlines).collect();
-        LineIndex { newlines }
+        LineIndex {
+            text: text.to_owned(),
+            newlines,
+        }
     }
 
     pub fn offset_to_position(&self, offset: usize) -> Position<ZeroIndexed> {
         let line = self.newlines.upper_bound(&offset) - 1;
         let line_start_offset = self.newlines[line];
-        let col = offset - line_start_offset;
+        let col = self.text[line_start_offset..]
+            .char_indices()
+            .take_while(|&(i, _)| line_start_offset + i < offset)
+            .count();
         Position::new(
             Row::new_zero_indexed(line as u32),
             Column::new_zero_indexed(col as u32),
         )
     }
+
+    /// Returns the column of the byte offset `offset` in UTF-16 code units, as
+    /// LSP counts them.
+    pub fn utf16_column(&self, offset: usize) -> usize {
+        let line_start_offset = self.newlines[self.newlines.upper_bound(&offset) - 1];
+        self.text[line_start_offset..offset].encode_utf16().count()
+    }
+
+    /// Returns the byte offset of `pos`, or `None` if it is past the end of
+    /// its line, or its row is past the end of the text.
+    pub fn position_to_offset(&self, pos: Position<ZeroIndexed>) -> Option<usize> {
+        let row = pos.row.0 as usize;
+        let line_start_offset = *self.newlines.get(row)?;
+        let line_end_offset = self.newlines.get(row + 1).map_or(self.text.len(), |&next| next - 1);
+        let line = &self.text[line_start_offset..line_end_offset];
+        line.char_indices()
+            .map(|(i, _)| i)
+            .chain(iter::once(line.len()))
+            .nth(pos.col.0 as usize)
+            .map(|i| line_start_offset + i)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn attributes(src: &str, name: &str) -> Vec<String> {
+        let tokens = lex::tokenize(src);
+        let offset = src.find(name).unwrap();
+        item_attributes(src, &tokens, offset)
+    }
+
+    #[test]
+    fn test_line_index() {
+        let text = "fn f() {\n    g(\"é\", Foo);\n}";
+        let line_index = LineIndex::new(text);
+        let foo = text.find("Foo").unwrap();
+        let pos = Position::new(Row::new_zero_indexed(1), Column::new_zero_indexed(11));
+        assert_eq!(line_index.offset_to_position(foo), pos);
+        assert_eq!(line_index.position_to_offset(pos), Some(foo));
+
+        let line_end = Position::new(Row::new_zero_indexed(1), Column::new_zero_indexed(16));
+        assert_eq!(line_index.position_to_offset(line_end), Some(text.rfind('\n').unwrap()));
+        let past_line_end = Position::new(Row::new_zero_indexed(1), Column::new_zero_indexed(17));
+        assert_eq!(line_index.position_to_offset(past_line_end), None);
+        let past_text_end = Position::new(Row::new_zero_indexed(3), Column::new_zero_indexed(0));
+        assert_eq!(line_index.position_to_offset(past_text_end), None);
+
+        let text = "g(\"🦀\", Foo)";
+        assert_eq!(LineIndex::new(text).utf16_column(text.find("Foo").unwrap()), 8);
+    }
+
+    #[test]
+    fn test_item_attributes() {
+        assert_eq!(attributes("#[test]\nfn foo() {}", "foo"), vec!["test"]);
+        assert_eq!(
+            attributes("#[test]\n#[should_panic(expected = \"]\")]\npub fn foo() {}", "foo"),
+            vec!["test", "should_panic"]
+        );
+        assert_eq!(
+            attributes("#[tokio::test]\n/// Docs.\nasync fn foo() {}", "foo"),
+            vec!["tokio::test"]
+        );
+        assert_eq!(
+            attributes("#[test] fn bar() {}\nfn foo() {}", "foo"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            attributes("#[cfg(test)]\nmod tests {\n    #[test]\n    fn foo() {}\n}", "foo"),
+            vec!["test"]
+        );
+    }
+
+    #[test]
+    fn test_is_test_attribute() {
+        assert!(is_test_attribute("test"));
+        assert!(is_test_attribute("tokio::test"));
+        assert!(!is_test_attribute("cfg"));
+        assert!(!is_test_attribute("contest"));
+    }
+
+    #[test]
+    fn test_item_path() {
+        assert_eq!(item_path("::tests::foo"), "tests::foo");
+        assert_eq!(item_path("::main"), "main");
+        assert_eq!(item_path("<Foo>::bar"), "Foo::bar");
+    }
+
+    #[test]
+    fn test_doctest_fences() {
+        fn fences(src: &str) -> Vec<&str> {
+            doctest_fences(src, &lex::tokenize(src))
+                .into_iter()
+                .map(|(end, (start, fence_end))| {
+                    assert!(src[end..].trim_left().starts_with("fn"));
+                    &src[start..fence_end]
+                })
+                .collect()
+        }
+
+        assert_eq!(
+            fences("/// Foo.\n///\n/// ```\n/// foo();\n/// ```\nfn foo() {}"),
+            vec!["/// ```"]
+        );
+        assert_eq!(
+            fences("/// ```text\n/// foo\n/// ```\n/// ```rust,no_run\n/// ```\nfn foo() {}"),
+            vec!["/// ```rust,no_run"]
+        );
+        assert_eq!(
+            fences("/**\n * ```\n * foo();\n * ```\n */\nfn foo() {}"),
+            vec!["* ```"]
+        );
+        assert_eq!(
+            fences("/// ```ignore\n/// ```\nfn foo() {}\n//! ```\n// ```\nfn bar() {}"),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn test_is_rust_fence() {
+        assert!(is_rust_fence(""));
+        assert!(is_rust_fence("rust"));
+        assert!(is_rust_fence("should_panic, edition2018"));
+        assert!(!is_rust_fence("ignore"));
+        assert!(!is_rust_fence("text"));
+        assert!(!is_rust_fence("rust,ignore"));
+    }
+
+    #[test]
+    fn test_select_target() {
+        fn target(kind: TargetKind, name: &str, src_path: &str) -> (CargoTarget, PathBuf) {
+            let target = CargoTarget {
+                package: "foo".to_owned(),
+                kind,
+                name: name.to_owned(),
+            };
+            (target, PathBuf::from(src_path))
+        }
+
+        let targets = vec![
+            target(TargetKind::Bin, "foo", "/foo/src/main.rs"),
+            target(TargetKind::Lib, "foo", "/foo/src/lib.rs"),
+            target(TargetKind::Bin, "bar", "/foo/src/bin/bar.rs"),
+            target(TargetKind::Test, "baz", "/foo/tests/baz.rs"),
+        ];
+        let select = |file: &str| select_target(Path::new(file), &targets).map(|t| t.name.clone());
+        let kind = |file: &str| select_target(Path::new(file), &targets).map(|t| t.kind);
+
+        assert_eq!(kind("/foo/src/main.rs"), Some(TargetKind::Bin));
+        assert_eq!(kind("/foo/src/a/b.rs"), Some(TargetKind::Lib));
+        assert_eq!(select("/foo/src/bin/bar.rs"), Some("bar".to_owned()));
+        assert_eq!(select("/foo/tests/baz.rs"), Some("baz".to_owned()));
+        assert_eq!(select("/other/src/lib.rs"), None);
+
+        assert_eq!(
+            targets[2].0.args(),
+            vec!["-p", "foo", "--bin", "bar"]
+        );
+        assert_eq!(targets[1].0.args(), vec!["-p", "foo", "--lib"]);
+    }
 }
@@ -0,0 +1,466 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Computing selection ranges for `textDocument/selectionRange`.
+//!
+//! Like folding, this only uses the text of the file, so that selections can be
+//! expanded in code which has been edited since the last build, or which
+//! doesn't build at all.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::run::LineIndex;
+use lsp_data::{ls_util, Position, Range, SelectionRange};
+use span;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::iter;
+
+// Keywords which start a statement or item ending with a block, rather than
+// with a `;`.
+const BLOCK_KEYWORDS: &[&str] = &[
+    "async", "default", "enum", "extern", "fn", "for", "if", "impl", "loop", "macro_rules",
+    "match", "mod", "struct", "trait", "union", "unsafe", "while",
+];
+
+/// Finds the selection ranges around each of `positions` in `text`. Each range
+/// is the innermost of a chain of ranges from the token at the position out to
+/// the whole file, via the enclosing expressions, statements, blocks and items.
+pub fn selection_ranges(text: &str, positions: &[Position]) -> Vec<SelectionRange> {
+    let selector = Selector {
+        src: text,
+        tokens: lex::tokenize(text),
+        line_index: LineIndex::new(text),
+    };
+    let pairs = lex::delimiter_pairs(&selector.tokens);
+
+    positions
+        .iter()
+        .map(|&pos| {
+            let offset = selector
+                .line_index
+                .position_to_offset(ls_util::position_to_rls(pos))
+                .map_or(text.len(), |offset| cmp::min(offset, text.len()));
+            selector.selection_range(offset, &pairs)
+        })
+        .collect()
+}
+
+struct Selector<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    line_index: LineIndex,
+}
+
+impl<'a> Selector<'a> {
+    fn selection_range(&self, offset: usize, pairs: &HashMap<usize, usize>) -> SelectionRange {
+        let tokens = &self.tokens;
+        // Byte ranges, from the innermost outwards.
+        let mut spans = vec![];
+
+        let token = tokens
+            .iter()
+            .filter(|t| t.start <= offset && offset <= t.end)
+            .find(|t| match t.kind {
+                TokenKind::Punct(_) => false,
+                _ => true,
+            });
+        if let Some(t) = token {
+            spans.push((t.start, t.end));
+        }
+
+        // The delimited groups containing `offset`, from the innermost outwards.
+        let mut groups: Vec<(usize, usize)> = pairs
+            .iter()
+            .map(|(&open, &close)| (open, close))
+            .filter(|&(open, close)| {
+                open < close && tokens[open].end <= offset && offset <= tokens[close].start
+            })
+            .collect();
+        groups.sort_by(|a, b| b.0.cmp(&a.0));
+
+        for group in groups.iter().map(Some).chain(iter::once(None)) {
+            let (first, end) = match group {
+                Some(&(open, close)) => (open + 1, close),
+                None => (0, tokens.len()),
+            };
+            let list = match group {
+                Some(&(open, _)) if !tokens[open].is_punct('{') => true,
+                Some(_) => self.is_list(first, end, pairs),
+                None => false,
+            };
+
+            if let Some((start, last)) = self.segment(first, end, list, offset, pairs) {
+                if let Some(rhs) = self.right_hand_side(start, last, offset, pairs) {
+                    spans.push(rhs);
+                }
+                spans.push((tokens[start].start, tokens[last].end));
+            }
+            if let Some(&(open, close)) = group {
+                if first < close {
+                    spans.push((tokens[first].start, tokens[close - 1].end));
+                }
+                spans.push((tokens[open].start, tokens[close].end));
+            }
+        }
+        spans.push((0, self.src.len()));
+
+        // Drop any ranges which don't strictly contain the previous one.
+        let mut nested: Vec<(usize, usize)> = vec![];
+        for span in spans {
+            if let Some(&last) = nested.last() {
+                if span == last || span.0 > last.0 || span.1 < last.1 {
+                    continue;
+                }
+            }
+            nested.push(span);
+        }
+
+        let mut result = None;
+        for &(start, end) in nested.iter().rev() {
+            result = Some(SelectionRange {
+                range: self.range(start, end),
+                parent: result.map(Box::new),
+            });
+        }
+        result.expect("no selection ranges")
+    }
+
+    fn range(&self, start: usize, end: usize) -> Range {
+        ls_util::rls_to_range(span::Range::from_positions(
+            self.line_index.offset_to_position(start),
+            self.line_index.offset_to_position(end),
+        ))
+    }
+
+    /// Finds the statement, item or list element among the tokens `first..end`
+    /// which contains `offset`. Returns the indices of its first and last
+    /// tokens, not counting any separating comma.
+    fn segment(
+        &self,
+        first: usize,
+        end: usize,
+        list: bool,
+        offset: usize,
+        pairs: &HashMap<usize, usize>,
+    ) -> Option<(usize, usize)> {
+        let tokens = &self.tokens;
+        let mut start = first;
+        // Commas inside generic parameters don't separate list elements.
+        let mut angle_depth = 0;
+        let mut i = first;
+        while i < end {
+            let t = &tokens[i];
+            let mut last = None;
+            match t.kind {
+                TokenKind::Punct('(') | TokenKind::Punct('[') | TokenKind::Punct('{') => {
+                    if let Some(&close) = pairs.get(&i) {
+                        if t.is_punct('{') && !list && self.ends_statement(start, i, close, end)
+                            || self.is_arm_body(i) && close + 1 < end
+                                && !tokens[close + 1].is_punct(',')
+                        {
+                            last = Some(close);
+                        }
+                        i = close;
+                    }
+                }
+                TokenKind::Punct('<') => angle_depth += 1,
+                TokenKind::Punct('>')
+                    if i > 0 && !self.joined(i - 1, '-', '>') && !self.joined(i - 1, '=', '>') =>
+                {
+                    angle_depth = cmp::max(angle_depth - 1, 0);
+                }
+                TokenKind::Punct(';') => last = Some(i),
+                TokenKind::Punct(',') if list && angle_depth == 0 => {
+                    if let Some(found) = self.find_segment(start, i, offset) {
+                        return Some(found);
+                    }
+                    start = i + 1;
+                    angle_depth = 0;
+                }
+                _ => {}
+            }
+
+            if let Some(last) = last {
+                if let Some(found) = self.find_segment(start, last + 1, offset) {
+                    return Some(found);
+                }
+                start = last + 1;
+                if start < end && list && tokens[start].is_punct(',') {
+                    start += 1;
+                    i += 1;
+                }
+                angle_depth = 0;
+            }
+            i += 1;
+        }
+
+        self.find_segment(start, end, offset)
+    }
+
+    // The first and last tokens of `start..end`, skipping any leading non-doc
+    // comments, if they contain `offset`. Comments on a statement are selected
+    // with the statement they precede, but not on their own.
+    fn find_segment(&self, start: usize, end: usize, offset: usize) -> Option<(usize, usize)> {
+        let tokens = &self.tokens;
+        let start = (start..end).find(|&i| {
+            let t = &tokens[i];
+            !t.is_comment() || t.is_doc_comment(self.src)
+        })?;
+        if tokens[start].start <= offset && offset <= tokens[end - 1].end {
+            Some((start, end - 1))
+        } else {
+            None
+        }
+    }
+
+    // Does the block `open..close` end the statement or item starting at
+    // `start`?
+    fn ends_statement(&self, start: usize, open: usize, close: usize, end: usize) -> bool {
+        if close + 1 < end && self.tokens[close + 1].is_ident(self.src, "else") {
+            return false;
+        }
+        match self.lead(start, open) {
+            Some(lead) if lead == open => true,
+            Some(lead) => {
+                let t = &self.tokens[lead];
+                t.kind == TokenKind::Ident && BLOCK_KEYWORDS.contains(&t.text(self.src))
+            }
+            None => false,
+        }
+    }
+
+    // Is the brace group within `first..end` a list, like the fields of a struct
+    // or the arms of a match, rather than a sequence of statements or items?
+    fn is_list(&self, first: usize, end: usize, pairs: &HashMap<usize, usize>) -> bool {
+        let mut i = first;
+        while i < end {
+            let t = &self.tokens[i];
+            if t.is_punct(';') {
+                return false;
+            }
+            if t.is_punct('=') && i + 1 < end && self.joined(i, '=', '>') {
+                return true;
+            }
+            i = match pairs.get(&i) {
+                Some(&close) if close > i => close + 1,
+                _ => i + 1,
+            };
+        }
+        match self.lead(first, end) {
+            Some(lead) => {
+                let t = &self.tokens[lead];
+                t.kind != TokenKind::Punct('{')
+                    && !(t.kind == TokenKind::Ident && BLOCK_KEYWORDS.contains(&t.text(self.src)))
+            }
+            None => true,
+        }
+    }
+
+    // The first token from `start` which isn't a comment, an attribute or a
+    // visibility qualifier.
+    fn lead(&self, start: usize, end: usize) -> Option<usize> {
+        let tokens = &self.tokens;
+        let mut i = start;
+        while i < end {
+            let t = &tokens[i];
+            if t.is_comment() {
+                i += 1;
+            } else if t.is_punct('#') {
+                i += 1;
+                if i < end && tokens[i].is_punct('!') {
+                    i += 1;
+                }
+                if i < end && tokens[i].is_punct('[') {
+                    i = self.after_group(i);
+                }
+            } else if t.is_ident(self.src, "pub") {
+                i += 1;
+                if i < end && tokens[i].is_punct('(') {
+                    i = self.after_group(i);
+                }
+            } else {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn after_group(&self, open: usize) -> usize {
+        lex::delimiter_pairs(&self.tokens[open..])
+            .get(&0)
+            .map_or(open + 1, |&close| open + close + 1)
+    }
+
+    // Is `{` at `open` the body of a match arm?
+    fn is_arm_body(&self, open: usize) -> bool {
+        open >= 2 && self.joined(open - 2, '=', '>')
+    }
+
+    // Are the tokens at `i` and `i + 1` the characters `a` and `b` with no
+    // space between them?
+    fn joined(&self, i: usize, a: char, b: char) -> bool {
+        let tokens = &self.tokens;
+        i + 1 < tokens.len() && tokens[i].is_punct(a) && tokens[i + 1].is_punct(b)
+            && tokens[i].end == tokens[i + 1].start
+    }
+
+    // The expression on the right of an assignment, initialiser or match arm
+    // within the tokens `start..last + 1`, if it contains `offset`.
+    fn right_hand_side(
+        &self,
+        start: usize,
+        last: usize,
+        offset: usize,
+        pairs: &HashMap<usize, usize>,
+    ) -> Option<(usize, usize)> {
+        let tokens = &self.tokens;
+        let mut i = start;
+        let mut op_end = None;
+        while i <= last {
+            if tokens[i].is_punct('=') {
+                let after = |c| i < last && self.joined(i, '=', c);
+                let before = |c| i > start && self.joined(i - 1, c, '=');
+                if after('>') {
+                    op_end = Some(i + 1);
+                    break;
+                }
+                if !after('=') && !before('=') && !before('<') && !before('>') && !before('!') {
+                    op_end = Some(i);
+                    break;
+                }
+            }
+            i = match pairs.get(&i) {
+                Some(&close) if close > i => close + 1,
+                _ => i + 1,
+            };
+        }
+
+        let rhs_start = op_end? + 1;
+        let rhs_last = if tokens[last].is_punct(';') {
+            last.checked_sub(1)?
+        } else {
+            last
+        };
+        if rhs_start > rhs_last {
+            return None;
+        }
+        let (start, end) = (tokens[rhs_start].start, tokens[rhs_last].end);
+        if start <= offset && offset <= end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The text of each range around the `$0` marker in `src`, innermost first.
+    fn selections(src: &str) -> Vec<String> {
+        let offset = src.find("$0").unwrap();
+        let src = src.replace("$0", "");
+        let line = src[..offset].matches('\n').count();
+        let line_start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let pos = Position::new(line as u64, (offset - line_start) as u64);
+
+        let line_index = LineIndex::new(&src);
+        let offset = |pos: Position| {
+            line_index
+                .position_to_offset(ls_util::position_to_rls(pos))
+                .unwrap()
+        };
+        let mut result = vec![];
+        let mut selection = selection_ranges(&src, &[pos]).into_iter().next();
+        while let Some(s) = selection {
+            result.push(src[offset(s.range.start)..offset(s.range.end)].to_owned());
+            selection = s.parent.map(|p| *p);
+        }
+        result
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let src = r#"mod foo {
+    /// Docs.
+    fn bar(x: u32, y: Vec<u8>) -> u32 {
+        // Comment.
+        let z = baz(x, y$0.len());
+        z
+    }
+}
+"#;
+        let src_no_marker = src.replace("$0", "");
+        assert_eq!(
+            selections(src),
+            vec![
+                "y",
+                "y.len()",
+                "x, y.len()",
+                "(x, y.len())",
+                "baz(x, y.len())",
+                "let z = baz(x, y.len());",
+                "// Comment.\n        let z = baz(x, y.len());\n        z",
+                "{\n        // Comment.\n        let z = baz(x, y.len());\n        z\n    }",
+                "/// Docs.\n    fn bar(x: u32, y: Vec<u8>) -> u32 {\n        // Comment.\n        \
+                 let z = baz(x, y.len());\n        z\n    }",
+                "{\n    /// Docs.\n    fn bar(x: u32, y: Vec<u8>) -> u32 {\n        \
+                 // Comment.\n        let z = baz(x, y.len());\n        z\n    }\n}",
+                &src_no_marker[..src_no_marker.len() - 1],
+                &src_no_marker[..],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_ranges_lists() {
+        assert_eq!(
+            selections("fn foo<A, B>(a: HashMap<A, B>, b$0: u32) {}"),
+            vec![
+                "b",
+                "b: u32",
+                "a: HashMap<A, B>, b: u32",
+                "(a: HashMap<A, B>, b: u32)",
+                "fn foo<A, B>(a: HashMap<A, B>, b: u32) {}",
+            ]
+        );
+        assert_eq!(
+            selections("fn f() {\n    match x {\n        A => {}\n        B => b$0ar(),\n    }\n}"),
+            vec![
+                "bar",
+                "bar()",
+                "B => bar()",
+                "A => {}\n        B => bar(),",
+                "{\n        A => {}\n        B => bar(),\n    }",
+                "match x {\n        A => {}\n        B => bar(),\n    }",
+                "{\n    match x {\n        A => {}\n        B => bar(),\n    }\n}",
+                "fn f() {\n    match x {\n        A => {}\n        B => bar(),\n    }\n}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_ranges_if_else() {
+        let src = "fn f() {\n    if a { b } else { c$0 }\n    d;\n}";
+        assert_eq!(
+            selections(src),
+            vec![
+                "c",
+                "{ c }",
+                "if a { b } else { c }",
+                "if a { b } else { c }\n    d;",
+                "{\n    if a { b } else { c }\n    d;\n}",
+                "fn f() {\n    if a { b } else { c }\n    d;\n}",
+            ]
+        );
+    }
+}
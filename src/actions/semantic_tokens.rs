@@ -0,0 +1,362 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Semantic highlighting, for `textDocument/semanticTokens`.
+//!
+//! Each identifier in the current text of a file is classified by the kind of
+//! the definition it refers to in the save-analysis data. Modifiers which
+//! aren't recorded by save-analysis, such as whether a binding is mutable, come
+//! from the source text around the definition.
+
+use actions::lex::{self, Token, TokenKind};
+use actions::run::LineIndex;
+use analysis::{AnalysisHost, DefKind};
+use lsp_data::{SemanticTokensEdit, SemanticTokensLegend};
+use span::{self, ZeroIndexed};
+use vfs::{FileContents, Vfs};
+use Span;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The names of the token types, in the order of `TokenType`.
+const TOKEN_TYPES: &[&str] = &[
+    "namespace",
+    "type",
+    "struct",
+    "enum",
+    "interface",
+    "enumMember",
+    "function",
+    "method",
+    "macro",
+    "variable",
+    "property",
+];
+
+/// The names of the token modifiers, in the order of their bits.
+const TOKEN_MODIFIERS: &[&str] = &["declaration", "mutable", "unsafe", "static"];
+
+pub const DECLARATION: u32 = 1;
+pub const MUTABLE: u32 = 1 << 1;
+pub const UNSAFE: u32 = 1 << 2;
+pub const STATIC: u32 = 1 << 3;
+
+/// The types of semantic token, whose values are their indices in the legend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    Namespace,
+    Type,
+    Struct,
+    Enum,
+    Interface,
+    EnumMember,
+    Function,
+    Method,
+    Macro,
+    Variable,
+    Property,
+}
+
+impl TokenType {
+    pub fn from_def_kind(k: DefKind) -> TokenType {
+        match k {
+            DefKind::Mod => TokenType::Namespace,
+            DefKind::Type | DefKind::ExternType => TokenType::Type,
+            DefKind::Struct | DefKind::Union | DefKind::Tuple => TokenType::Struct,
+            DefKind::Enum => TokenType::Enum,
+            DefKind::Trait => TokenType::Interface,
+            DefKind::TupleVariant | DefKind::StructVariant => TokenType::EnumMember,
+            DefKind::Function => TokenType::Function,
+            DefKind::Method => TokenType::Method,
+            DefKind::Macro => TokenType::Macro,
+            DefKind::Local | DefKind::Static | DefKind::Const => TokenType::Variable,
+            DefKind::Field => TokenType::Property,
+        }
+    }
+}
+
+/// The legend to register with the client, naming the token types and
+/// modifiers used in encoded tokens.
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.iter().map(|s| s.to_string()).collect(),
+        token_modifiers: TOKEN_MODIFIERS.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub line: u32,
+    pub start: u32,
+    pub length: u32,
+    pub token_type: TokenType,
+    /// A bit set of `DECLARATION`, `MUTABLE`, etc.
+    pub modifiers: u32,
+}
+
+/// Classifies the identifiers in `text`, the contents of `file`, which refer to
+/// a definition known to `analysis`.
+pub fn semantic_tokens(
+    analysis: &AnalysisHost,
+    vfs: &Vfs,
+    file: &Path,
+    text: String,
+) -> Vec<SemanticToken> {
+    let source = Source::new(text);
+    // The sources of other files containing definitions, loaded on demand.
+    let mut def_sources: HashMap<PathBuf, Option<Source>> = HashMap::new();
+    let mut result = vec![];
+
+    for t in source.tokens.iter().filter(|t| t.kind == TokenKind::Ident) {
+        let start = source.line_index.offset_to_position(t.start);
+        let end = source.line_index.offset_to_position(t.end);
+        let span = Span::from_positions(start, end, file.to_owned());
+        let def = match analysis.id(&span).and_then(|id| analysis.get_def(id)) {
+            Ok(def) => def,
+            Err(_) => continue,
+        };
+
+        let def_start = def.span.range.start();
+        let mut modifiers = 0;
+        if def.span.file == file {
+            if def_start == start {
+                modifiers |= DECLARATION;
+            }
+            modifiers |= source.def_modifiers(def.kind, def_start);
+        } else {
+            let def_source = def_sources
+                .entry(def.span.file.clone())
+                .or_insert_with(|| match vfs.load_file(&def.span.file) {
+                    Ok(FileContents::Text(text)) => Some(Source::new(text)),
+                    _ => None,
+                });
+            if let Some(ref def_source) = *def_source {
+                modifiers |= def_source.def_modifiers(def.kind, def_start);
+            }
+        }
+
+        result.push(SemanticToken {
+            line: start.row.0,
+            start: source.line_index.utf16_column(t.start) as u32,
+            length: source.text[t.start..t.end].encode_utf16().count() as u32,
+            token_type: TokenType::from_def_kind(def.kind),
+            modifiers,
+        });
+    }
+    result
+}
+
+/// Encodes `tokens` as LSP expects, five integers per token with positions
+/// relative to the previous token.
+pub fn encode(tokens: &[SemanticToken]) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut line, mut start) = (0, 0);
+    for t in tokens {
+        let delta_line = t.line - line;
+        let delta_start = if delta_line == 0 { t.start - start } else { t.start };
+        data.extend_from_slice(&[
+            delta_line,
+            delta_start,
+            t.length,
+            t.token_type as u32,
+            t.modifiers,
+        ]);
+        line = t.line;
+        start = t.start;
+    }
+    data
+}
+
+/// The edits to turn the encoded tokens `old` into `new`. This is at most a
+/// single edit replacing whole tokens between a common prefix and suffix, which
+/// covers the usual case of a change in one place in a file.
+pub fn diff(old: &[u32], new: &[u32]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count() / 5 * 5;
+    if prefix == old.len() && prefix == new.len() {
+        return vec![];
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count() / 5 * 5;
+
+    vec![SemanticTokensEdit {
+        start: prefix as u32,
+        delete_count: (old.len() - prefix - suffix) as u32,
+        data: Some(new[prefix..new.len() - suffix].to_vec()),
+    }]
+}
+
+/// The last semantic tokens sent to the client for each file, from which to
+/// compute deltas.
+#[derive(Debug, Default)]
+pub struct SemanticTokensCache {
+    next_id: u64,
+    results: HashMap<PathBuf, (String, Vec<u32>)>,
+}
+
+impl SemanticTokensCache {
+    /// Records `data` as the latest result for `file`, returning its result id.
+    pub fn insert(&mut self, file: PathBuf, data: Vec<u32>) -> String {
+        self.next_id += 1;
+        let result_id = self.next_id.to_string();
+        self.results.insert(file, (result_id.clone(), data));
+        result_id
+    }
+
+    /// The data previously sent for `file`, if it was sent with `result_id`.
+    pub fn get(&self, file: &Path, result_id: &str) -> Option<&[u32]> {
+        match self.results.get(file) {
+            Some(&(ref id, ref data)) if id == result_id => Some(data),
+            _ => None,
+        }
+    }
+}
+
+struct Source {
+    text: String,
+    tokens: Vec<Token>,
+    line_index: LineIndex,
+}
+
+impl Source {
+    fn new(text: String) -> Source {
+        Source {
+            tokens: lex::tokenize(&text),
+            line_index: LineIndex::new(&text),
+            text,
+        }
+    }
+
+    /// The modifiers of a definition of kind `kind` whose name starts at `pos`.
+    fn def_modifiers(&self, kind: DefKind, pos: span::Position<ZeroIndexed>) -> u32 {
+        let mut modifiers = match kind {
+            DefKind::Static | DefKind::Const => STATIC,
+            _ => 0,
+        };
+        let i = match self.line_index
+            .position_to_offset(pos)
+            .and_then(|offset| self.tokens.binary_search_by_key(&offset, |t| t.start).ok())
+        {
+            Some(i) => i,
+            None => return modifiers,
+        };
+        let is = |n: usize, keyword: &str| {
+            i >= n && self.tokens[i - n].is_ident(&self.text, keyword)
+        };
+
+        // `let mut x`, `ref mut x`, `static mut X`, etc.
+        if is(1, "mut") {
+            modifiers |= MUTABLE;
+            // Accessing a mutable static is unsafe.
+            if kind == DefKind::Static {
+                modifiers |= UNSAFE;
+            }
+        }
+        match kind {
+            DefKind::Function | DefKind::Method if is(1, "fn") => {
+                // `unsafe fn`, `unsafe extern "C" fn`, `const unsafe fn`, etc.
+                let is_unsafe = self.tokens[..i - 1]
+                    .iter()
+                    .rev()
+                    .take_while(|t| {
+                        t.kind == TokenKind::Literal
+                            || ["unsafe", "extern", "const", "async"]
+                                .iter()
+                                .any(|keyword| t.is_ident(&self.text, keyword))
+                    })
+                    .any(|t| t.is_ident(&self.text, "unsafe"));
+                if is_unsafe {
+                    modifiers |= UNSAFE;
+                }
+            }
+            DefKind::Trait if is(1, "trait") && is(2, "unsafe") => modifiers |= UNSAFE,
+            _ => {}
+        }
+        modifiers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(line: u32, start: u32, length: u32, token_type: TokenType) -> SemanticToken {
+        SemanticToken {
+            line,
+            start,
+            length,
+            token_type,
+            modifiers: 0,
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let tokens = vec![
+            token(1, 4, 3, TokenType::Function),
+            token(1, 10, 1, TokenType::Variable),
+            token(3, 2, 5, TokenType::Struct),
+        ];
+        assert_eq!(
+            encode(&tokens),
+            vec![1, 4, 3, 6, 0, 0, 6, 1, 9, 0, 2, 2, 5, 2, 0]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![0, 1, 2, 3, 0, 1, 1, 2, 3, 0, 1, 1, 2, 3, 0];
+        assert_eq!(diff(&old, &old), vec![]);
+
+        // A token inserted in the middle.
+        let new = vec![0, 1, 2, 3, 0, 1, 1, 2, 3, 0, 0, 5, 2, 3, 0, 1, 1, 2, 3, 0];
+        assert_eq!(
+            diff(&old, &new),
+            vec![SemanticTokensEdit {
+                start: 10,
+                delete_count: 0,
+                data: Some(vec![0, 5, 2, 3, 0]),
+            }]
+        );
+
+        // The last token removed, the others must not be split.
+        let new = vec![0, 1, 2, 3, 0, 1, 1, 2, 3, 0];
+        assert_eq!(
+            diff(&old, &new),
+            vec![SemanticTokensEdit {
+                start: 10,
+                delete_count: 5,
+                data: Some(vec![]),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_def_modifiers() {
+        let source = Source::new(
+            "static mut X: u32 = 0;\nconst unsafe fn f(mut a: u32, b: u32) {}\n\
+             pub unsafe trait T {}\n"
+                .to_owned(),
+        );
+        let modifiers = |name: &str, kind: DefKind| {
+            let offset = source.text.find(name).unwrap();
+            source.def_modifiers(kind, source.line_index.offset_to_position(offset))
+        };
+        assert_eq!(modifiers("X", DefKind::Static), STATIC | MUTABLE | UNSAFE);
+        assert_eq!(modifiers("f(", DefKind::Function), UNSAFE);
+        assert_eq!(modifiers("a:", DefKind::Local), MUTABLE);
+        assert_eq!(modifiers("b:", DefKind::Local), 0);
+        assert_eq!(modifiers("T ", DefKind::Trait), UNSAFE);
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers for `textDocument/signatureHelp`: finding the call expression
+//! enclosing the cursor and splitting a function signature into parameters.
+
+use actions::lex::{Token, TokenKind};
+
+/// The call expression enclosing a cursor.
+#[derive(Debug, PartialEq)]
+pub struct CallContext {
+    /// Byte offset of the start of the callee's name.
+    pub callee_offset: usize,
+    /// Is this a method call (`foo.bar(..)`)? If so, `self` is not passed as
+    /// an explicit argument.
+    pub is_method: bool,
+    /// Index of the argument the cursor is in.
+    pub active_parameter: usize,
+}
+
+/// Find the innermost unclosed call in `tokens` before the byte offset `cursor`.
+///
+/// Returns `None` if the cursor is not inside the parentheses of a call, or if
+/// the call is a macro invocation or a function definition.
+pub fn find_call_context(src: &str, tokens: &[Token], cursor: usize) -> Option<CallContext> {
+    let before = tokens.iter().take_while(|t| t.end <= cursor).count();
+    let tokens = &tokens[..before];
+
+    let mut depth = 0;
+    let mut active_parameter = 0;
+    // Closure parameters contain commas too, track whether we're between `|`s.
+    let mut bars = 0;
+    let mut open = None;
+    for (i, t) in tokens.iter().enumerate().rev() {
+        match t.kind {
+            TokenKind::Punct(')') | TokenKind::Punct(']') | TokenKind::Punct('}') => depth += 1,
+            TokenKind::Punct('(') if depth == 0 => {
+                open = Some(i);
+                break;
+            }
+            TokenKind::Punct('[') | TokenKind::Punct('{') | TokenKind::Punct(';')
+                if depth == 0 =>
+            {
+                return None;
+            }
+            TokenKind::Punct('(') | TokenKind::Punct('[') | TokenKind::Punct('{') => depth -= 1,
+            TokenKind::Punct('|') if depth == 0 => bars += 1,
+            TokenKind::Punct(',') if depth == 0 && bars % 2 == 0 => active_parameter += 1,
+            _ => {}
+        }
+    }
+
+    let open = open?;
+    let mut callee = open.checked_sub(1)?;
+    // Skip a turbofish, e.g., `foo::<T>(`.
+    if tokens[callee].is_punct('>') {
+        let mut depth = 0;
+        loop {
+            if tokens[callee].is_punct('>') {
+                depth += 1;
+            } else if tokens[callee].is_punct('<') {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            callee = callee.checked_sub(1)?;
+        }
+        if callee < 3 || !tokens[callee - 1].is_punct(':') || !tokens[callee - 2].is_punct(':') {
+            return None;
+        }
+        callee -= 3;
+    }
+
+    let name = &tokens[callee];
+    if name.kind != TokenKind::Ident || is_keyword(name.text(src)) {
+        return None;
+    }
+    let prev = callee.checked_sub(1).map(|i| &tokens[i]);
+    if prev.map_or(false, |t| t.is_ident(src, "fn")) {
+        return None;
+    }
+
+    Some(CallContext {
+        callee_offset: name.start,
+        is_method: prev.map_or(false, |t| t.is_punct('.')),
+        active_parameter,
+    })
+}
+
+fn is_keyword(s: &str) -> bool {
+    match s {
+        "if" | "while" | "match" | "for" | "in" | "return" | "loop" | "let" | "mut" | "ref"
+        | "move" | "as" | "else" | "unsafe" | "where" | "impl" | "dyn" => true,
+        _ => false,
+    }
+}
+
+/// A function signature split into its parameters.
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    pub label: String,
+    pub params: Vec<String>,
+}
+
+/// Parse a signature as found in save-analysis (`fn <T>(x: T) -> u32`) or
+/// racer's context string (`pub fn foo<T>(x: T) -> u32 {`).
+///
+/// `name` is inserted into the label if the signature doesn't contain it.
+pub fn parse_signature(name: &str, sig: &str) -> Option<Signature> {
+    let mut label = sig.trim().trim_right_matches('{').trim_right().to_owned();
+    if label.starts_with("fn (") || label.starts_with("fn <") {
+        label.insert_str("fn ".len(), name);
+    }
+
+    let open = find_top_level(&label, '(')?;
+    let mut depth = 0;
+    let mut close = None;
+    let mut param_starts = vec![open + 1];
+    let mut prev = '\0';
+    for (i, c) in label[open..].char_indices() {
+        let i = open + i;
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            // `->` inside `Fn(..) -> T` is not a closing bracket.
+            '>' if prev == '-' => {}
+            ')' | ']' | '}' | '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            ',' if depth == 1 => param_starts.push(i + 1),
+            _ => {}
+        }
+        prev = c;
+    }
+    let close = close?;
+
+    let mut params = vec![];
+    for (i, &start) in param_starts.iter().enumerate() {
+        let end = param_starts.get(i + 1).map_or(close, |next| next - 1);
+        let param = label[start..end].trim();
+        if !param.is_empty() {
+            params.push(param.to_owned());
+        }
+    }
+
+    Some(Signature { label, params })
+}
+
+// Find `c` outside of any generics brackets.
+fn find_top_level(s: &str, c: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut prev = '\0';
+    for (i, ch) in s.char_indices() {
+        match ch {
+            _ if ch == c && depth == 0 => return Some(i),
+            '<' => depth += 1,
+            '>' if prev != '-' => depth -= 1,
+            _ => {}
+        }
+        prev = ch;
+    }
+    None
+}
+
+/// Is `param` a `self` parameter, e.g., `&'a mut self` or `self: Box<Self>`?
+pub fn is_self_param(param: &str) -> bool {
+    let mut param = param.trim_left_matches('&').trim_left();
+    if param.starts_with('\'') {
+        param = param
+            .trim_left_matches(|c: char| c == '\'' || c == '_' || c.is_alphanumeric())
+            .trim_left();
+    }
+    if param.starts_with("mut ") {
+        param = param["mut ".len()..].trim_left();
+    }
+    param == "self" || param.starts_with("self:") || param.starts_with("self :")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actions::lex::tokenize;
+
+    fn call_context(src: &str) -> Option<(String, bool, usize)> {
+        let cursor = src.find('^').unwrap();
+        let src = src.replace('^', "");
+        let tokens = tokenize(&src);
+        find_call_context(&src, &tokens, cursor).map(|c| {
+            let name = tokens
+                .iter()
+                .find(|t| t.start == c.callee_offset)
+                .unwrap()
+                .text(&src)
+                .to_owned();
+            (name, c.is_method, c.active_parameter)
+        })
+    }
+
+    #[test]
+    fn test_find_call_context() {
+        assert_eq!(call_context("foo(^)"), Some(("foo".to_owned(), false, 0)));
+        assert_eq!(call_context("foo(a, ^)"), Some(("foo".to_owned(), false, 1)));
+        assert_eq!(call_context("x.bar(a, b(c, d), ^"), Some(("bar".to_owned(), true, 2)));
+        assert_eq!(call_context("foo(bar(1^), 2)"), Some(("bar".to_owned(), false, 0)));
+        assert_eq!(call_context("foo(|a, b| a + b, ^"), Some(("foo".to_owned(), false, 1)));
+        assert_eq!(call_context("foo::<u8, u16>(1, ^"), Some(("foo".to_owned(), false, 1)));
+        assert_eq!(call_context("foo(\"(,\", ^"), Some(("foo".to_owned(), false, 1)));
+        assert_eq!(call_context("foo(); ^"), None);
+        assert_eq!(call_context("println!(\"{}\", ^"), None);
+        assert_eq!(call_context("fn foo(a: u32, ^"), None);
+        assert_eq!(call_context("if (a ^"), None);
+        assert_eq!(call_context("foo(vec![1, ^"), None);
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        assert_eq!(
+            parse_signature("foo", "fn (a: u32, b: Vec<(u8, u8)>) -> u32"),
+            Some(Signature {
+                label: "fn foo(a: u32, b: Vec<(u8, u8)>) -> u32".to_owned(),
+                params: vec!["a: u32".to_owned(), "b: Vec<(u8, u8)>".to_owned()],
+            })
+        );
+        assert_eq!(
+            parse_signature("foo", "pub fn foo<F: Fn(u8) -> u8>(&self, f: F,) {"),
+            Some(Signature {
+                label: "pub fn foo<F: Fn(u8) -> u8>(&self, f: F,)".to_owned(),
+                params: vec!["&self".to_owned(), "f: F".to_owned()],
+            })
+        );
+        assert_eq!(
+            parse_signature("foo", "fn foo()").map(|s| s.params),
+            Some(vec![])
+        );
+        assert_eq!(parse_signature("Foo", "struct Foo"), None);
+    }
+
+    #[test]
+    fn test_is_self_param() {
+        assert!(is_self_param("self"));
+        assert!(is_self_param("&self"));
+        assert!(is_self_param("&mut self"));
+        assert!(is_self_param("&'a mut self"));
+        assert!(is_self_param("mut self"));
+        assert!(is_self_param("self: Box<Self>"));
+        assert!(!is_self_param("self_: u32"));
+        assert!(!is_self_param("x: &Self"));
+    }
+}
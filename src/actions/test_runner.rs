@@ -0,0 +1,475 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The `rls.runTests` command: runs `cargo test` in the background, reports the
+//! result of each test to the client and publishes failures as diagnostics.
+
+use actions::InitActionContext;
+use actions::diagnostics::Diagnostic;
+use actions::post_build::BuildResults;
+use actions::run::{self, CargoTarget};
+use config::Config;
+use ls_types::notification::PublishDiagnostics;
+use lsp_data::{ls_util, parse_file_path, DiagnosticSeverity, Location, PublishDiagnosticsParams,
+               Range, TestResult, TestResultParams, TestStatus, TestsFinished,
+               TestsFinishedParams};
+use server::{Notification, Output};
+use url::Url;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Arguments to the `rls.runTests` command.
+#[derive(Debug, Deserialize)]
+pub struct RunTestsParams {
+    /// The file containing the tests to run.
+    pub uri: Url,
+    /// The path of a module or test in the file to run. If missing, all tests
+    /// in the file are run.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// If true, `path` is a single test rather than a module.
+    #[serde(default)]
+    pub exact: bool,
+}
+
+/// A `cargo test` invocation and what we need to report its results.
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    cwd: PathBuf,
+    args: Vec<String>,
+    uri: Url,
+    /// The ranges of the names of the tests in the file, by libtest path.
+    tests: HashMap<String, Range>,
+    file: PathBuf,
+    /// The results of the last build, whose diagnostics for the file test
+    /// failures are published alongside. They're read once the tests have
+    /// finished, as there may have been another build in the meantime.
+    build_results: Arc<Mutex<BuildResults>>,
+    show_warnings: bool,
+}
+
+impl TestRun {
+    pub fn new(ctx: &InitActionContext, params: RunTestsParams) -> Result<TestRun, String> {
+        let file = parse_file_path(&params.uri).map_err(|e| e.to_string())?;
+        let tests = run::collect_tests(ctx, &file);
+
+        let exact = params.exact && params.path.is_some();
+        let filter = match params.path {
+            Some(ref path) if exact => path.clone(),
+            Some(ref path) => format!("{}::", path),
+            None if tests.is_empty() => return Err("No tests found".to_owned()),
+            None => file_filter(tests.iter().map(|&(ref path, _)| &path[..])),
+        };
+
+        let target = run::find_cargo_target(ctx, &file);
+        let config = ctx.config.lock().unwrap();
+        let args = cargo_args(&config, target.as_ref(), &filter, exact);
+
+        Ok(TestRun {
+            cwd: ctx.current_project.clone(),
+            args,
+            uri: params.uri,
+            tests: tests
+                .into_iter()
+                .map(|(path, range)| (path, ls_util::rls_to_range(range)))
+                .collect(),
+            file,
+            build_results: ctx.previous_build_results.clone(),
+            show_warnings: config.show_warnings,
+        })
+    }
+
+    /// Runs the tests on a new thread.
+    pub fn spawn<O: Output>(self, out: O) {
+        thread::spawn(move || self.run(&out));
+    }
+
+    fn run<O: Output>(self, out: &O) {
+        trace!("running tests: cargo {:?}", self.args);
+        let mut summary = TestsFinishedParams::default();
+        let mut failures = vec![];
+
+        let result = self.run_cargo(|event| match event {
+            TestEvent::Result(name, status) => {
+                match status {
+                    TestStatus::Passed => summary.passed += 1,
+                    TestStatus::Failed => summary.failed += 1,
+                    TestStatus::Ignored => summary.ignored += 1,
+                }
+                let location = self.tests
+                    .get(&name)
+                    .map(|range| Location::new(self.uri.clone(), *range));
+                out.notify(Notification::<TestResult>::new(TestResultParams {
+                    name,
+                    status,
+                    location,
+                }));
+            }
+            TestEvent::Failure(name, message) => failures.push((name, message)),
+        });
+        if let Err(e) = result {
+            summary.error = Some(e);
+        }
+
+        let mut diagnostics = self.build_diagnostics();
+        for (name, message) in failures {
+            if let Some(range) = self.tests.get(&name) {
+                diagnostics.push(Diagnostic {
+                    range: *range,
+                    severity: Some(DiagnosticSeverity::Error),
+                    code: None,
+                    source: Some("test".to_owned()),
+                    message: format!("test `{}` failed: {}", name, message),
+                    related_information: None,
+                });
+            }
+        }
+        out.notify(Notification::<PublishDiagnostics>::new(PublishDiagnosticsParams {
+            uri: self.uri.clone(),
+            diagnostics,
+        }));
+        out.notify(Notification::<TestsFinished>::new(summary));
+    }
+
+    // The current diagnostics from the last build for the file.
+    fn build_diagnostics(&self) -> Vec<Diagnostic> {
+        let show_warnings = self.show_warnings;
+        self.build_results
+            .lock()
+            .unwrap()
+            .get(&self.file)
+            .map(|results| {
+                results
+                    .iter()
+                    .map(|&(ref diag, _)| diag)
+                    .filter(|diag| {
+                        show_warnings || diag.severity != Some(DiagnosticSeverity::Warning)
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Runs cargo, calling `f` for each event parsed from its output.
+    fn run_cargo<F: FnMut(TestEvent)>(&self, mut f: F) -> Result<(), String> {
+        let mut child = Command::new("cargo")
+            .args(&self.args)
+            .current_dir(&self.cwd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Couldn't run cargo: {}", e))?;
+
+        // Read stderr (compiler output) on another thread so that neither pipe
+        // can fill up and block cargo.
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr = thread::spawn(move || {
+            let mut s = String::new();
+            let _ = stderr.read_to_string(&mut s);
+            s
+        });
+
+        let mut parser = OutputParser::default();
+        let mut ran_tests = false;
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Some(event) = parser.parse_line(&line) {
+                ran_tests = true;
+                f(event);
+            }
+        }
+        if let Some(event) = parser.finish() {
+            f(event);
+        }
+
+        let status = child.wait().map_err(|e| format!("Couldn't run cargo: {}", e))?;
+        let stderr = stderr.join().unwrap_or_default();
+        // A failing test also makes cargo fail, only report an error if the
+        // tests didn't run at all.
+        if !status.success() && !ran_tests {
+            let message = stderr
+                .lines()
+                .find(|line| line.starts_with("error"))
+                .unwrap_or("`cargo test` failed");
+            return Err(message.to_owned());
+        }
+        Ok(())
+    }
+}
+
+// Arguments to `cargo test` for running the tests matching `filter`.
+fn cargo_args(
+    config: &Config,
+    target: Option<&CargoTarget>,
+    filter: &str,
+    exact: bool,
+) -> Vec<String> {
+    let mut args = vec!["test".to_owned()];
+    match target {
+        Some(target) => args.extend(target.args()),
+        None if config.all_targets => args.push("--all-targets".to_owned()),
+        None => {}
+    }
+    if config.all_features {
+        args.push("--all-features".to_owned());
+    }
+    if config.no_default_features {
+        args.push("--no-default-features".to_owned());
+    }
+    if !config.features.is_empty() {
+        args.push("--features".to_owned());
+        args.push(config.features.join(" "));
+    }
+    if let Some(ref target) = config.target {
+        args.push("--target".to_owned());
+        args.push(target.clone());
+    }
+    // Share the target directory with our builds.
+    if let Some(ref target_dir) = *config.target_dir.as_ref() {
+        args.push("--target-dir".to_owned());
+        args.push(target_dir.display().to_string());
+    }
+
+    args.push("--".to_owned());
+    if !filter.is_empty() {
+        args.push(filter.to_owned());
+    }
+    if exact {
+        args.push("--exact".to_owned());
+    }
+    args
+}
+
+// A libtest filter matching all of the given tests, which are in the same file:
+// the longest module path they share.
+fn file_filter<'a, I: Iterator<Item = &'a str>>(tests: I) -> String {
+    let mut prefix: Option<Vec<&str>> = None;
+    for test in tests {
+        let mut module: Vec<&str> = test.split("::").collect();
+        module.pop();
+        prefix = Some(match prefix {
+            None => module,
+            Some(prefix) => prefix
+                .into_iter()
+                .zip(module)
+                .take_while(|&(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+
+    match prefix {
+        Some(ref prefix) if !prefix.is_empty() => format!("{}::", prefix.join("::")),
+        _ => String::new(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum TestEvent {
+    /// A test has finished.
+    Result(String, TestStatus),
+    /// The failure message of a test.
+    Failure(String, String),
+}
+
+/// Parses the (human readable) output of libtest.
+#[derive(Default)]
+struct OutputParser {
+    /// The name and output so far of the failed test whose output is being read.
+    failure: Option<(String, Vec<String>)>,
+}
+
+impl OutputParser {
+    fn parse_line(&mut self, line: &str) -> Option<TestEvent> {
+        // The output of each failed test is printed after all tests have run,
+        // starting with `---- name stdout ----` and ending at the next test's
+        // output or the list of failures.
+        if line.starts_with("---- ") && line.ends_with(" stdout ----") {
+            let previous = self.finish();
+            let name = &line["---- ".len()..line.len() - " stdout ----".len()];
+            self.failure = Some((name.to_owned(), vec![]));
+            return previous;
+        }
+        if let Some((_, ref mut output)) = self.failure {
+            if line != "failures:" {
+                output.push(line.to_owned());
+                return None;
+            }
+        }
+        if line == "failures:" {
+            return self.finish();
+        }
+
+        // `test foo::bar ... ok`
+        if !line.starts_with("test ") {
+            return None;
+        }
+        let line = &line["test ".len()..];
+        let separator = line.rfind(" ... ")?;
+        let status = match &line[separator + " ... ".len()..] {
+            "ok" => TestStatus::Passed,
+            "FAILED" => TestStatus::Failed,
+            s if s.starts_with("ignored") => TestStatus::Ignored,
+            _ => return None,
+        };
+        Some(TestEvent::Result(line[..separator].to_owned(), status))
+    }
+
+    fn finish(&mut self) -> Option<TestEvent> {
+        self.failure
+            .take()
+            .map(|(name, output)| TestEvent::Failure(name, panic_message(&output)))
+    }
+}
+
+// Extracts the panic message from the output of a failed test.
+fn panic_message(output: &[String]) -> String {
+    const PANICKED_AT: &str = "' panicked at ";
+
+    let start = output
+        .iter()
+        .position(|line| line.starts_with("thread '") && line.contains(PANICKED_AT));
+    let message = match start {
+        Some(start) => {
+            let first = &output[start];
+            let first = &first[first.find(PANICKED_AT).unwrap() + PANICKED_AT.len()..];
+            let rest = output[start + 1..]
+                .iter()
+                .take_while(|line| {
+                    !line.starts_with("note: ") && !line.starts_with("stack backtrace:")
+                });
+            if first.starts_with('\'') {
+                // `panicked at 'message', src/lib.rs:1:1`
+                let text = ::std::iter::once(&first[1..])
+                    .chain(rest.map(|s| &s[..]))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                match text.rfind("', ") {
+                    Some(end) => text[..end].to_owned(),
+                    None => text,
+                }
+            } else {
+                // `panicked at src/lib.rs:1:1:` followed by the message.
+                rest.map(|s| &s[..]).collect::<Vec<_>>().join("\n")
+            }
+        }
+        None => output.join("\n"),
+    };
+
+    let message = message.trim();
+    if message.is_empty() {
+        "test failed".to_owned()
+    } else {
+        message.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(output: &str) -> Vec<TestEvent> {
+        let mut parser = OutputParser::default();
+        let mut events: Vec<_> = output.lines().filter_map(|l| parser.parse_line(l)).collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let output = "
+running 4 tests
+test tests::ignored ... ignored
+test tests::passes ... ok
+test tests::fails ... FAILED
+test tests::panics ... FAILED
+
+failures:
+
+---- tests::fails stdout ----
+thread 'tests::fails' panicked at 'assertion failed: `(left == right)`
+  left: `1`,
+ right: `2`', src/lib.rs:10:9
+note: Run with `RUST_BACKTRACE=1` for a backtrace.
+
+---- tests::panics stdout ----
+some output
+thread 'tests::panics' panicked at src/lib.rs:15:9:
+oh no
+
+failures:
+    tests::fails
+    tests::panics
+
+test result: FAILED. 1 passed; 2 failed; 1 ignored; 0 measured; 0 filtered out
+";
+        assert_eq!(
+            parse(output),
+            vec![
+                TestEvent::Result("tests::ignored".to_owned(), TestStatus::Ignored),
+                TestEvent::Result("tests::passes".to_owned(), TestStatus::Passed),
+                TestEvent::Result("tests::fails".to_owned(), TestStatus::Failed),
+                TestEvent::Result("tests::panics".to_owned(), TestStatus::Failed),
+                TestEvent::Failure(
+                    "tests::fails".to_owned(),
+                    "assertion failed: `(left == right)`\n  left: `1`,\n right: `2`".to_owned(),
+                ),
+                TestEvent::Failure("tests::panics".to_owned(), "oh no".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panic_message() {
+        assert_eq!(
+            panic_message(&["note: test did not panic as expected".to_owned()]),
+            "note: test did not panic as expected"
+        );
+        assert_eq!(panic_message(&[]), "test failed");
+    }
+
+    #[test]
+    fn test_file_filter() {
+        assert_eq!(file_filter(vec!["foo::tests::a", "foo::tests::b"].into_iter()), "foo::tests::");
+        assert_eq!(file_filter(vec!["foo::a", "foo::tests::b"].into_iter()), "foo::");
+        assert_eq!(file_filter(vec!["a", "tests::b"].into_iter()), "");
+    }
+
+    #[test]
+    fn test_cargo_args() {
+        let mut config = Config::default();
+        config.features = vec!["foo".to_owned(), "bar".to_owned()];
+        config.target = Some("wasm32-unknown-unknown".to_owned());
+        let target = CargoTarget {
+            package: "pkg".to_owned(),
+            kind: run::TargetKind::Lib,
+            name: "pkg".to_owned(),
+        };
+        assert_eq!(
+            cargo_args(&config, Some(&target), "tests::foo", true),
+            vec![
+                "test", "-p", "pkg", "--lib", "--features", "foo bar", "--target",
+                "wasm32-unknown-unknown", "--", "tests::foo", "--exact",
+            ]
+        );
+
+        let config = Config::default();
+        assert_eq!(cargo_args(&config, None, "", false), vec!["test", "--all-targets", "--"]);
+    }
+}
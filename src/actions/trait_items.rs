@@ -0,0 +1,442 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Filling in the items missing from a trait impl, which rustc reports as
+//! E0046.
+
+use actions::imports::{indentation, line_start, Module};
+use actions::lex::{self, Token, TokenKind};
+
+use std::collections::HashMap;
+
+/// The names of the items missing from an impl, from the message of rustc's
+/// E0046 error, "not all trait items implemented, missing: `foo`, `Bar`".
+pub fn missing_item_names(message: &str) -> Vec<String> {
+    let line = message.lines().next().unwrap_or("");
+    let missing = match line.find("missing: ") {
+        Some(i) => &line[i + "missing: ".len()..],
+        None => return vec![],
+    };
+    missing
+        .split(", ")
+        .map(|name| name.trim().trim_matches('`'))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect()
+}
+
+/// An `impl Trait for Type` block.
+#[derive(Debug, PartialEq)]
+pub struct TraitImpl {
+    /// The byte offsets of the last segment of the trait's path, e.g.,
+    /// `Display` in `impl fmt::Display for Foo`.
+    pub trait_name: (usize, usize),
+    /// The generic arguments of the trait, e.g., `u8` in `impl From<u8> for Foo`.
+    pub trait_args: Vec<String>,
+    /// The byte offsets of the braces around the impl's body.
+    body: (usize, usize),
+    indent: String,
+}
+
+/// The trait impl starting at `offset`, which may be at a qualifier before its
+/// `impl` keyword, e.g., `unsafe`.
+pub fn trait_impl(src: &str, offset: usize) -> Option<TraitImpl> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let start = tokens.iter().position(|t| t.start >= offset)?;
+    let impl_keyword = (start..tokens.len())
+        .take_while(|&i| tokens[i].kind == TokenKind::Ident)
+        .find(|&i| tokens[i].is_ident(src, "impl"))?;
+
+    // The trait's name is the last identifier before `for` which isn't in
+    // the impl's generics or the trait's generic arguments.
+    let mut trait_name = None;
+    let mut angle_depth = 0;
+    let mut i = impl_keyword + 1;
+    loop {
+        let t = tokens.get(i)?;
+        match t.kind {
+            TokenKind::Punct('<') => angle_depth += 1,
+            // Not `->`, as in `Fn() -> T`.
+            TokenKind::Punct('>') if !tokens[i - 1].is_punct('-') => angle_depth -= 1,
+            TokenKind::Punct('(') | TokenKind::Punct('[') => i = *pairs.get(&i)?,
+            // An inherent impl.
+            TokenKind::Punct('{') | TokenKind::Punct(';') => return None,
+            TokenKind::Ident if angle_depth == 0 => {
+                if t.is_ident(src, "for") {
+                    break;
+                }
+                trait_name = Some(i);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let open = (i..tokens.len()).find(|&i| tokens[i].is_punct('{'))?;
+    let close = match pairs.get(&open) {
+        Some(&close) if close > open => close,
+        _ => return None,
+    };
+    let trait_name = trait_name?;
+    let trait_args = match tokens.get(trait_name + 1) {
+        Some(t) if t.is_punct('<') => generics(&tokens, &pairs, trait_name + 1)
+            .into_iter()
+            .map(|(start, end)| src[tokens[start].start..tokens[end - 1].end].to_owned())
+            .collect(),
+        _ => vec![],
+    };
+    let indent = indentation(src, line_start(src, tokens[impl_keyword].start));
+    Some(TraitImpl {
+        trait_name: (tokens[trait_name].start, tokens[trait_name].end),
+        trait_args,
+        body: (tokens[open].start, tokens[close].start),
+        indent: indent.to_owned(),
+    })
+}
+
+impl TraitImpl {
+    /// The edit adding `items`, which are indented as if in an impl at the
+    /// top level, to the end of the impl's body, as the byte offsets of the
+    /// text to replace and its replacement.
+    pub fn insert_items(&self, src: &str, items: &str) -> (usize, usize, String) {
+        let items = items
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}{}", self.indent, line)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let (open, close) = self.body;
+        let body = &src[open + 1..close];
+        if body.trim().is_empty() {
+            (open + 1, close, format!("\n{}\n{}", items, self.indent))
+        } else {
+            let end = open + 1 + body.trim_right().len();
+            (end, close, format!("\n\n{}\n{}", items, self.indent))
+        }
+    }
+}
+
+/// Stubs for the items named `missing` of the trait whose name is at
+/// `name_offset` in `src`, in the order they are declared in the trait. The
+/// trait's generic parameters are replaced by `args`, the arguments the impl
+/// gives them, or else by their defaults.
+///
+/// Methods keep their signatures, including generics and where clauses, and
+/// get a body of `unimplemented!()`. Associated consts get a value if their
+/// type has an obvious one, e.g., `0` for integers, and are left as a comment
+/// otherwise. Associated types are defined as `()`. Items with a default aren't
+/// stubbed.
+pub fn item_stubs(
+    src: &str,
+    name_offset: usize,
+    args: &[String],
+    missing: &[String],
+) -> Vec<String> {
+    let tokens = lex::tokenize(src);
+    let pairs = lex::delimiter_pairs(&tokens);
+    let name = match tokens.iter().position(|t| t.start == name_offset) {
+        Some(name) => name,
+        None => return vec![],
+    };
+    let substitutions = match tokens.get(name + 1) {
+        Some(t) if t.is_punct('<') => generic_substitutions(src, &tokens, &pairs, name + 1, args),
+        _ => HashMap::new(),
+    };
+    let open = match (name..tokens.len()).find(|&i| tokens[i].is_punct('{')) {
+        Some(open) => open,
+        None => return vec![],
+    };
+    let close = match pairs.get(&open) {
+        Some(&close) if close > open => close,
+        _ => return vec![],
+    };
+
+    let body = Module::new(src, &tokens, &pairs, (open + 1, close));
+    body.items
+        .iter()
+        .filter_map(|&item| {
+            let keyword = body.item_keyword(item)?;
+            // Skip qualifiers, e.g., `unsafe extern "C" fn`.
+            let kind = (keyword..item.1).find(|&i| {
+                ["fn", "type", "const"].iter().any(|k| tokens[i].is_ident(src, k))
+            })?;
+            let name = tokens.get(kind + 1)?.text(src);
+            if !missing.iter().any(|m| m == name) {
+                return None;
+            }
+            let last = &tokens[item.1 - 1];
+            let is_fn = tokens[kind].is_ident(src, "fn");
+            if !last.is_punct(';') || (!is_fn && has_default(&tokens[kind..item.1])) {
+                return None;
+            }
+
+            let signature = substitute(src, &tokens[keyword..item.1 - 1], &substitutions);
+            let signature = signature.trim_right();
+            Some(if is_fn {
+                format!("{} {{\n    unimplemented!()\n}}", signature)
+            } else if tokens[kind].is_ident(src, "type") {
+                format!("type {} = ();", name)
+            } else {
+                let ty = signature.splitn(2, ':').nth(1).unwrap_or("").trim();
+                match const_value(ty) {
+                    Some(value) => format!("{} = {};", signature, value),
+                    None => format!("// TODO: {} = ...;", signature),
+                }
+            })
+        })
+        .collect()
+}
+
+/// The token ranges of the comma separated generic parameters or arguments in
+/// the angle brackets opening at `tokens[open]`.
+fn generics(tokens: &[Token], pairs: &HashMap<usize, usize>, open: usize) -> Vec<(usize, usize)> {
+    let mut generics = vec![];
+    let mut start = open + 1;
+    let mut angle_depth = 1;
+    let mut i = open + 1;
+    while let Some(t) = tokens.get(i) {
+        match t.kind {
+            TokenKind::Punct('<') => angle_depth += 1,
+            // Not `->`, as in `Fn() -> T`.
+            TokenKind::Punct('>') if !tokens[i - 1].is_punct('-') => {
+                angle_depth -= 1;
+                if angle_depth == 0 {
+                    break;
+                }
+            }
+            TokenKind::Punct('(') | TokenKind::Punct('[') => match pairs.get(&i) {
+                Some(&close) if close > i => i = close,
+                _ => return vec![],
+            },
+            TokenKind::Punct(',') if angle_depth == 1 => {
+                generics.push((start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if start < i {
+        generics.push((start, i));
+    }
+    generics
+}
+
+/// The replacement of each generic parameter of the trait whose parameters
+/// open at `tokens[open]`, by the argument the impl gives it in `args` or
+/// else by its default.
+fn generic_substitutions(
+    src: &str,
+    tokens: &[Token],
+    pairs: &HashMap<usize, usize>,
+    open: usize,
+    args: &[String],
+) -> HashMap<String, String> {
+    let mut substitutions = HashMap::new();
+    for (i, (start, end)) in generics(tokens, pairs, open).into_iter().enumerate() {
+        let param = &tokens[start..end];
+        // Skip `const` in `const N: usize`.
+        let name = match param.iter().find(|t| !t.is_comment() && !t.is_ident(src, "const")) {
+            Some(name) => name.text(src),
+            None => continue,
+        };
+        let default = default_position(param)
+            .filter(|&eq| eq + 1 < param.len())
+            .map(|eq| &src[param[eq + 1].start..param[param.len() - 1].end]);
+        if let Some(arg) = args.get(i).map(|arg| &arg[..]).or(default) {
+            if arg != name {
+                substitutions.insert(name.to_owned(), arg.to_owned());
+            }
+        }
+    }
+    substitutions
+}
+
+/// The text of `tokens` with the identifiers and lifetimes in `substitutions`
+/// replaced, except where they are a later segment of a path.
+fn substitute(src: &str, tokens: &[Token], substitutions: &HashMap<String, String>) -> String {
+    let (start, end) = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => (first.start, last.end),
+        _ => return String::new(),
+    };
+    let mut text = String::new();
+    let mut copied = start;
+    for (i, t) in tokens.iter().enumerate() {
+        if t.kind != TokenKind::Ident && t.kind != TokenKind::Lifetime {
+            continue;
+        }
+        // E.g., `Self::T`.
+        if i >= 2 && tokens[i - 1].is_punct(':') && tokens[i - 2].is_punct(':') {
+            continue;
+        }
+        if let Some(replacement) = substitutions.get(t.text(src)) {
+            text.push_str(&src[copied..t.start]);
+            text.push_str(replacement);
+            copied = t.end;
+        }
+    }
+    text.push_str(&src[copied..end]);
+    text
+}
+
+/// A value for an associated const of type `ty`, if it has an obvious one.
+fn const_value(ty: &str) -> Option<&'static str> {
+    let integers = [
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+    ];
+    Some(match ty {
+        _ if integers.contains(&ty) => "0",
+        "f32" | "f64" => "0.0",
+        "bool" => "false",
+        "char" => "'\\0'",
+        "&str" | "&'static str" => "\"\"",
+        "()" => "()",
+        _ if ty.starts_with("Option<") => "None",
+        _ => return None,
+    })
+}
+
+/// Whether the associated const or type declared by `tokens` has a default, as
+/// in `const N: usize = 1;`.
+fn has_default(tokens: &[Token]) -> bool {
+    default_position(tokens).is_some()
+}
+
+/// The position of the `=` before the default of the associated const or type,
+/// or generic parameter, declared by `tokens`.
+fn default_position(tokens: &[Token]) -> Option<usize> {
+    let mut angle_depth = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match t.kind {
+            TokenKind::Punct('<') => angle_depth += 1,
+            // Not `->`, as in `const F: fn() -> u8;`.
+            TokenKind::Punct('>') if i == 0 || !tokens[i - 1].is_punct('-') => angle_depth -= 1,
+            // Not `=` in a bound, as in `type I: Iterator<Item = u8>;`.
+            TokenKind::Punct('=') if angle_depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing_item_names() {
+        let message = "not all trait items implemented, missing: `foo`, `Bar`\n\n\
+                       missing `foo`, `Bar` in implementation";
+        assert_eq!(missing_item_names(message), vec!["foo", "Bar"]);
+        assert_eq!(missing_item_names("mismatched types"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_trait_impl() {
+        let src = "mod m {\n    unsafe impl<T: Fn() -> u8> fmt::Display<T> for Foo<T>\n\
+                   where T: Copy {}\n}";
+        let imp = trait_impl(src, src.find("unsafe").unwrap()).unwrap();
+        let (start, end) = imp.trait_name;
+        assert_eq!(&src[start..end], "Display");
+        assert_eq!(imp.trait_args, vec!["T"]);
+        assert_eq!(imp.indent, "    ");
+        let body = src.len() - 3;
+        assert_eq!(
+            imp.insert_items(src, "    fn f() {}\n\n    type T = ();"),
+            (body, body, "\n        fn f() {}\n\n        type T = ();\n    ".to_owned())
+        );
+
+        let src = "impl Foo for Bar {\n    fn g() {}\n}\n";
+        let imp = trait_impl(src, 0).unwrap();
+        assert!(imp.trait_args.is_empty());
+        let end = src.find("{}").unwrap() + 2;
+        assert_eq!(
+            imp.insert_items(src, "    fn f() {}"),
+            (end, end + 1, "\n\n    fn f() {}\n".to_owned())
+        );
+
+        let src = "impl<'a> Foo<'a, Vec<(u8, u16)>, Fn(u8) -> u8> for Bar {}";
+        assert_eq!(
+            trait_impl(src, 0).unwrap().trait_args,
+            vec!["'a", "Vec<(u8, u16)>", "Fn(u8) -> u8"]
+        );
+
+        assert_eq!(trait_impl("impl Foo {}", 0), None);
+        assert_eq!(trait_impl("impl<T> Foo<T> {}", 0), None);
+    }
+
+    #[test]
+    fn test_item_stubs() {
+        let src = r#"
+pub trait Foo<T>: Clone where T: Copy {
+    /// The output.
+    type Output: Default;
+    type Iter: Iterator<Item = T>;
+    const N: usize;
+    const M: usize = 1;
+    fn required<U>(&self, u: U) -> T
+    where
+        U: Into<T>;
+    fn provided(&self) {}
+    unsafe fn qualified(&mut self);
+}
+"#;
+        let missing = ["Output", "Iter", "N", "M", "required", "provided", "qualified"];
+        let missing: Vec<_> = missing.iter().map(|m| m.to_string()).collect();
+        assert_eq!(
+            item_stubs(src, src.find("Foo").unwrap(), &[], &missing),
+            vec![
+                "type Output = ();",
+                "type Iter = ();",
+                "const N: usize = 0;",
+                "fn required<U>(&self, u: U) -> T\n    where\n        U: Into<T> {\n    \
+                 unimplemented!()\n}",
+                "unsafe fn qualified(&mut self) {\n    unimplemented!()\n}",
+            ]
+        );
+        // `M` has a default.
+        assert!(item_stubs(src, src.find("Foo").unwrap(), &[], &missing[3..4]).is_empty());
+    }
+
+    #[test]
+    fn test_item_stubs_generics() {
+        let src = r#"
+trait Convert<'a, T: Into<u8>, Rhs = Self> {
+    const DEFAULT: T;
+    const OTHER: Wrapper<T>;
+    fn convert(&self, t: &'a T, rhs: Rhs) -> Option<T::Output>;
+}
+"#;
+        let missing = ["DEFAULT", "OTHER", "convert"];
+        let missing: Vec<_> = missing.iter().map(|m| m.to_string()).collect();
+        let args = vec!["'static".to_owned(), "Vec<u8>".to_owned()];
+        assert_eq!(
+            item_stubs(src, src.find("Convert").unwrap(), &args, &missing),
+            vec![
+                "// TODO: const DEFAULT: Vec<u8> = ...;",
+                "// TODO: const OTHER: Wrapper<Vec<u8>> = ...;",
+                "fn convert(&self, t: &'static Vec<u8>, rhs: Self) -> Option<Vec<u8>::Output> \
+                 {\n    unimplemented!()\n}",
+            ]
+        );
+        let args = vec!["'static".to_owned(), "u32".to_owned()];
+        assert_eq!(
+            item_stubs(src, src.find("Convert").unwrap(), &args, &missing[..1]),
+            vec!["const DEFAULT: u32 = 0;"]
+        );
+    }
+}
@@ -449,6 +449,12 @@ impl BuildQueue {
             .unwrap()
             .insert(file, version);
     }
+
+    /// Returns whether `file` has changed since the last successful build, in
+    /// which case the positions in its diagnostics may be out of date.
+    pub fn is_file_dirty(&self, file: &Path) -> bool {
+        self.internals.dirty_files.lock().unwrap().contains_key(file)
+    }
 }
 
 impl Internals {
@@ -394,7 +394,7 @@ fn initialize(root_path: String) -> Request<server::InitializeRequest> {
     };
     Request {
         id: next_id(),
-        params,
+        params: params.into(),
         received: Instant::now(),
         _action: PhantomData,
     }
@@ -10,6 +10,7 @@
 
 //! Types, helpers, and conversions to and from LSP and `racer` types.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::path::PathBuf;
 use std::error::Error;
@@ -18,8 +19,8 @@ use analysis::DefKind;
 use url::Url;
 use span;
 use racer;
-use vfs::FileContents;
 use ls_types;
+use serde_json;
 
 pub use ls_types::*;
 pub use ls_types::request::Request as LSPRequest;
@@ -85,9 +86,6 @@ pub mod ls_util {
     use super::*;
     use Span;
 
-    use std::path::Path;
-    use vfs::Vfs;
-
     /// Convert a language server protocol range into an RLS range.
     pub fn range_to_rls(r: Range) -> span::Range<span::ZeroIndexed> {
         span::Range::from_positions(position_to_rls(r.start), position_to_rls(r.end))
@@ -140,42 +138,6 @@ pub mod ls_util {
             character: p.col.0.into(),
         }
     }
-
-    /// Creates a `Range` spanning the whole file as currently known by `Vfs`
-    ///
-    /// Panics if `Vfs` cannot load the file.
-    pub fn range_from_vfs_file(vfs: &Vfs, fname: &Path) -> Range {
-        // FIXME load_file clones the entire file text, this could be much more
-        // efficient by adding a `with_file` fn to the VFS.
-        let content = match vfs.load_file(fname).unwrap() {
-            FileContents::Text(t) => t,
-            _ => panic!("unexpected binary file: {:?}", fname),
-        };
-        if content.is_empty() {
-            Range {
-                start: Position::new(0, 0),
-                end: Position::new(0, 0),
-            }
-        } else {
-            let mut line_count = content.lines().count() as u64 - 1;
-            let col = if content.ends_with('\n') {
-                line_count += 1;
-                0
-            } else {
-                content
-                    .lines()
-                    .last()
-                    .expect("String is not empty.")
-                    .chars()
-                    .count() as u64
-            };
-            // range is zero-based and the end position is exclusive
-            Range {
-                start: Position::new(0, 0),
-                end: Position::new(line_count, col),
-            }
-        }
-    }
 }
 
 /// Convert an RLS def-kind to a language server protocol symbol-kind.
@@ -263,6 +225,9 @@ pub struct InitializationOptions {
     /// Should the build not be triggered immediately after receiving `initialize`
     pub omit_init_build: bool,
     pub cmd_run: bool,
+    /// Whether the client provides the `rls.showReferences` command, which
+    /// the reference and implementation count lenses run.
+    pub cmd_show_references: bool,
 }
 
 impl Default for InitializationOptions {
@@ -270,10 +235,63 @@ impl Default for InitializationOptions {
         InitializationOptions {
             omit_init_build: false,
             cmd_run: false,
+            cmd_show_references: false,
         }
     }
 }
 
+/// The `initialize` request. Its params keep the JSON of the client
+/// capabilities, as languageserver-types doesn't support all of them yet.
+#[derive(Debug)]
+pub enum InitializeRequest { }
+
+impl LSPRequest for InitializeRequest {
+    type Params = InitializeRequestParams;
+    type Result = InitializeResult;
+    const METHOD: &'static str = "initialize";
+}
+
+#[derive(Debug)]
+pub struct InitializeRequestParams {
+    pub params: InitializeParams,
+    /// The `capabilities` as sent by the client, including those which
+    /// `params.capabilities` has no fields for.
+    pub capabilities: serde_json::Value,
+}
+
+impl From<InitializeParams> for InitializeRequestParams {
+    fn from(params: InitializeParams) -> InitializeRequestParams {
+        let capabilities = serde_json::to_value(&params.capabilities)
+            .unwrap_or(serde_json::Value::Null);
+        InitializeRequestParams { params, capabilities }
+    }
+}
+
+impl ::serde::Serialize for InitializeRequestParams {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let mut value = serde_json::to_value(&self.params).map_err(S::Error::custom)?;
+        value["capabilities"] = self.capabilities.clone();
+        ::serde::Serialize::serialize(&value, serializer)
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for InitializeRequestParams {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let value = <serde_json::Value as ::serde::Deserialize>::deserialize(deserializer)?;
+        let params = <InitializeParams as ::serde::Deserialize>::deserialize(&value)
+            .map_err(D::Error::custom)?;
+        let capabilities = value
+            .get("capabilities")
+            .cloned()
+            .unwrap_or(serde_json::Value::Null);
+        Ok(InitializeRequestParams { params, capabilities })
+    }
+}
+
 // Subset of flags from ls_types::ClientCapabilities that affects this RLS.
 // Passed in the `initialize` request under `capabilities`.
 #[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy, Default)]
@@ -281,16 +299,30 @@ impl Default for InitializationOptions {
 pub struct ClientCapabilities {
     pub code_completion_has_snippet_support: bool,
     pub related_information_support: bool,
+    pub hierarchical_document_symbol_support: bool,
+    pub rename_file_support: bool,
+    pub rename_dynamic_registration: bool,
+    pub implementation_dynamic_registration: bool,
+    pub type_definition_dynamic_registration: bool,
+    pub folding_range_dynamic_registration: bool,
+    pub selection_range_dynamic_registration: bool,
+    pub semantic_tokens_dynamic_registration: bool,
+    pub inlay_hint_dynamic_registration: bool,
+    pub call_hierarchy_dynamic_registration: bool,
+    pub type_hierarchy_dynamic_registration: bool,
+    pub will_rename_files_dynamic_registration: bool,
+    pub code_action_literal_support: bool,
 }
 
 impl ClientCapabilities {
-    pub fn new(params: &ls_types::InitializeParams) -> ClientCapabilities {
+    pub fn new(params: &InitializeRequestParams) -> ClientCapabilities {
         // ls_types::ClientCapabilities is a rather awkward object to use internally
         // (for instance it doesn't Clone). Instead we pick out the bits of it that we
         // are going to handle into ClientCapabilities. The upside of
         // using this very simple struct is that it can be kept thread safe
         // without mutex locking it on every request.
         let code_completion_has_snippet_support = params
+        .params
         .capabilities
         .text_document
         .as_ref()
@@ -301,6 +333,7 @@ impl ClientCapabilities {
         .to_owned();
 
         let related_information_support = params
+        .params
         .capabilities
         .text_document
         .as_ref()
@@ -309,9 +342,68 @@ impl ClientCapabilities {
         .unwrap_or(&false)
         .to_owned();
 
+        // languageserver-types doesn't support these yet, so they're read from the
+        // JSON.
+        let hierarchical_document_symbol_support = params
+        .capabilities
+        .pointer("/textDocument/documentSymbol/hierarchicalDocumentSymbolSupport")
+        .and_then(|support| support.as_bool())
+        .unwrap_or(false);
+
+        let rename_file_support = params
+        .capabilities
+        .pointer("/workspace/workspaceEdit/resourceOperations")
+        .and_then(|ops| ops.as_array())
+        .map(|ops| ops.iter().any(|op| op.as_str() == Some("rename")))
+        .unwrap_or(false);
+
+        let dynamic_registration = |request: &str| {
+            params
+            .capabilities
+            .pointer(&format!("/textDocument/{}/dynamicRegistration", request))
+            .and_then(|support| support.as_bool())
+            .unwrap_or(false)
+        };
+        let rename_dynamic_registration = dynamic_registration("rename");
+        let implementation_dynamic_registration = dynamic_registration("implementation");
+        let type_definition_dynamic_registration = dynamic_registration("typeDefinition");
+        let folding_range_dynamic_registration = dynamic_registration("foldingRange");
+        let selection_range_dynamic_registration = dynamic_registration("selectionRange");
+        let semantic_tokens_dynamic_registration = dynamic_registration("semanticTokens");
+        let inlay_hint_dynamic_registration = dynamic_registration("inlayHint");
+        let call_hierarchy_dynamic_registration = dynamic_registration("callHierarchy");
+        let type_hierarchy_dynamic_registration = dynamic_registration("typeHierarchy");
+        let file_operations = |flag: &str| {
+            params
+            .capabilities
+            .pointer(&format!("/workspace/fileOperations/{}", flag))
+            .and_then(|support| support.as_bool())
+            .unwrap_or(false)
+        };
+        let will_rename_files_dynamic_registration =
+            file_operations("dynamicRegistration") && file_operations("willRename");
+
+        let code_action_literal_support = params
+        .capabilities
+        .pointer("/textDocument/codeAction/codeActionLiteralSupport")
+        .map_or(false, |support| support.is_object());
+
         ClientCapabilities {
             code_completion_has_snippet_support,
             related_information_support,
+            hierarchical_document_symbol_support,
+            rename_file_support,
+            rename_dynamic_registration,
+            implementation_dynamic_registration,
+            type_definition_dynamic_registration,
+            folding_range_dynamic_registration,
+            selection_range_dynamic_registration,
+            semantic_tokens_dynamic_registration,
+            inlay_hint_dynamic_registration,
+            call_hierarchy_dynamic_registration,
+            type_hierarchy_dynamic_registration,
+            will_rename_files_dynamic_registration,
+            code_action_literal_support,
         }
     }
 }
@@ -350,6 +442,54 @@ impl LSPNotification for BeginBuild {
     const METHOD: &'static str = "rustDocument/beginBuild";
 }
 
+/// Custom LSP notification sent to the client for each test run by an
+/// `rls.runTests` command, as its result becomes known.
+#[derive(Debug)]
+pub enum TestResult { }
+
+impl LSPNotification for TestResult {
+    type Params = TestResultParams;
+    const METHOD: &'static str = "rustDocument/testResult";
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TestResultParams {
+    /// The path of the test, as reported by libtest, e.g., `tests::foo`.
+    pub name: String,
+    pub status: TestStatus,
+    /// The location of the test's name, if it is in the file tests were run for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+/// Custom LSP notification sent to the client when an `rls.runTests` command
+/// has finished.
+#[derive(Debug)]
+pub enum TestsFinished { }
+
+impl LSPNotification for TestsFinished {
+    type Params = TestsFinishedParams;
+    const METHOD: &'static str = "rustDocument/testsFinished";
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestsFinishedParams {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    /// Set if the tests could not be built or run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 /* ------------------ Custom JSON-RPC requests ---------------- */
 
 /// Find all the implementations of a given trait.
@@ -405,3 +545,588 @@ pub struct ProgressParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub done: Option<bool>,
 }
+
+/* -------- Temporary LSP types until languageserver-types supports LSP 3.6 ------- */
+
+/// The `textDocument/implementation` request, finds the implementations of the
+/// trait or type at a position.
+#[derive(Debug)]
+pub enum GotoImplementation { }
+
+impl LSPRequest for GotoImplementation {
+    type Params = TextDocumentPositionParams;
+    type Result = Vec<Location>;
+    const METHOD: &'static str = "textDocument/implementation";
+}
+
+/// The `textDocument/typeDefinition` request, finds the definition of the type
+/// of the symbol at a position.
+#[derive(Debug)]
+pub enum GotoTypeDefinition { }
+
+impl LSPRequest for GotoTypeDefinition {
+    type Params = TextDocumentPositionParams;
+    type Result = Vec<Location>;
+    const METHOD: &'static str = "textDocument/typeDefinition";
+}
+
+/// Represents programming constructs like variables, classes, interfaces etc.
+/// that appear in a document. Document symbols can be hierarchical.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    /// The name of this symbol.
+    pub name: String,
+    /// More detail for this symbol, e.g the signature of a function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The kind of this symbol.
+    pub kind: SymbolKind,
+    /// Indicates if this symbol is deprecated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<bool>,
+    /// The range enclosing this symbol, including its attributes and doc comments.
+    pub range: Range,
+    /// The range that should be selected and revealed when this symbol is being
+    /// picked, e.g the name of a function.
+    pub selection_range: Range,
+    /// Children of this symbol, e.g. the methods of an impl.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<DocumentSymbol>>,
+}
+
+/// The response to a `textDocument/documentSymbol` request.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum DocumentSymbolResponse {
+    Flat(Vec<SymbolInformation>),
+    Nested(Vec<DocumentSymbol>),
+}
+
+/// A change which can be made to the code, e.g., a fix or a refactoring, which
+/// the client can offer depending on its kind.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeAction {
+    /// A short description of the change.
+    pub title: String,
+    /// The kind of the change, one of `code_action_kind`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The edit to make.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit: Option<WorkspaceEdit>,
+    /// The command to run, after the edit is made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+}
+
+/// The kinds of `CodeAction`s the RLS provides.
+pub mod code_action_kind {
+    pub const QUICKFIX: &str = "quickfix";
+    pub const REFACTOR_EXTRACT: &str = "refactor.extract";
+    pub const REFACTOR_INLINE: &str = "refactor.inline";
+    pub const REFACTOR_REWRITE: &str = "refactor.rewrite";
+    pub const SOURCE_ORGANIZE_IMPORTS: &str = "source.organizeImports";
+}
+
+/// The response to a `textDocument/codeAction` request. Clients which don't
+/// support `CodeAction` literals are sent their commands.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum CodeActionResponse {
+    Commands(Vec<Command>),
+    Actions(Vec<CodeAction>),
+}
+
+/// The `textDocument/foldingRange` request, returns the ranges which can be
+/// folded in a document.
+#[derive(Debug)]
+pub enum FoldingRangeRequest { }
+
+impl LSPRequest for FoldingRangeRequest {
+    type Params = FoldingRangeParams;
+    type Result = Vec<FoldingRange>;
+    const METHOD: &'static str = "textDocument/foldingRange";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldingRangeParams {
+    /// The text document.
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Represents a folding range.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldingRange {
+    /// The zero-based line number from where the folded range starts.
+    pub start_line: u64,
+    /// The zero-based character offset from where the folded range starts. If
+    /// not defined, defaults to the length of the start line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_character: Option<u64>,
+    /// The zero-based line number where the folded range ends.
+    pub end_line: u64,
+    /// The zero-based character offset before the folded range ends. If not
+    /// defined, defaults to the length of the end line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_character: Option<u64>,
+    /// Describes the kind of the folding range. Used to categorize folding
+    /// ranges, e.g., for a 'Fold all comments' command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<FoldingRangeKind>,
+}
+
+/// The kind of a `FoldingRange`.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FoldingRangeKind {
+    Comment,
+    Imports,
+    Region,
+}
+
+/// The `textDocument/selectionRange` request, returns the ranges which can be
+/// selected around positions in a document, for expanding and shrinking the
+/// selection.
+#[derive(Debug)]
+pub enum SelectionRangeRequest { }
+
+impl LSPRequest for SelectionRangeRequest {
+    type Params = SelectionRangeParams;
+    type Result = Vec<SelectionRange>;
+    const METHOD: &'static str = "textDocument/selectionRange";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRangeParams {
+    /// The text document.
+    pub text_document: TextDocumentIdentifier,
+    /// The positions inside the text document.
+    pub positions: Vec<Position>,
+}
+
+/// A range to select around a position, and the larger ranges containing it.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRange {
+    /// The range of this selection range.
+    pub range: Range,
+    /// The parent selection range containing this range, so that
+    /// `parent.range` contains `self.range`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<SelectionRange>>,
+}
+
+/// The `textDocument/semanticTokens/full` request, classifies the identifiers
+/// in a document.
+#[derive(Debug)]
+pub enum SemanticTokensFullRequest { }
+
+impl LSPRequest for SemanticTokensFullRequest {
+    type Params = SemanticTokensParams;
+    type Result = SemanticTokens;
+    const METHOD: &'static str = "textDocument/semanticTokens/full";
+}
+
+/// The `textDocument/semanticTokens/full/delta` request, returns the changes
+/// to the semantic tokens of a document since a previous result.
+#[derive(Debug)]
+pub enum SemanticTokensFullDeltaRequest { }
+
+impl LSPRequest for SemanticTokensFullDeltaRequest {
+    type Params = SemanticTokensDeltaParams;
+    type Result = SemanticTokensFullDeltaResult;
+    const METHOD: &'static str = "textDocument/semanticTokens/full/delta";
+}
+
+/// The method used to dynamically register for all semantic tokens requests.
+pub const SEMANTIC_TOKENS_METHOD: &str = "textDocument/semanticTokens";
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensParams {
+    /// The text document.
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDeltaParams {
+    /// The text document.
+    pub text_document: TextDocumentIdentifier,
+    /// The result id of a previous response, which the delta is relative to.
+    pub previous_result_id: String,
+}
+
+/// The token types and modifiers which a server uses, the indices of which
+/// are used to encode semantic tokens.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensLegend {
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensFullOptions {
+    /// Whether the server supports `textDocument/semanticTokens/full/delta`.
+    pub delta: bool,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensRegistrationOptions {
+    /// The documents to register for, or null for those of the client's
+    /// document selector.
+    pub document_selector: Option<DocumentSelector>,
+    pub legend: SemanticTokensLegend,
+    /// Whether the server supports `textDocument/semanticTokens/range`.
+    pub range: bool,
+    pub full: SemanticTokensFullOptions,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    /// An id for this result, for use in a later delta request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    /// The tokens, each encoded as five integers: the line relative to the
+    /// previous token, the start character (relative to the previous token if
+    /// on the same line), the length, the token type and the modifier bit set.
+    pub data: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    /// Edits to the `data` of the previous result.
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensEdit {
+    /// The index in the previous `data` to start the edit at.
+    pub start: u32,
+    /// The number of integers to delete.
+    pub delete_count: u32,
+    /// The integers to insert.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<u32>>,
+}
+
+/// The response to a `textDocument/semanticTokens/full/delta` request, which
+/// has all the tokens if the previous result is unknown.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum SemanticTokensFullDeltaResult {
+    Tokens(SemanticTokens),
+    TokensDelta(SemanticTokensDelta),
+}
+
+/// The `textDocument/inlayHint` request, returns the hints to show inline in a
+/// range of a document.
+#[derive(Debug)]
+pub enum InlayHintRequest { }
+
+impl LSPRequest for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Vec<InlayHint>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    /// The text document.
+    pub text_document: TextDocumentIdentifier,
+    /// The visible range for which to compute hints.
+    pub range: Range,
+}
+
+/// A hint shown inline in the source, such as the inferred type of a binding.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    /// The position the hint is shown at.
+    pub position: Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<InlayHintKind>,
+    /// Whether to add padding before the hint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+    /// Whether to add padding after the hint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_right: Option<bool>,
+}
+
+/// The kind of an `InlayHint`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InlayHintKind {
+    Type = 1,
+    Parameter = 2,
+}
+
+impl ::serde::Serialize for InlayHintKind {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for InlayHintKind {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        match <u8 as ::serde::Deserialize>::deserialize(deserializer)? {
+            1 => Ok(InlayHintKind::Type),
+            2 => Ok(InlayHintKind::Parameter),
+            n => Err(D::Error::custom(format!("unknown inlay hint kind: {}", n))),
+        }
+    }
+}
+
+/// The `textDocument/prepareCallHierarchy` request, returns the function or
+/// method at a position as the root of a call hierarchy.
+#[derive(Debug)]
+pub enum CallHierarchyPrepare { }
+
+impl LSPRequest for CallHierarchyPrepare {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<CallHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareCallHierarchy";
+}
+
+/// The `callHierarchy/incomingCalls` request, finds the callers of an item.
+#[derive(Debug)]
+pub enum CallHierarchyIncomingCalls { }
+
+impl LSPRequest for CallHierarchyIncomingCalls {
+    type Params = CallHierarchyIncomingCallsParams;
+    type Result = Option<Vec<CallHierarchyIncomingCall>>;
+    const METHOD: &'static str = "callHierarchy/incomingCalls";
+}
+
+/// The `callHierarchy/outgoingCalls` request, finds the functions and methods
+/// an item calls.
+#[derive(Debug)]
+pub enum CallHierarchyOutgoingCalls { }
+
+impl LSPRequest for CallHierarchyOutgoingCalls {
+    type Params = CallHierarchyOutgoingCallsParams;
+    type Result = Option<Vec<CallHierarchyOutgoingCall>>;
+    const METHOD: &'static str = "callHierarchy/outgoingCalls";
+}
+
+/// A function or method in a call hierarchy.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// More detail, e.g., the signature of a function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: Url,
+    /// The range of the whole item, including its body.
+    pub range: Range,
+    /// The range of the item's name.
+    pub selection_range: Range,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+/// A caller of an item.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    /// The ranges of the calls, within `from`.
+    pub from_ranges: Vec<Range>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+/// An item called by another.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    /// The ranges of the calls, within the caller.
+    pub from_ranges: Vec<Range>,
+}
+
+/// The `textDocument/prepareTypeHierarchy` request, returns the trait or type
+/// at a position as the root of a type hierarchy.
+#[derive(Debug)]
+pub enum TypeHierarchyPrepare { }
+
+impl LSPRequest for TypeHierarchyPrepare {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+}
+
+/// The `typeHierarchy/supertypes` request, finds the supertraits of a trait or
+/// the traits implemented by a type.
+#[derive(Debug)]
+pub enum TypeHierarchySupertypes { }
+
+impl LSPRequest for TypeHierarchySupertypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+}
+
+/// The `typeHierarchy/subtypes` request, finds the types implementing a trait.
+#[derive(Debug)]
+pub enum TypeHierarchySubtypes { }
+
+impl LSPRequest for TypeHierarchySubtypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+}
+
+/// A trait or type in a type hierarchy, which has the same fields as an item in
+/// a call hierarchy.
+pub type TypeHierarchyItem = CallHierarchyItem;
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+}
+
+/// A workspace edit which may contain resource operations, such as renaming a
+/// file, as well as text edits.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceWorkspaceEdit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<HashMap<Url, Vec<TextEdit>>>,
+    /// Applied in order, so text edits to a file must come before it is renamed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_changes: Option<Vec<DocumentChangeOperation>>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum DocumentChangeOperation {
+    Op(ResourceOp),
+    Edit(TextDocumentEdit),
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ResourceOp {
+    Rename(RenameFile),
+}
+
+/// Moves a file or directory.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameFile {
+    pub old_uri: Url,
+    pub new_uri: Url,
+}
+
+/// The `textDocument/prepareRename` request, checks that the definition at a
+/// position can be renamed and finds the range of the name to rename.
+#[derive(Debug)]
+pub enum PrepareRenameRequest { }
+
+impl LSPRequest for PrepareRenameRequest {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<PrepareRenameResponse>;
+    const METHOD: &'static str = "textDocument/prepareRename";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PrepareRenameResponse {
+    pub range: Range,
+    /// The current name, to show in the client as the initial new name.
+    pub placeholder: String,
+}
+
+/// The registration options for `textDocument/rename`.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameOptions {
+    /// Whether the server handles `textDocument/prepareRename`.
+    pub prepare_provider: bool,
+}
+
+/// The `workspace/willRenameFiles` request, sent before files are renamed in
+/// the client, returns the edits to make before they are.
+#[derive(Debug)]
+pub enum WillRenameFiles { }
+
+impl LSPRequest for WillRenameFiles {
+    type Params = RenameFilesParams;
+    type Result = Option<WorkspaceEdit>;
+    const METHOD: &'static str = "workspace/willRenameFiles";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameFilesParams {
+    pub files: Vec<FileRename>,
+}
+
+/// A file or directory renamed from `old_uri` to `new_uri`.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRename {
+    pub old_uri: Url,
+    pub new_uri: Url,
+}
+
+/// The registration options for file operations such as
+/// `workspace/willRenameFiles`, restricting the files they apply to.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationRegistrationOptions {
+    pub filters: Vec<FileOperationFilter>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    pub pattern: FileOperationPattern,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperationPattern {
+    pub glob: String,
+    /// Whether the pattern matches files or folders, or both if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<FileOperationPatternKind>,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FileOperationPatternKind {
+    File,
+    Folder,
+}
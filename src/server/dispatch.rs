@@ -94,16 +94,33 @@ define_dispatch_request_enum!(
     References,
     WorkspaceSymbol,
     Symbols,
+    FoldingRanges,
+    SelectionRanges,
+    SemanticTokensFull,
+    SemanticTokensFullDelta,
+    InlayHints,
+    PrepareCallHierarchy,
+    IncomingCalls,
+    OutgoingCalls,
+    PrepareTypeHierarchy,
+    Supertypes,
+    Subtypes,
     Hover,
     FindImpls,
+    Implementation,
+    TypeDefinition,
     DocumentHighlight,
+    PrepareRename,
     Rename,
+    WillRenameFiles,
     CodeAction,
     ResolveCompletion,
     Formatting,
     RangeFormatting,
     ExecuteCommand,
     CodeLensRequest,
+    ResolveCodeLens,
+    SignatureHelp,
 );
 
 /// Provides ability to dispatch requests to a worker thread that will
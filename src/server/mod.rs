@@ -17,13 +17,14 @@ use analysis::AnalysisHost;
 use config::Config;
 use jsonrpc_core::{self as jsonrpc, Id, types::error::ErrorCode};
 pub use ls_types::notification::Exit as ExitNotification;
-pub use ls_types::request::Initialize as InitializeRequest;
 pub use ls_types::request::Shutdown as ShutdownRequest;
 use ls_types::{
     CompletionOptions, ExecuteCommandOptions, InitializeParams, InitializeResult,
-    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, CodeLensOptions
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, CodeLensOptions,
+    SignatureHelpOptions,
 };
 use lsp_data;
+pub use lsp_data::InitializeRequest;
 use lsp_data::{InitializationOptions, LSPNotification, LSPRequest};
 use serde_json;
 use server::dispatch::Dispatcher;
@@ -95,6 +96,7 @@ impl BlockingRequestAction for InitializeRequest {
         out: O,
     ) -> Result<NoResponse, ResponseError> {
         let init_options: InitializationOptions = params
+            .params
             .initialization_options
             .as_ref()
             .and_then(|options| serde_json::from_value(options.to_owned()).ok())
@@ -110,16 +112,16 @@ impl BlockingRequestAction for InitializeRequest {
             ));
         }
 
+        let capabilities = lsp_data::ClientCapabilities::new(&params);
         let result = InitializeResult {
-            capabilities: server_caps(ctx),
+            capabilities: server_caps(ctx, &capabilities),
         };
 
         // send response early before `ctx.init` to enforce
         // initialize-response-before-all-other-messages constraint
         result.send(id, &out);
 
-        let capabilities = lsp_data::ClientCapabilities::new(&params);
-        ctx.init(get_root_path(&params), &init_options, capabilities, &out).unwrap();
+        ctx.init(get_root_path(&params.params), &init_options, capabilities, &out).unwrap();
 
         Ok(NoResponse)
     }
@@ -267,17 +269,34 @@ impl<O: Output> LsService<O> {
                 requests::Formatting,
                 requests::RangeFormatting,
                 requests::ResolveCompletion,
+                requests::PrepareRename,
                 requests::Rename,
+                requests::WillRenameFiles,
                 requests::CodeAction,
                 requests::DocumentHighlight,
                 requests::FindImpls,
+                requests::Implementation,
+                requests::TypeDefinition,
                 requests::Symbols,
+                requests::FoldingRanges,
+                requests::SelectionRanges,
+                requests::SemanticTokensFull,
+                requests::SemanticTokensFullDelta,
+                requests::InlayHints,
+                requests::PrepareCallHierarchy,
+                requests::IncomingCalls,
+                requests::OutgoingCalls,
+                requests::PrepareTypeHierarchy,
+                requests::Supertypes,
+                requests::Subtypes,
                 requests::Hover,
                 requests::WorkspaceSymbol,
                 requests::Definition,
                 requests::References,
                 requests::Completion,
-                requests::CodeLensRequest;
+                requests::CodeLensRequest,
+                requests::ResolveCodeLens,
+                requests::SignatureHelp;
         );
         Ok(())
     }
@@ -349,7 +368,10 @@ pub enum ServerStateChange {
     },
 }
 
-fn server_caps(ctx: &ActionContext) -> ServerCapabilities {
+fn server_caps(
+    ctx: &ActionContext,
+    client_capabilities: &lsp_data::ClientCapabilities,
+) -> ServerCapabilities {
     ServerCapabilities {
         text_document_sync: Some(TextDocumentSyncCapability::Kind(
             TextDocumentSyncKind::Incremental,
@@ -372,20 +394,33 @@ fn server_caps(ctx: &ActionContext) -> ServerCapabilities {
             // commands.
             commands: vec![
                 format!("rls.applySuggestion-{}", ctx.pid()),
+                format!("rls.fixAll-{}", ctx.pid()),
                 format!("rls.deglobImports-{}", ctx.pid()),
+                format!("rls.deglobAll-{}", ctx.pid()),
+                format!("rls.extract-{}", ctx.pid()),
+                format!("rls.inline-{}", ctx.pid()),
+                format!("rls.runTests-{}", ctx.pid()),
             ],
         }),
-        rename_provider: Some(true),
+        // Registered dynamically instead, with the `prepareProvider` option
+        // which languageserver-types doesn't support yet, if the client lets us.
+        rename_provider: if client_capabilities.rename_dynamic_registration {
+            None
+        } else {
+            Some(true)
+        },
         // These are supported if the `unstable_features` option is set.
         // We'll update these capabilities dynamically when we get config
         // info from the client.
         document_range_formatting_provider: Some(false),
 
         code_lens_provider: Some(CodeLensOptions {
-            resolve_provider: Some(false),
+            resolve_provider: Some(true),
         }),
         document_on_type_formatting_provider: None,
-        signature_help_provider: None,
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+        }),
     }
 }
 
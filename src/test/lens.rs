@@ -31,6 +31,7 @@ fn test_lens_run() {
         initialize_with_opts(0, root_path, Some(InitializationOptions {
             omit_init_build: false,
             cmd_run: true,
+            cmd_show_references: true,
         })).to_string(),
         request::<requests::CodeLensRequest>(
             100,
@@ -49,7 +50,7 @@ fn test_lens_run() {
     expect_messages(
         results.clone(),
         &[
-            ExpectedMessage::new(Some(0)).expect_contains(r#""codeLensProvider":{"resolveProvider":false}"#),
+            ExpectedMessage::new(Some(0)).expect_contains(r#""codeLensProvider":{"resolveProvider":true}"#),
             ExpectedMessage::new(None).expect_contains("progress"),
             ExpectedMessage::new(None).expect_contains("progress"),
             ExpectedMessage::new(None).expect_contains("progress"),
@@ -65,16 +66,47 @@ fn test_lens_run() {
     );
     wait_for_n_results!(1, results);
     let result: serde_json::Value = serde_json::from_str(&results.lock().unwrap().remove(0)).unwrap();
+    let lenses = result.get("result").unwrap().as_array().unwrap();
+
+    // Reference count lenses are resolved later.
+    let (run_lenses, unresolved): (Vec<_>, Vec<_>) =
+        lenses.iter().cloned().partition(|lens| lens.get("command").is_some());
+    let unresolved: Vec<_> = unresolved
+        .iter()
+        .map(|lens| {
+            let line = lens["range"]["start"]["line"].as_u64().unwrap();
+            let uri = lens["data"]["references"]["textDocument"]["uri"].as_str().unwrap();
+            (line, uri)
+        })
+        .collect();
+    assert_eq!(unresolved, vec![(10, url.as_str()), (14, url.as_str())]);
+
     compare_json(
-        result.get("result").unwrap(),
+        &serde_json::Value::Array(run_lenses),
         r#"[{
+            "command": {
+              "command": "rls.run",
+              "title": "Run",
+              "arguments": [{
+                  "args": [ "run", "-p", "run", "--bin", "run" ],
+                  "binary": "cargo",
+                  "env": { "RUST_BACKTRACE": "short" },
+                  "target": { "package": "run", "kind": "bin", "name": "run" }
+              }]
+            },
+            "range": {
+              "start": { "character": 7, "line": 10 },
+              "end": { "character": 11, "line": 10 }
+            }
+        }, {
             "command": {
               "command": "rls.run",
               "title": "Run test",
               "arguments": [{
-                  "args": [ "test", "--", "--nocapture", "test_foo" ],
+                  "args": [ "test", "-p", "run", "--bin", "run", "--", "--nocapture", "--exact", "test_foo" ],
                   "binary": "cargo",
-                  "env": { "RUST_BACKTRACE": "short" }
+                  "env": { "RUST_BACKTRACE": "short" },
+                  "target": { "package": "run", "kind": "bin", "name": "run" }
               }]
             },
             "range": {
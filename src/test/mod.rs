@@ -65,7 +65,7 @@ pub fn initialize_with_opts(
     };
     Request {
         id: RequestId::Num(id as u64),
-        params,
+        params: params.into(),
         received: Instant::now(),
         _action: PhantomData,
     }
@@ -362,6 +362,86 @@ fn test_hover_after_src_line_change() {
     );
 }
 
+#[test]
+fn test_signature_help() {
+    let mut env = Environment::new("signature_help");
+
+    let source_file_path = Path::new("src").join("main.rs");
+
+    let root_path = env.cache.abs_path(Path::new("."));
+    let url = Url::from_file_path(env.cache.abs_path(&source_file_path))
+        .expect("couldn't convert file path to URL");
+
+    let messages = vec![
+        initialize(0, root_path.as_os_str().to_str().map(|x| x.to_owned())).to_string(),
+        request::<requests::SignatureHelp>(
+            11,
+            TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier::new(url.clone()),
+                position: env.cache
+                    .mk_ls_position(src(&source_file_path, 26, "foo.bar")),
+            },
+        ).to_string(),
+        request::<requests::SignatureHelp>(
+            12,
+            TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier::new(url),
+                position: env.cache
+                    .mk_ls_position(src(&source_file_path, 26, "2)")),
+            },
+        ).to_string(),
+    ];
+
+    let (mut server, results) = env.mock_server(messages);
+    // Initialize and build.
+    assert_eq!(
+        ls_server::LsService::handle_message(&mut server),
+        ls_server::ServerStateChange::Continue
+    );
+    expect_messages(
+        results.clone(),
+        &[
+            ExpectedMessage::new(Some(0))
+                .expect_contains(r#""signatureHelpProvider":{"triggerCharacters":["(",","]}"#),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains(r#"title":"Building""#),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains("signature_help"),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains("signature_help"),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains(r#""done":true"#),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains(r#"title":"Indexing""#),
+            ExpectedMessage::new(None).expect_contains("progress").expect_contains(r#""done":true"#),
+        ],
+    );
+
+    assert_eq!(
+        ls_server::LsService::handle_message(&mut server),
+        ls_server::ServerStateChange::Continue
+    );
+    expect_messages(
+        results.clone(),
+        &[
+            ExpectedMessage::new(Some(11))
+                .expect_contains(r#""activeParameter":1"#)
+                .expect_contains(r#"{"label":"first: u32""#)
+                .expect_contains(r#"{"label":"second: u32""#)
+                .expect_contains("Adds two numbers."),
+        ],
+    );
+
+    assert_eq!(
+        ls_server::LsService::handle_message(&mut server),
+        ls_server::ServerStateChange::Continue
+    );
+    // `self` is not an explicit argument of a method call.
+    expect_messages(
+        results.clone(),
+        &[
+            ExpectedMessage::new(Some(12))
+                .expect_contains(r#""activeParameter":0"#)
+                .expect_contains(r#""parameters":[{"label":"x: u32""#),
+        ],
+    );
+}
+
 #[test]
 fn test_workspace_symbol() {
     let mut env = Environment::new("workspace_symbol");
@@ -743,8 +823,8 @@ fn test_reformat() {
         ls_server::LsService::handle_message(&mut server),
         ls_server::ServerStateChange::Continue
     );
-    expect_messages(results.clone(), &[ExpectedMessage::new(Some(42)).expect_contains(r#"{"start":{"line":0,"character":0},"end":{"line":12,"character":0}}"#)
-                                            .expect_contains(r#"newText":"// Copyright 2017 The Rust Project Developers. See the COPYRIGHT\n// file at the top-level directory of this distribution and at\n// http://rust-lang.org/COPYRIGHT.\n//\n// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or\n// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license\n// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your\n// option. This file may not be copied, modified, or distributed\n// except according to those terms.\n\npub mod foo;\npub fn main() {\n    let world = \"world\";\n    println!(\"Hello, {}!\", world);\n}"#)]);
+    expect_messages(results.clone(), &[ExpectedMessage::new(Some(42)).expect_contains(r#"{"start":{"line":10,"character":0},"end":{"line":12,"character":0}}"#)
+                                            .expect_contains(r#"newText":"pub mod foo;\npub fn main() {\n    let world = \"world\";\n    println!(\"Hello, {}!\", world);\n}"#)]);
 }
 
 #[test]
@@ -805,8 +885,8 @@ fn test_reformat_with_range() {
         ls_server::LsService::handle_message(&mut server),
         ls_server::ServerStateChange::Continue
     );
-    expect_messages(results.clone(), &[ExpectedMessage::new(Some(42)).expect_contains(r#"{"start":{"line":0,"character":0},"end":{"line":15,"character":5}}"#)
-                                            .expect_contains(r#"newText":"// Copyright 2017 The Rust Project Developers. See the COPYRIGHT\n// file at the top-level directory of this distribution and at\n// http://rust-lang.org/COPYRIGHT.\n//\n// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or\n// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license\n// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your\n// option. This file may not be copied, modified, or distributed\n// except according to those terms.\n\npub fn main() {\n    let world1 = \"world\";\n    println!(\"Hello, {}!\", world1);\n    let world2 = \"world\";\n    println!(\"Hello, {}!\", world2);\n    let world3 = \"world\";\n    println!(\"Hello, {}!\", world3);\n}\n"#)]);
+    expect_messages(results.clone(), &[ExpectedMessage::new(Some(42)).expect_contains(r#"{"start":{"line":10,"character":0},"end":{"line":15,"character":5}}"#)
+                                            .expect_contains(r#"newText":"pub fn main() {\n    let world1 = \"world\";\n    println!(\"Hello, {}!\", world1);\n    let world2 = \"world\";\n    println!(\"Hello, {}!\", world2);\n    let world3 = \"world\";\n    println!(\"Hello, {}!\", world3);\n}\n"#)]);
 }
 
 #[test]
@@ -1076,6 +1156,7 @@ fn test_omit_init_build() {
     let init_options = Some(InitializationOptions {
         omit_init_build: true,
         cmd_run: true,
+        cmd_show_references: false,
     });
     let initialize = initialize_with_opts(0, root_path, init_options);
 
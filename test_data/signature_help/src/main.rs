@@ -0,0 +1,27 @@
+// Copyright 2018 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Adds two numbers.
+fn add(first: u32, second: u32) -> u32 {
+    first + second
+}
+
+struct Foo;
+
+impl Foo {
+    fn bar(&self, x: u32) -> u32 {
+        x
+    }
+}
+
+pub fn main() {
+    let foo = Foo;
+    add(1, foo.bar(2));
+}